@@ -126,13 +126,33 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
                     let ty = &*pat_type.ty;
                     let ident_str = ident.to_string();
                     Some(quote! {
-                        let #ident: #ty = serde_json::from_value(
-                            inp.as_object()
-                                .unwrap()
-                                .get(#ident_str)
-                                .unwrap()
-                                .clone()
-                        ).unwrap_or_default();  // use default value if model doesn't send arg
+                        let #ident: #ty = {
+                            let value = inp
+                                .as_object()
+                                .and_then(|args| args.get(#ident_str))
+                                .cloned()
+                                .unwrap_or(serde_json::Value::Null);
+                            // models sometimes send scalars with the wrong type,
+                            // e.g. "5" for 5, which are coerced before giving up
+                            serde_json::from_value(value.clone())
+                                .ok()
+                                .or_else(|| {
+                                    let coerced = match value {
+                                        serde_json::Value::String(s) => {
+                                            serde_json::from_str::<serde_json::Value>(s.trim()).ok()
+                                        }
+                                        serde_json::Value::Number(n) => {
+                                            Some(serde_json::Value::String(n.to_string()))
+                                        }
+                                        serde_json::Value::Bool(b) => {
+                                            Some(serde_json::Value::String(b.to_string()))
+                                        }
+                                        _ => None,
+                                    };
+                                    coerced.and_then(|v| serde_json::from_value(v).ok())
+                                })
+                                .unwrap_or_default() // use default value if model doesn't send arg
+                        };
                     })
                 } else {
                     None
@@ -171,14 +191,17 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 #(#struct_fields),*
             }
 
-            let input_schema = schema_for!(Function);
+            // sorted keys serialize the same whatever the serde_json features
+            let mut input_schema = schema_for!(Function).to_value();
+            input_schema.sort_all_objects();
             // End
 
             let mut tool = Tool::new();
 
             tool.name = #name.to_string();
             tool.description = #description.to_string();
-            tool.input_schema = input_schema;
+            tool.input_schema = Schema::try_from(input_schema)
+                .expect("generated schemas are json objects");
             tool.execute = ToolExecute::new(Box::new(|inp| -> std::result::Result<String, String> {
                 #(#binding_tokens)*
                 #block
@@ -0,0 +1,322 @@
+//! An in-memory cache of language model responses.
+//!
+//! Meant for development, where the same deterministic prompts are sent over
+//! and over again. A cache is opt-in per request:
+//!
+//! ```
+//! use aisdk::core::cache::ResponseCache;
+//!
+//! let cache = ResponseCache::new();
+//! // share `cache.clone()` between requests with `.cache(cache.clone())`
+//! assert!(cache.is_empty());
+//! ```
+
+use crate::core::language_model::{LanguageModel, LanguageModelOptions, LanguageModelResponse};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// Caches responses of `generate_text` keyed on the model and the options
+/// sent to it. Clones share the same entries.
+///
+/// Only requests with a temperature of 0 are cached, as other responses are
+/// not expected to be reproducible, unless `force` is set.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseCache {
+    entries: Arc<Mutex<HashMap<u64, LanguageModelResponse>>>,
+    force: bool,
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caches responses regardless of the temperature.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Returns the number of cached responses.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// Removes all cached responses.
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    /// Returns the key of a request, or `None` if it should not be cached.
+    pub(crate) fn key<M: LanguageModel + ?Sized>(
+        &self,
+        model: &M,
+        options: &LanguageModelOptions,
+    ) -> Option<u64> {
+        if !self.force && options.temperature != Some(0) {
+            return None;
+        }
+
+        // everything sent to the provider, hooks and local settings such as
+        // retries do not change the response. Message ids and timestamps
+        // differ on every run and are left out.
+        let messages: Vec<_> = options
+            .messages
+            .iter()
+            .map(|m| (m.step_id, &m.message))
+            .collect();
+        // tools are hashed by their serialized schema, as their debug output
+        // leaves it out.
+        let tools: Vec<_> = options
+            .tools
+            .as_ref()
+            .map(|tools| tools.describe_all())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tool| {
+                serde_json::json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.input_schema,
+                })
+                .to_string()
+            })
+            .collect();
+        let headers: BTreeMap<_, _> = options.headers.iter().collect();
+        let normalized = format!(
+            "{:?} {:?} {:?} {:?}",
+            (
+                &options.system,
+                &messages,
+                &options.schema,
+                options.schema_strict,
+                options.json_mode,
+                &tools,
+            ),
+            (
+                options.seed,
+                options.temperature,
+                options.top_p,
+                options.top_k,
+                options.max_output_tokens,
+                options.n,
+            ),
+            (
+                &options.stop_sequences,
+                options.presence_penalty,
+                options.frequency_penalty,
+                &options.reasoning_effort,
+                options.logprobs,
+                options.top_logprobs,
+                &options.user,
+                &options.previous_response_id,
+            ),
+            (&headers, options.store, &options.audio_output,)
+        );
+
+        let mut hasher = DefaultHasher::new();
+        model.provider_name().hash(&mut hasher);
+        model.model_name().hash(&mut hasher);
+        model.settings_key().hash(&mut hasher);
+        normalized.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<LanguageModelResponse> {
+        self.entries().get(&key).cloned()
+    }
+
+    pub(crate) fn insert(&self, key: u64, response: LanguageModelResponse) {
+        self.entries().insert(key, response);
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<u64, LanguageModelResponse>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::LanguageModelRequest;
+    use crate::core::language_model::AudioOutput;
+    use crate::core::tools::ToolList;
+    use crate::test_utils::{MockLanguageModel, tool, tool_call};
+
+    #[tokio::test]
+    async fn test_cache_hit_skips_provider() {
+        let model = MockLanguageModel::new().respond_text("hello");
+        let cache = ResponseCache::new();
+
+        for _ in 0..2 {
+            let result = LanguageModelRequest::builder()
+                .model(model.clone())
+                .prompt("hi")
+                .temperature(0_u32)
+                .cache(cache.clone())
+                .build()
+                .generate_text()
+                .await
+                .unwrap();
+            assert_eq!(result.text(), Some("hello".to_string()));
+        }
+
+        assert_eq!(model.calls().len(), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_on_every_step_of_tool_conversation() {
+        let model = MockLanguageModel::new()
+            .respond(Ok(tool_call("lookup", "call_1")))
+            .respond_text("It is 42.");
+        let cache = ResponseCache::new();
+
+        for _ in 0..2 {
+            let result = LanguageModelRequest::builder()
+                .model(model.clone())
+                .prompt("What is the answer?")
+                .with_tool(tool("lookup", "42"))
+                .temperature(0_u32)
+                .cache(cache.clone())
+                .build()
+                .generate_text()
+                .await
+                .unwrap();
+            assert_eq!(result.text(), Some("It is 42.".to_string()));
+        }
+
+        // the second run is answered from the cache on both steps
+        assert_eq!(model.calls().len(), 2);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_miss_on_different_prompt() {
+        let model = MockLanguageModel::new().echo();
+        let cache = ResponseCache::new();
+
+        for prompt in ["hi", "hello"] {
+            let result = LanguageModelRequest::builder()
+                .model(model.clone())
+                .prompt(prompt)
+                .temperature(0_u32)
+                .cache(cache.clone())
+                .build()
+                .generate_text()
+                .await
+                .unwrap();
+            assert_eq!(result.text(), Some(prompt.to_string()));
+        }
+
+        assert_eq!(model.calls().len(), 2);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_skips_non_zero_temperature_unless_forced() {
+        let model = MockLanguageModel::new().echo();
+
+        let cache = ResponseCache::new();
+        let request = |cache: &ResponseCache| {
+            LanguageModelRequest::builder()
+                .model(model.clone())
+                .prompt("hi")
+                .temperature(70_u32)
+                .cache(cache.clone())
+                .build()
+        };
+
+        request(&cache).generate_text().await.unwrap();
+        request(&cache).generate_text().await.unwrap();
+        assert_eq!(model.calls().len(), 2);
+        assert!(cache.is_empty());
+
+        let forced = ResponseCache::new().force(true);
+        request(&forced).generate_text().await.unwrap();
+        request(&forced).generate_text().await.unwrap();
+        assert_eq!(model.calls().len(), 3);
+        assert_eq!(forced.len(), 1);
+    }
+
+    #[test]
+    fn test_key_covers_request_settings() {
+        let cache = ResponseCache::new();
+        let model = MockLanguageModel::new();
+        let key = |options: LanguageModelOptions| {
+            cache
+                .key(
+                    &model,
+                    &LanguageModelOptions {
+                        temperature: Some(0),
+                        ..options
+                    },
+                )
+                .unwrap()
+        };
+        let with_schema = |schema| {
+            let mut tool = tool("lookup", "ok");
+            tool.input_schema = schema;
+            LanguageModelOptions {
+                tools: Some(ToolList::new(vec![tool])),
+                ..Default::default()
+            }
+        };
+        let with_header = |value: &str| LanguageModelOptions {
+            headers: HashMap::from([("x-tenant".to_string(), value.to_string())]),
+            ..Default::default()
+        };
+
+        assert_ne!(
+            key(with_schema(schemars::json_schema!({ "type": "object" }))),
+            key(with_schema(schemars::json_schema!({ "type": "string" })))
+        );
+        assert_ne!(key(with_header("a")), key(with_header("b")));
+        assert_ne!(
+            key(LanguageModelOptions::default()),
+            key(LanguageModelOptions {
+                store: Some(true),
+                ..Default::default()
+            })
+        );
+        assert_ne!(
+            key(LanguageModelOptions::default()),
+            key(LanguageModelOptions {
+                audio_output: Some(AudioOutput::new("alloy", "wav")),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_key_covers_provider_settings() {
+        use crate::providers::openai::OpenAI;
+
+        let openai = |base_url: &str| {
+            OpenAI::builder()
+                .base_url(base_url)
+                .model_name("gpt-4o")
+                .api_key("test")
+                .build()
+                .unwrap()
+        };
+        let options = LanguageModelOptions {
+            temperature: Some(0),
+            ..Default::default()
+        };
+        let cache = ResponseCache::new();
+
+        assert_ne!(
+            cache.key(&openai("https://a.example.com/v1"), &options),
+            cache.key(&openai("https://b.example.com/v1"), &options)
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Strategies for keeping long conversation histories within a token budget.
+//!
+//! A `HistoryPolicy` is applied to the messages of a request before it is sent
+//! when a `context_limit` is set. The oldest messages are dropped (or
+//! summarized) first, while the most recent user turn and everything after it
+//! are always kept.
+
+use crate::core::Message;
+use crate::core::language_model::LanguageModelResponseContentType;
+use crate::core::messages::{AssistantMessage, TaggedMessage};
+use crate::core::tokens::count_message_tokens;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Produces a summary of the messages dropped from the history.
+pub type SummarizeHook = Arc<dyn Fn(&[Message]) -> String + Send + Sync>;
+
+/// Policy for fitting a conversation history into a token budget.
+#[derive(Clone)]
+pub enum HistoryPolicy {
+    /// Drops the oldest messages until the history fits. System messages
+    /// are kept if `keep_system` is true.
+    TruncateOldest { keep_system: bool },
+    /// Replaces the oldest messages with a single system message containing
+    /// the summary returned by the hook. System messages are always kept.
+    /// The summary itself is not counted against the budget.
+    SummarizeOldest(SummarizeHook),
+}
+
+impl Debug for HistoryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryPolicy::TruncateOldest { keep_system } => f
+                .debug_struct("TruncateOldest")
+                .field("keep_system", keep_system)
+                .finish(),
+            HistoryPolicy::SummarizeOldest(_) => f.write_str("SummarizeOldest"),
+        }
+    }
+}
+
+impl HistoryPolicy {
+    /// Creates a policy summarizing the dropped messages with the given hook.
+    pub fn summarize_oldest<F>(hook: F) -> Self
+    where
+        F: Fn(&[Message]) -> String + Send + Sync + 'static,
+    {
+        HistoryPolicy::SummarizeOldest(Arc::new(hook))
+    }
+
+    /// Applies the policy, returning messages whose estimated size fits in
+    /// `budget` tokens where possible. The most recent user message and any
+    /// messages after it are never dropped.
+    pub(crate) fn apply(&self, messages: Vec<TaggedMessage>, budget: usize) -> Vec<TaggedMessage> {
+        let mut remaining: usize = messages
+            .iter()
+            .map(|m| count_message_tokens(&m.message))
+            .sum();
+
+        if remaining <= budget {
+            return messages;
+        }
+
+        let keep_system = match self {
+            HistoryPolicy::TruncateOldest { keep_system } => *keep_system,
+            HistoryPolicy::SummarizeOldest(_) => true,
+        };
+
+        let protected_from = messages
+            .iter()
+            .rposition(|m| matches!(m.message, Message::User(_)))
+            .unwrap_or(messages.len());
+
+        let mut dropped = vec![false; messages.len()];
+        for i in 0..protected_from {
+            if remaining <= budget {
+                break;
+            }
+            if dropped[i] || (keep_system && matches!(messages[i].message, Message::System(_))) {
+                continue;
+            }
+
+            dropped[i] = true;
+            remaining -= count_message_tokens(&messages[i].message);
+
+            // drop the results of a dropped tool call too, providers reject
+            // tool results without a matching call.
+            if let Message::Assistant(AssistantMessage {
+                content: LanguageModelResponseContentType::ToolCall(ref call),
+                ..
+            }) = messages[i].message
+            {
+                for j in i + 1..protected_from {
+                    if let Message::Tool(ref result) = messages[j].message
+                        && result.tool.id == call.tool.id
+                        && !dropped[j]
+                    {
+                        dropped[j] = true;
+                        remaining -= count_message_tokens(&messages[j].message);
+                    }
+                }
+            }
+        }
+
+        let summary = match self {
+            HistoryPolicy::SummarizeOldest(hook) => {
+                let removed: Vec<Message> = messages
+                    .iter()
+                    .zip(dropped.iter())
+                    .filter(|(_, d)| **d)
+                    .map(|(m, _)| m.message.clone())
+                    .collect();
+                (!removed.is_empty()).then(|| hook(&removed))
+            }
+            HistoryPolicy::TruncateOldest { .. } => None,
+        };
+
+        let mut summary = summary;
+        let mut result = Vec::with_capacity(messages.len());
+        for (message, dropped) in messages.into_iter().zip(dropped) {
+            if dropped {
+                if let Some(summary) = summary.take() {
+                    result.push(TaggedMessage::new(
+                        message.step_id,
+                        Message::System(summary.into()),
+                    ));
+                }
+            } else {
+                result.push(message);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokens::TOKENS_PER_MESSAGE;
+    use crate::core::{ToolCallInfo, ToolResultInfo};
+
+    fn tagged(message: Message) -> TaggedMessage {
+        TaggedMessage::initial_step_msg(message)
+    }
+
+    // every message is 40 chars, i.e. 10 tokens plus overhead
+    fn history() -> Vec<TaggedMessage> {
+        let text = |c: char| c.to_string().repeat(40);
+        vec![
+            tagged(Message::System(text('s').into())),
+            tagged(Message::User(text('a').into())),
+            tagged(Message::Assistant(text('b').into())),
+            tagged(Message::User(text('c').into())),
+            tagged(Message::Assistant(text('d').into())),
+            tagged(Message::User(text('e').into())),
+        ]
+    }
+
+    fn first_chars(messages: &[TaggedMessage]) -> String {
+        messages
+            .iter()
+            .map(|m| match &m.message {
+                Message::System(s) => s.content.chars().next().unwrap(),
+                Message::User(u) => u.content.chars().next().unwrap(),
+                Message::Assistant(a) => match &a.content {
+                    LanguageModelResponseContentType::Text(t) => t.chars().next().unwrap(),
+                    _ => '?',
+                },
+                _ => '?',
+            })
+            .collect()
+    }
+
+    const MESSAGE_TOKENS: usize = 10 + TOKENS_PER_MESSAGE;
+
+    #[test]
+    fn test_truncate_oldest_within_budget_is_noop() {
+        let policy = HistoryPolicy::TruncateOldest { keep_system: true };
+        let messages = policy.apply(history(), 6 * MESSAGE_TOKENS);
+        assert_eq!(first_chars(&messages), "sabcde");
+    }
+
+    #[test]
+    fn test_truncate_oldest_keeps_system_and_last_turns() {
+        let policy = HistoryPolicy::TruncateOldest { keep_system: true };
+        let messages = policy.apply(history(), 4 * MESSAGE_TOKENS);
+        assert_eq!(first_chars(&messages), "scde");
+    }
+
+    #[test]
+    fn test_truncate_oldest_without_keep_system() {
+        let policy = HistoryPolicy::TruncateOldest { keep_system: false };
+        let messages = policy.apply(history(), 4 * MESSAGE_TOKENS);
+        assert_eq!(first_chars(&messages), "bcde");
+    }
+
+    #[test]
+    fn test_truncate_oldest_never_drops_last_user_message() {
+        let policy = HistoryPolicy::TruncateOldest { keep_system: true };
+        let messages = policy.apply(history(), 0);
+        assert_eq!(first_chars(&messages), "se");
+    }
+
+    #[test]
+    fn test_truncate_oldest_drops_tool_results_with_their_call() {
+        let mut call = ToolCallInfo::new("search");
+        call.id("call_1");
+        let mut result = ToolResultInfo::new("search");
+        result.id("call_1");
+        result.output(serde_json::Value::String("x".repeat(400)));
+
+        let messages = vec![
+            tagged(Message::Assistant(AssistantMessage::new(
+                LanguageModelResponseContentType::ToolCall(call),
+                None,
+            ))),
+            tagged(Message::Tool(result)),
+            tagged(Message::User("latest".into())),
+        ];
+
+        let policy = HistoryPolicy::TruncateOldest { keep_system: true };
+        let messages = policy.apply(messages, MESSAGE_TOKENS);
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0].message, Message::User(_)));
+    }
+
+    #[test]
+    fn test_summarize_oldest_replaces_dropped_messages() {
+        let policy = HistoryPolicy::summarize_oldest(|dropped| {
+            format!("{} earlier messages", dropped.len())
+        });
+        let messages = policy.apply(history(), 4 * MESSAGE_TOKENS);
+
+        assert_eq!(messages.len(), 5);
+        assert_eq!(first_chars(&messages[2..]), "cde");
+        match &messages[1].message {
+            Message::System(s) => assert_eq!(s.content, "2 earlier messages"),
+            other => panic!("expected summary message, got {other:?}"),
+        }
+    }
+}
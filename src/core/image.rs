@@ -0,0 +1,115 @@
+//! Defines the `ImageModel` trait for generating images from text prompts.
+//!
+//! This module mirrors the `LanguageModel` abstraction for image generation.
+//! Providers that can create images implement `ImageModel`, returning the
+//! generated images either as inline base64 data or as urls.
+
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use derive_builder::Builder;
+
+/// The core trait abstracting the capabilities of an image generation model.
+#[async_trait]
+pub trait ImageModel: Send + Sync + std::fmt::Debug {
+    /// Generates one or more images for the given prompt.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the API call fails or the request is invalid.
+    async fn generate_image(&self, prompt: &str, options: ImageOptions) -> Result<ImageResponse>;
+}
+
+/// Options for an image generation request.
+#[derive(Debug, Clone, Default, Builder)]
+#[builder(
+    pattern = "owned",
+    setter(into, strip_option),
+    default,
+    build_fn(error = "Error")
+)]
+pub struct ImageOptions {
+    /// The size of the generated images in `WIDTHxHEIGHT` format, e.g. `1024x1024`.
+    pub size: Option<String>,
+
+    /// The quality of the generated images, e.g. `standard`, `hd`, `low`, `high`.
+    /// Supported values depend on the provider and model.
+    pub quality: Option<String>,
+
+    /// The number of images to generate.
+    pub n: Option<u8>,
+}
+
+impl ImageOptions {
+    pub fn builder() -> ImageOptionsBuilder {
+        ImageOptionsBuilder::default()
+    }
+}
+
+/// A single generated image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratedImage {
+    /// Base64 encoded image data.
+    Base64(String),
+    /// A url the image can be downloaded from.
+    Url(String),
+}
+
+impl GeneratedImage {
+    /// Decodes the base64 image data into raw bytes.
+    ///
+    /// Returns an `Error` for url images or if the data is not valid base64.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            GeneratedImage::Base64(data) => base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| Error::InvalidInput(format!("Invalid base64 image data: {e}"))),
+            GeneratedImage::Url(url) => Err(Error::InvalidInput(format!(
+                "Image is hosted at {url} and has no inline data"
+            ))),
+        }
+    }
+}
+
+/// Response from an image model.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageResponse {
+    /// The generated images.
+    pub images: Vec<GeneratedImage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_image_base64_to_bytes() {
+        let image = GeneratedImage::Base64("aGVsbG8=".to_string());
+        assert_eq!(image.to_bytes().unwrap(), b"hello".to_vec());
+    }
+
+    #[test]
+    fn test_generated_image_invalid_base64_to_bytes() {
+        let image = GeneratedImage::Base64("not base64!".to_string());
+        assert!(matches!(image.to_bytes(), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_generated_image_url_to_bytes() {
+        let image = GeneratedImage::Url("https://example.com/image.png".to_string());
+        assert!(matches!(image.to_bytes(), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_image_options_builder() {
+        let options = ImageOptions::builder()
+            .size("1024x1024")
+            .quality("high")
+            .n(2)
+            .build()
+            .unwrap();
+        assert_eq!(options.size, Some("1024x1024".to_string()));
+        assert_eq!(options.quality, Some("high".to_string()));
+        assert_eq!(options.n, Some(2));
+    }
+}
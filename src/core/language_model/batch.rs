@@ -0,0 +1,147 @@
+use crate::core::language_model::{
+    LanguageModel, LanguageModelOptions, generate_text::GenerateTextResponse,
+    request::LanguageModelRequest,
+};
+use crate::error::Result;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Generates text for each prompt using the same model and options.
+///
+/// At most `concurrency` requests run at the same time. The results are
+/// returned in the same order as `prompts`, a failed request does not stop
+/// the others. The options should not contain messages, as messages take
+/// precedence over the prompt.
+pub async fn generate_batch<M>(
+    model: M,
+    prompts: Vec<String>,
+    options: LanguageModelOptions,
+    concurrency: usize,
+) -> Vec<Result<GenerateTextResponse>>
+where
+    M: LanguageModel + Clone,
+{
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+
+    let requests = prompts.into_iter().map(|prompt| {
+        let semaphore = semaphore.clone();
+        let mut request = LanguageModelRequest {
+            model: Arc::new(model.clone()),
+            prompt: Some(prompt),
+            options: options.clone(),
+        };
+
+        async move {
+            let _permit = semaphore
+                .acquire()
+                .await
+                .expect("batch semaphore is never closed");
+            request.generate_text().await
+        }
+    });
+
+    futures::future::join_all(requests).await
+}
+
+/// Generates text for the same prompt with each model concurrently, e.g. to
+/// compare models in evals.
+///
+/// Each result is labeled with `provider/model` and returned in the same
+/// order as `models`, a failed request does not stop the others.
+pub async fn compare(
+    models: Vec<Box<dyn LanguageModel>>,
+    prompt: impl Into<String>,
+    options: LanguageModelOptions,
+) -> Vec<(String, Result<GenerateTextResponse>)> {
+    let prompt = prompt.into();
+
+    let requests = models.into_iter().map(|model| {
+        let label = format!("{}/{}", model.provider_name(), model.model_name());
+        let mut request = LanguageModelRequest {
+            model: Arc::new(model),
+            prompt: Some(prompt.clone()),
+            options: options.clone(),
+        };
+
+        async move { (label, request.generate_text().await) }
+    });
+
+    futures::future::join_all(requests).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockLanguageModel;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_generate_batch_preserves_order_and_limits_concurrency() {
+        let model = MockLanguageModel::new()
+            .echo()
+            .with_delay(Duration::from_millis(10));
+        let prompts: Vec<String> = (1..=5).map(|i| format!("prompt {i}")).collect();
+
+        let results = generate_batch(
+            model.clone(),
+            prompts.clone(),
+            LanguageModelOptions::default(),
+            2,
+        )
+        .await;
+
+        let texts: Vec<String> = results
+            .into_iter()
+            .map(|result| result.unwrap().text().unwrap())
+            .collect();
+        assert_eq!(texts, prompts);
+        assert_eq!(model.calls().len(), 5);
+        assert_eq!(model.max_concurrent_calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_generate_batch_keeps_failures_in_place() {
+        let model = MockLanguageModel::new()
+            .respond_text("first")
+            .respond(Err(crate::error::Error::Other("boom".to_string())));
+
+        let results = generate_batch(
+            model,
+            vec!["a".to_string(), "b".to_string()],
+            LanguageModelOptions::default(),
+            1,
+        )
+        .await;
+
+        assert_eq!(
+            results[0].as_ref().unwrap().text(),
+            Some("first".to_string())
+        );
+        assert!(results[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_compare_labels_results() {
+        let results = compare(
+            vec![
+                Box::new(MockLanguageModel::new().respond_text("Paris")),
+                Box::new(MockLanguageModel::new().respond_text("It is Paris.")),
+            ],
+            "Capital of France?",
+            LanguageModelOptions::default(),
+        )
+        .await;
+
+        let labeled: Vec<(String, String)> = results
+            .into_iter()
+            .map(|(label, result)| (label, result.unwrap().text().unwrap()))
+            .collect();
+        assert_eq!(
+            labeled,
+            vec![
+                ("mock/mock-model".to_string(), "Paris".to_string()),
+                ("mock/mock-model".to_string(), "It is Paris.".to_string()),
+            ]
+        );
+    }
+}
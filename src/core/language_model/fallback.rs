@@ -0,0 +1,209 @@
+//! A language model falling back to other models when a request fails.
+
+use crate::core::language_model::{
+    Capabilities, LanguageModel, LanguageModelOptions, LanguageModelResponse, ProviderStream,
+    ServedBy, routed_settings_key,
+};
+use crate::core::retry::RetryOnHook;
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Tries an ordered list of models, moving on to the next one when a request
+/// fails with an error worth falling back on (retryable errors by default).
+///
+/// The model that served a response is recorded in its `served_by`.
+pub struct FallbackModel {
+    models: Vec<Box<dyn LanguageModel>>,
+    fallback_on: RetryOnHook,
+}
+
+impl Debug for FallbackModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FallbackModel")
+            .field("models", &self.models)
+            .finish()
+    }
+}
+
+impl FallbackModel {
+    /// Creates a model trying `models` in order.
+    pub fn new(models: Vec<Box<dyn LanguageModel>>) -> Self {
+        Self {
+            models,
+            fallback_on: Arc::new(Error::is_retryable),
+        }
+    }
+
+    /// Sets the predicate deciding which errors fall back to the next model.
+    pub fn fallback_on<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.fallback_on = Arc::new(hook);
+        self
+    }
+
+    fn no_models() -> Error {
+        Error::InvalidInput("FallbackModel has no models".to_string())
+    }
+}
+
+#[async_trait]
+impl LanguageModel for FallbackModel {
+    /// The name of the first model. Responses record the model that served
+    /// them in `served_by`.
+    fn model_name(&self) -> String {
+        self.models
+            .first()
+            .map(|m| m.model_name())
+            .unwrap_or_default()
+    }
+
+    /// The provider of the first model.
+    fn provider_name(&self) -> &str {
+        self.models.first().map_or("unknown", |m| m.provider_name())
+    }
+
+    /// The capabilities supported by every model.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::shared(self.models.iter().map(|m| m.as_ref()))
+    }
+
+    /// The settings of every model, as any of them may serve a request.
+    fn settings_key(&self) -> String {
+        routed_settings_key(self.models.iter().map(|m| m.as_ref()))
+    }
+
+    /// The request body of the first model.
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        self.models
+            .first()
+            .ok_or_else(Self::no_models)?
+            .request_body(options)
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        let mut last_error = Self::no_models();
+        for model in &self.models {
+            match model.generate_text(options.clone()).await {
+                Ok(mut response) => {
+                    response
+                        .served_by
+                        .get_or_insert_with(|| ServedBy::new(model.as_ref()));
+                    return Ok(response);
+                }
+                Err(e) if (self.fallback_on)(&e) => {
+                    log::warn!(
+                        "{}/{} failed, falling back to the next model: {e}",
+                        model.provider_name(),
+                        model.model_name()
+                    );
+                    last_error = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        let mut last_error = Self::no_models();
+        for model in &self.models {
+            match model.stream_text(options.clone()).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) if (self.fallback_on)(&e) => {
+                    log::warn!(
+                        "{}/{} failed, falling back to the next model: {e}",
+                        model.provider_name(),
+                        model.model_name()
+                    );
+                    last_error = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::LanguageModelRequest;
+    use crate::test_utils::MockLanguageModel;
+
+    #[tokio::test]
+    async fn test_falls_back_to_next_model_on_error() {
+        let first = MockLanguageModel::new().respond(Err(Error::Other("rate limited".to_string())));
+        let second = MockLanguageModel::new()
+            .named("second")
+            .respond_text("from second");
+
+        let mut request = LanguageModelRequest::builder()
+            .model(
+                FallbackModel::new(vec![Box::new(first.clone()), Box::new(second.clone())])
+                    .fallback_on(|_| true),
+            )
+            .prompt("hi")
+            .build();
+        let response = request.generate_text().await.unwrap();
+
+        assert_eq!(response.text(), Some("from second".to_string()));
+        assert_eq!(response.provider_name(), "mock");
+        assert_eq!(response.model_name(), "second");
+        assert_eq!(first.calls().len(), 1);
+        assert_eq!(second.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shared_model_records_the_model_of_each_response() {
+        let first = MockLanguageModel::new()
+            .named("first")
+            .respond(Err(Error::Other("rate limited".to_string())))
+            .respond_text("from first");
+        let second = MockLanguageModel::new()
+            .named("second")
+            .respond_text("from second");
+        let model = Arc::new(
+            FallbackModel::new(vec![Box::new(first), Box::new(second)]).fallback_on(|_| true),
+        );
+
+        let generate = |model: Arc<FallbackModel>| async move {
+            LanguageModelRequest::builder()
+                .model(model)
+                .prompt("hi")
+                .build()
+                .generate_text()
+                .await
+                .unwrap()
+        };
+        let (a, b) = tokio::join!(generate(model.clone()), generate(model));
+
+        for response in [a, b] {
+            assert_eq!(
+                response.text(),
+                Some(format!("from {}", response.model_name()))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_does_not_fall_back_on_other_errors() {
+        let first = MockLanguageModel::new().respond(Err(Error::InvalidInput("bad".to_string())));
+        let second = MockLanguageModel::new().respond_text("from second");
+
+        let model = FallbackModel::new(vec![Box::new(first), Box::new(second.clone())]);
+        let result = model.generate_text(LanguageModelOptions::default()).await;
+
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        assert!(second.calls().is_empty());
+    }
+
+    #[test]
+    fn test_without_models_supports_nothing() {
+        let model = FallbackModel::new(Vec::new());
+        assert_eq!(model.capabilities(), Capabilities::default());
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "otel")]
+use crate::core::telemetry::GenerationSpan;
 use crate::error::Result;
 use crate::{
     Error,
@@ -5,15 +7,16 @@ use crate::{
         AssistantMessage, Message,
         language_model::{
             LanguageModel, LanguageModelOptions, LanguageModelResponse,
-            LanguageModelResponseContentType, StopReason, request::LanguageModelRequest,
+            LanguageModelResponseContentType, ServedBy, StopReason, TokenLogprob, Warning,
+            request::LanguageModelRequest, transcript,
         },
         messages::TaggedMessage,
-        utils::resolve_message,
+        utils,
     },
 };
 use serde::de::DeserializeOwned;
-use serde::ser::Error as SerdeError;
 use std::ops::Deref;
+use std::time::{Duration, Instant};
 
 impl<M: LanguageModel> LanguageModelRequest<M> {
     /// Generates text using a specified language model.
@@ -23,20 +26,15 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
     ///
     /// Returns an `Error` if the underlying model fails to generate a response.
     pub async fn generate_text(&mut self) -> Result<GenerateTextResponse> {
-        let (system_prompt, messages) = resolve_message(&self.options, &self.prompt);
+        let mut options = self.resolve_options()?;
 
-        let mut options = LanguageModelOptions {
-            system: Some(system_prompt),
-            messages,
-            schema: self.options.schema.to_owned(),
-            stop_sequences: self.options.stop_sequences.to_owned(),
-            tools: self.options.tools.to_owned(),
-            stop_when: self.options.stop_when.clone(),
-            prepare_step: self.options.prepare_step.clone(),
-            on_step_finish: self.options.on_step_finish.clone(),
-            stop_reason: None,
-            ..self.options
-        };
+        let mut logprobs: Vec<TokenLogprob> = Vec::new();
+        let mut citations: Vec<String> = Vec::new();
+        let mut warnings: Vec<Warning> = Vec::new();
+        let mut candidates: Vec<String> = Vec::new();
+        let mut response_id: Option<String>;
+        let mut served_by: Option<ServedBy>;
+        let started = Instant::now();
 
         loop {
             // Update the current step
@@ -47,16 +45,87 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
                 hook(&mut options);
             }
 
-            let response: LanguageModelResponse = self
-                .model
-                .generate_text(options.clone())
-                .await
-                .inspect_err(|e| {
-                options.stop_reason = Some(StopReason::Error(e.clone()));
-            })?;
+            // the history policy only shortens what is sent, the response
+            // keeps every message.
+            let request = options.redacted().fit_history();
+            request.check_context_limit()?;
+            options.log_tool_schemas();
+
+            let retry_policy = options.resolved_retry_policy();
+
+            #[cfg(feature = "cache")]
+            let cache_key = (options.cache.as_ref())
+                .and_then(|cache| cache.key(&self.model, &request).map(|key| (cache, key)));
+            #[cfg(feature = "cache")]
+            let cached = cache_key.and_then(|(cache, key)| cache.get(key));
+            #[cfg(not(feature = "cache"))]
+            let cached: Option<LanguageModelResponse> = None;
+
+            let mut attempt = 0;
+            let response: LanguageModelResponse = match cached {
+                Some(response) => response,
+                None => loop {
+                    #[cfg(feature = "otel")]
+                    let span = GenerationSpan::start(
+                        self.model.provider_name(),
+                        &self.model.model_name(),
+                        &request,
+                    );
+                    let result = self.model.generate_text(request.clone()).await;
+                    #[cfg(feature = "otel")]
+                    match &result {
+                        Ok(response) => span.end_response(response),
+                        Err(e) => span.fail(e),
+                    }
+
+                    match result {
+                        Ok(response) => break response,
+                        Err(e) if retry_policy.should_retry(&e, attempt) => {
+                            tokio::time::sleep(retry_policy.delay(attempt)).await;
+                            attempt += 1;
+                        }
+                        Err(e) => {
+                            options.stop_reason = Some(StopReason::Error(e.clone()));
+                            return Err(e);
+                        }
+                    }
+                },
+            };
+
+            #[cfg(feature = "cache")]
+            if let Some((cache, key)) = cache_key {
+                cache.insert(key, response.clone());
+            }
+
+            response_id = response.id.clone();
+            served_by = response.served_by.clone();
+            logprobs.extend(response.logprobs.iter().cloned());
+            citations.extend(response.citations.iter().cloned());
+            for warning in &response.warnings {
+                if !warnings.contains(warning) {
+                    warnings.push(warning.clone());
+                }
+            }
+
+            // with multiple completions only the first text continues the
+            // conversation, all of them are kept as candidates.
+            let multiple = options.n.is_some_and(|n| n > 1);
+            candidates.clear();
 
             for output in response.contents.iter() {
                 match output {
+                    LanguageModelResponseContentType::Text(text) if multiple => {
+                        if candidates.is_empty() {
+                            options.messages.push(TaggedMessage::new(
+                                options.current_step_id,
+                                Message::Assistant(AssistantMessage {
+                                    content: text.clone().into(),
+                                    usage: response.usage.clone(),
+                                }),
+                            ));
+                        }
+                        candidates.push(text.clone());
+                    }
                     LanguageModelResponseContentType::Text(text) => {
                         let assistant_msg = Message::Assistant(AssistantMessage {
                             content: text.clone().into(),
@@ -87,6 +156,17 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
                         ));
                         options.handle_tool_call(tool_info).await;
                     }
+                    LanguageModelResponseContentType::BuiltinToolCall(_)
+                    | LanguageModelResponseContentType::Refusal(_)
+                    | LanguageModelResponseContentType::Audio { .. } => {
+                        let assistant_msg = Message::Assistant(AssistantMessage {
+                            content: output.clone(),
+                            usage: response.usage.clone(),
+                        });
+                        options
+                            .messages
+                            .push(TaggedMessage::new(options.current_step_id, assistant_msg));
+                    }
                     _ => (),
                 }
             }
@@ -96,6 +176,11 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
                 hook(&options);
             };
 
+            if let Some(reason) = &response.incomplete {
+                options.stop_reason = Some(StopReason::Incomplete(reason.clone()));
+                break;
+            }
+
             if response.contents.is_empty() {
                 options.stop_reason = Some(StopReason::Error(Error::Other(
                     "Language model returned empty response".to_string(),
@@ -103,11 +188,21 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
                 break;
             }
 
+            // A tool marked `stop_after` produced the final answer
+            if options.stop_reason.is_some() {
+                break;
+            }
+
             // Stop If
             if let Some(hook) = &options.stop_when.clone()
-                && hook(&options)
+                && let Some(reason) = hook(&options)
             {
-                options.stop_reason = Some(StopReason::Hook);
+                options.stop_reason = Some(StopReason::Hook(reason));
+                break;
+            }
+
+            if options.tool_loop_detected() {
+                options.stop_reason = Some(StopReason::Other("tool loop detected".to_string()));
                 break;
             }
 
@@ -120,7 +215,23 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
             };
         }
 
-        Ok(GenerateTextResponse { options })
+        // a model routing requests records the model that served the last step
+        let ServedBy {
+            provider_name,
+            model_name,
+        } = served_by.unwrap_or_else(|| ServedBy::new(&*self.model));
+
+        Ok(GenerateTextResponse {
+            options,
+            logprobs,
+            citations,
+            warnings,
+            candidates,
+            response_id,
+            duration: started.elapsed(),
+            provider_name,
+            model_name,
+        })
     }
 }
 
@@ -129,21 +240,173 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
 // ============================================================================
 
 /// Response from a generate call on `GenerateText`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct GenerateTextResponse {
     /// The options that generated this response
     options: LanguageModelOptions,
+    /// Token log probabilities collected across all steps.
+    logprobs: Vec<TokenLogprob>,
+    /// Source urls collected across all steps.
+    citations: Vec<String>,
+    /// Options the provider could not honor, collected across all steps.
+    warnings: Vec<Warning>,
+    /// Text completions of the last step when more than one was requested.
+    candidates: Vec<String>,
+    /// The id the provider assigned to the response of the last step.
+    response_id: Option<String>,
+    /// Time taken to generate the response, across all steps.
+    duration: Duration,
+    /// The provider that generated the response.
+    provider_name: String,
+    /// The model that generated the response.
+    model_name: String,
 }
 
 impl GenerateTextResponse {
-    pub fn into_schema<T: DeserializeOwned>(&self) -> std::result::Result<T, serde_json::Error> {
-        if let Some(text) = &self.text() {
-            serde_json::from_str(text)
-        } else {
-            Err(serde_json::Error::custom("No text response found"))
+    /// Creates a response from the options and candidates of a finished
+    /// stream, which has no log probabilities, citations or warnings.
+    pub(crate) fn from_stream(
+        options: LanguageModelOptions,
+        candidates: Vec<String>,
+        duration: Duration,
+        provider_name: String,
+        model_name: String,
+    ) -> Self {
+        Self {
+            options,
+            candidates,
+            duration,
+            provider_name,
+            model_name,
+            ..Default::default()
         }
     }
 
+    /// Returns the token log probabilities of the generated output. Empty
+    /// unless `logprobs` was requested and supported by the provider.
+    pub fn logprobs(&self) -> &[TokenLogprob] {
+        &self.logprobs
+    }
+
+    /// Returns the source urls the response is grounded on. Only populated
+    /// by providers that search the web (e.g. Perplexity).
+    pub fn citations(&self) -> &[String] {
+        &self.citations
+    }
+
+    /// Returns the options the provider dropped or approximated, e.g. a
+    /// `top_k` sent to a provider that does not support it.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns the id the provider assigned to the last response, e.g. to
+    /// continue the conversation with `previous_response_id`. Only set by
+    /// providers storing responses (OpenAI).
+    pub fn response_id(&self) -> Option<&str> {
+        self.response_id.as_deref()
+    }
+
+    /// Returns the time taken to generate the response, including tool calls.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the name of the provider that generated the response.
+    pub fn provider_name(&self) -> &str {
+        &self.provider_name
+    }
+
+    /// Returns the id of the model that generated the response.
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    /// Returns every text completion of the last step when `n` completions
+    /// were requested. The first one is the response `text`.
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// Deserializes the final text response into `T`. Markdown code fences
+    /// and prose around the JSON, e.g. ```` ```json ... ``` ````, are ignored.
+    ///
+    /// Returns `Error::Schema` with the raw model output if there is no text
+    /// response, it contains no JSON, or the JSON does not match `T`.
+    pub fn into_schema<T: DeserializeOwned>(&self) -> Result<T> {
+        let Some(text) = self.text() else {
+            return Err(Error::Schema {
+                message: "No text response found".to_string(),
+                raw_text: String::new(),
+            });
+        };
+
+        if let Ok(value) = serde_json::from_str(&text) {
+            return Ok(value);
+        }
+
+        match utils::extract_json(&text) {
+            Some(json) => serde_json::from_str(json).map_err(|e| Error::Schema {
+                message: e.to_string(),
+                raw_text: text.clone(),
+            }),
+            None => Err(Error::Schema {
+                message: "No JSON found in the text response".to_string(),
+                raw_text: text,
+            }),
+        }
+    }
+
+    /// Continues the conversation with a new user message.
+    ///
+    /// Returns a request to `model` with the options and messages of this
+    /// response and the user message appended as a new step, so the next
+    /// `generate_text` call appends to the history.
+    pub fn continue_with<M: LanguageModel>(
+        &self,
+        model: M,
+        user_message: impl Into<String>,
+    ) -> LanguageModelRequest<M> {
+        let mut options = self.options.clone();
+        let last_step_id = options
+            .messages
+            .iter()
+            .map(|m| m.step_id)
+            .max()
+            .unwrap_or_default()
+            .max(options.current_step_id);
+
+        options.current_step_id = last_step_id + 1;
+        options.messages.push(TaggedMessage::new(
+            options.current_step_id,
+            Message::User(user_message.into().into()),
+        ));
+        options.stop_reason = None;
+
+        LanguageModelRequest::from_parts(model, options)
+    }
+
+    /// Serializes every message of the conversation to JSON Lines, one
+    /// message per line with its step id, role, content, tool call or
+    /// result and usage.
+    pub fn to_transcript_jsonl(&self) -> String {
+        transcript::to_jsonl(&self.options.messages)
+    }
+
+    /// Parses a transcript written by `to_transcript_jsonl`.
+    ///
+    /// Only the messages are restored; options, log probabilities and
+    /// timings are left at their defaults.
+    pub fn from_transcript_jsonl(jsonl: &str) -> Result<Self> {
+        Ok(Self {
+            options: LanguageModelOptions {
+                messages: transcript::from_jsonl(jsonl)?,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
     #[cfg(any(test, feature = "test-access"))]
     pub fn step_ids(&self) -> Vec<usize> {
         self.options.messages.iter().map(|t| t.step_id).collect()
@@ -161,11 +424,14 @@ impl Deref for GenerateTextResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::retry::RetryPolicy;
+    use crate::core::tools::{ToolExecute, ToolResultPart};
     use crate::core::{
         AssistantMessage, ToolCallInfo, ToolResultInfo,
         language_model::{LanguageModelResponseContentType, Usage},
         messages::TaggedMessage,
     };
+    use crate::test_utils::{MockLanguageModel, tool, tool_call};
 
     #[test]
     fn test_generate_text_response_step() {
@@ -183,7 +449,10 @@ mod tests {
             ],
             ..Default::default()
         };
-        let response = GenerateTextResponse { options };
+        let response = GenerateTextResponse {
+            options,
+            ..Default::default()
+        };
 
         let step0 = response.step(0).unwrap();
         assert_eq!(step0.step_id, 0);
@@ -212,7 +481,10 @@ mod tests {
             ],
             ..Default::default()
         };
-        let response = GenerateTextResponse { options };
+        let response = GenerateTextResponse {
+            options,
+            ..Default::default()
+        };
 
         let final_step = response.last_step().unwrap();
         assert_eq!(final_step.step_id, 2);
@@ -242,7 +514,10 @@ mod tests {
             ],
             ..Default::default()
         };
-        let response = GenerateTextResponse { options };
+        let response = GenerateTextResponse {
+            options,
+            ..Default::default()
+        };
 
         let steps = response.steps();
         assert_eq!(steps.len(), 3);
@@ -288,7 +563,10 @@ mod tests {
             ],
             ..Default::default()
         };
-        let response = GenerateTextResponse { options };
+        let response = GenerateTextResponse {
+            options,
+            ..Default::default()
+        };
 
         let total_usage = response.usage();
         assert_eq!(total_usage.input_tokens, Some(15));
@@ -327,7 +605,10 @@ mod tests {
             messages,
             ..Default::default()
         };
-        GenerateTextResponse { options }
+        GenerateTextResponse {
+            options,
+            ..Default::default()
+        }
     }
 
     // Tests for GenerateTextResponse tool_calls()
@@ -428,6 +709,32 @@ mod tests {
         assert_eq!(calls[2].tool.name, "tool_from_step3");
     }
 
+    #[test]
+    fn test_generate_text_response_step_and_tool_counts() {
+        let messages = vec![
+            TaggedMessage::new(0, Message::User("User".to_string().into())),
+            create_tool_call_message(1, "search"),
+            create_tool_result_message(1, "search"),
+            create_tool_call_message(2, "fetch"),
+            create_tool_call_message(2, "search"),
+            create_tool_result_message(2, "fetch"),
+            create_tool_result_message(2, "search"),
+            create_text_assistant_message(3, "Done"),
+        ];
+        let response = create_response_with_messages(messages);
+        assert_eq!(response.step_count(), 3);
+        assert_eq!(response.tool_call_count(), 3);
+        assert_eq!(response.distinct_tools_used(), vec!["search", "fetch"]);
+    }
+
+    #[test]
+    fn test_generate_text_response_counts_without_tools() {
+        let response = create_response_with_messages(vec![]);
+        assert_eq!(response.step_count(), 0);
+        assert_eq!(response.tool_call_count(), 0);
+        assert!(response.distinct_tools_used().is_empty());
+    }
+
     // Tests for GenerateTextResponse tool_results()
     #[test]
     fn test_generate_text_response_tool_results_empty_messages() {
@@ -550,4 +857,743 @@ mod tests {
             assert_eq!(result.tool.name, format!("tool{}", i));
         }
     }
+
+    #[tokio::test]
+    async fn test_generate_text_exceeding_context_limit() {
+        let model = MockLanguageModel::new().respond_text("unused");
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("a".repeat(1000))
+            .context_limit(100_usize)
+            .build()
+            .generate_text()
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        assert!(model.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_policy_applies_to_every_step() {
+        use crate::core::HistoryPolicy;
+
+        // the old turn fits the first step, the tool result of the first
+        // step only fits once the old turn is dropped.
+        let model = MockLanguageModel::new()
+            .respond(Ok(tool_call("lookup", "call_1")))
+            .respond_text("done");
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .messages(vec![
+                Message::User("a".repeat(400).into()),
+                Message::Assistant("b".repeat(400).into()),
+                Message::User("now?".into()),
+            ])
+            .with_tool(tool("lookup", &"c".repeat(400)))
+            .context_limit(300_usize)
+            .history_policy(HistoryPolicy::TruncateOldest { keep_system: true })
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(result.text(), Some("done".to_string()));
+        let calls = model.calls();
+        assert_eq!(calls[0].messages.len(), 3);
+        assert!(calls[1].messages.iter().all(|m| !matches!(
+            m.message,
+            Message::User(ref u) if u.content.starts_with('a')
+        )));
+        assert!(matches!(
+            calls[1].messages.last().unwrap().message,
+            Message::Tool(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_within_context_limit() {
+        let model = MockLanguageModel::new().respond_text("hello");
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("hi")
+            .context_limit(100_usize)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(result.text(), Some("hello".to_string()));
+        assert_eq!(model.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_stops_after_final_tool() {
+        let response = tool_call("final_answer", "call_1");
+
+        // a second round would fail as no response is queued
+        let model = MockLanguageModel::new().respond(Ok(response));
+
+        let mut tool = tool("final_answer", "42");
+        tool.stop_after = true;
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("What is the answer?")
+            .with_tool(tool)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(result.text(), Some("42".to_string()));
+        assert_eq!(result.stop_reason(), Some(StopReason::Finish));
+        assert_eq!(result.tool_results().unwrap().len(), 1);
+        assert_eq!(model.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_reports_tool_errors_to_model() {
+        let response = tool_call("get_weather", "call_1");
+
+        let model = MockLanguageModel::new()
+            .respond(Ok(response))
+            .respond_text("Sorry, I could not find that city.");
+
+        let mut tool = tool("get_weather", "");
+        tool.execute = ToolExecute::new(Box::new(|_| Err("city not found".to_string())));
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("Weather in Atlantis?")
+            .with_tool(tool)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let tool_result = &result.tool_results().unwrap()[0];
+        assert!(tool_result.is_error);
+        assert_eq!(
+            tool_result.error_message().unwrap(),
+            "Tool error: city not found"
+        );
+
+        // the second call carries the error tagged result
+        let sent = model.calls()[1].messages();
+        assert!(sent.iter().any(|m| matches!(m, Message::Tool(info)
+            if info.is_error && info.tool.id == "call_1")));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_collects_warnings() {
+        let mut first = tool_call("lookup", "call_1");
+        first.warnings = vec![Warning::unsupported("top_k", "Mock")];
+        let mut second = LanguageModelResponse::new("done");
+        second.warnings = vec![Warning::unsupported("top_k", "Mock")];
+
+        let model = MockLanguageModel::new()
+            .respond(Ok(first))
+            .respond(Ok(second));
+
+        let tool = tool("lookup", "found");
+
+        let result = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("Look it up")
+            .top_k(40_u32)
+            .with_tool(tool)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        // repeated on every step but reported once
+        assert_eq!(result.warnings(), &[Warning::unsupported("top_k", "Mock")]);
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_sends_image_tool_results() {
+        let response = tool_call("screenshot", "call_1");
+
+        let model = MockLanguageModel::new()
+            .respond(Ok(response))
+            .respond_text("A login page.");
+
+        let mut tool = tool("screenshot", "");
+        tool.execute = ToolExecute::with_parts(Box::new(|_| {
+            Ok(vec![
+                ToolResultPart::Text("Current page".to_string()),
+                ToolResultPart::image("image/png", "iVBORw0KGgo="),
+            ])
+        }));
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("What is on screen?")
+            .with_tool(tool)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let tool_result = &result.tool_results().unwrap()[0];
+        assert_eq!(
+            tool_result.output.as_ref().unwrap(),
+            &serde_json::json!("Current page")
+        );
+        assert_eq!(
+            tool_result.parts[1],
+            ToolResultPart::image("image/png", "iVBORw0KGgo=")
+        );
+
+        let sent = model.calls()[1].messages();
+        assert!(sent.iter().any(|m| matches!(m, Message::Tool(info)
+            if info.parts.len() == 2)));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_redacts_messages_on_every_step() {
+        let response = tool_call("lookup_user", "call_1");
+        let model = MockLanguageModel::new()
+            .respond(Ok(response))
+            .respond_text("done");
+
+        let tool = tool("lookup_user", "found bob@example.com");
+
+        let mask_emails = |text: &str| {
+            text.split(' ')
+                .map(|word| if word.contains('@') { "[email]" } else { word })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .system("Support agent for admin@example.com")
+            .prompt("Who is alice@example.com?")
+            .with_tool(tool)
+            .redactor(mask_emails)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let calls = model.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(
+            calls[0].system.as_deref(),
+            Some("Support agent for [email]")
+        );
+        for call in &calls {
+            for message in call.messages() {
+                match message {
+                    Message::System(s) => assert_eq!(s.content, "Support agent for [email]"),
+                    Message::User(u) => assert_eq!(u.content, "Who is [email]"),
+                    Message::Tool(t) => {
+                        assert_eq!(t.output.unwrap(), serde_json::json!("found [email]"))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        assert!(matches!(calls[1].messages().last(), Some(Message::Tool(_))));
+
+        // the conversation kept locally is not redacted
+        let results = result.tool_results().unwrap();
+        assert_eq!(
+            results[0].output.as_ref().unwrap(),
+            &serde_json::json!("found bob@example.com")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transcript_jsonl_round_trip() {
+        let mut response = tool_call("lookup", "call_1");
+        response.usage = Some(Usage {
+            input_tokens: Some(10),
+            output_tokens: Some(5),
+            ..Default::default()
+        });
+        let model = MockLanguageModel::new()
+            .respond(Ok(response))
+            .respond_text("Rust is a language");
+
+        let tool = tool("lookup", "a language");
+
+        let result = LanguageModelRequest::builder()
+            .model(model)
+            .system("Be brief")
+            .prompt("What is rust?")
+            .with_tool(tool)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let jsonl = result.to_transcript_jsonl();
+        assert_eq!(jsonl.lines().count(), result.messages().len());
+
+        let parsed = GenerateTextResponse::from_transcript_jsonl(&jsonl).unwrap();
+        assert_eq!(parsed.messages().len(), result.messages().len());
+        assert_eq!(parsed.step_ids(), result.step_ids());
+        assert_eq!(parsed.steps().len(), result.steps().len());
+        assert_eq!(parsed.tool_calls(), result.tool_calls());
+        assert_eq!(parsed.usage(), result.usage());
+        assert_eq!(parsed.text(), Some("Rust is a language".to_string()));
+        assert_eq!(parsed.to_transcript_jsonl(), jsonl);
+    }
+
+    #[tokio::test]
+    async fn test_continue_with_appends_to_history() {
+        let response = tool_call("lookup", "call_1");
+        let model = MockLanguageModel::new()
+            .respond(Ok(response))
+            .respond_text("Rust is a language")
+            .respond_text("It was first released in 2015");
+
+        let tool = tool("lookup", "a language");
+
+        let first = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("What is rust?")
+            .with_tool(tool)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+        assert_eq!(first.step_ids(), vec![0, 1, 1, 2]);
+
+        // resume from the persisted conversation
+        let persisted =
+            GenerateTextResponse::from_transcript_jsonl(&first.to_transcript_jsonl()).unwrap();
+        let second = persisted
+            .continue_with(model.clone(), "When was it released?")
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(second.step_ids(), vec![0, 1, 1, 2, 3, 4]);
+        assert_eq!(
+            second.text(),
+            Some("It was first released in 2015".to_string())
+        );
+        let messages = model.calls()[2].messages();
+        assert_eq!(messages.len(), 5);
+        assert!(matches!(&messages[4], Message::User(u) if u.content == "When was it released?"));
+    }
+
+    #[test]
+    fn test_into_schema_without_text_response() {
+        let response = GenerateTextResponse::default();
+        let result = response.into_schema::<serde_json::Value>();
+        assert!(matches!(
+            result,
+            Err(Error::Schema { ref message, ref raw_text })
+                if message == "No text response found" && raw_text.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_into_schema_with_malformed_json() {
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::new(
+                1,
+                Message::Assistant(AssistantMessage::new(
+                    "{\"name\": ".to_string().into(),
+                    None,
+                )),
+            )],
+            ..Default::default()
+        };
+        let response = GenerateTextResponse {
+            options,
+            ..Default::default()
+        };
+
+        let result = response.into_schema::<serde_json::Value>();
+        assert!(matches!(
+            result,
+            Err(Error::Schema { ref raw_text, .. }) if raw_text == "{\"name\": "
+        ));
+    }
+
+    fn response_with_text(text: &str) -> GenerateTextResponse {
+        GenerateTextResponse {
+            options: LanguageModelOptions {
+                messages: vec![TaggedMessage::new(
+                    1,
+                    Message::Assistant(AssistantMessage::new(text.to_string().into(), None)),
+                )],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_into_schema_with_bare_json() {
+        let value: serde_json::Value = response_with_text("{\"name\": \"Ada\"}")
+            .into_schema()
+            .unwrap();
+        assert_eq!(value, serde_json::json!({ "name": "Ada" }));
+    }
+
+    #[test]
+    fn test_into_schema_with_fenced_json() {
+        let value: serde_json::Value = response_with_text("```json\n{\"name\": \"Ada\"}\n```")
+            .into_schema()
+            .unwrap();
+        assert_eq!(value, serde_json::json!({ "name": "Ada" }));
+    }
+
+    #[test]
+    fn test_into_schema_with_fenced_json_and_prose() {
+        let text = "Sure! Here is the user:\n```json\n{\"name\": \"Ada\"}\n```\nLet me know.";
+        let value: serde_json::Value = response_with_text(text).into_schema().unwrap();
+        assert_eq!(value, serde_json::json!({ "name": "Ada" }));
+    }
+
+    #[test]
+    fn test_into_schema_without_json() {
+        let result =
+            response_with_text("I can't help with that.").into_schema::<serde_json::Value>();
+        assert!(matches!(
+            result,
+            Err(Error::Schema { ref message, ref raw_text })
+                if message == "No JSON found in the text response"
+                    && raw_text == "I can't help with that."
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_retries_with_policy() {
+        let model = MockLanguageModel::new()
+            .respond(Err(Error::Other("flaky".to_string())))
+            .respond_text("hello");
+
+        let policy = RetryPolicy {
+            base_delay: std::time::Duration::from_millis(1),
+            ..Default::default()
+        }
+        .retry_on(|_| true);
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("hi")
+            .retry_policy(policy)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(result.text(), Some("hello".to_string()));
+        assert_eq!(model.calls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_does_not_retry_when_retry_on_rejects() {
+        let model = MockLanguageModel::new()
+            .respond(Err(Error::Other("flaky".to_string())))
+            .respond_text("hello");
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("hi")
+            .retry_policy(RetryPolicy::default().retry_on(|_| false))
+            .build()
+            .generate_text()
+            .await;
+
+        assert!(matches!(result, Err(Error::Other(_))));
+        assert_eq!(model.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_with_multiple_completions() {
+        let mut response = LanguageModelResponse::new("");
+        response.contents = vec![
+            "first".to_string().into(),
+            "second".to_string().into(),
+            "third".to_string().into(),
+        ];
+        let model = MockLanguageModel::new().respond(Ok(response));
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("hi")
+            .n(3_u32)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(result.text(), Some("first".to_string()));
+        assert_eq!(result.candidates(), ["first", "second", "third"]);
+        assert_eq!(result.messages().len(), 2);
+        assert_eq!(model.calls()[0].n, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_response_reports_provider_and_model() {
+        let result = LanguageModelRequest::builder()
+            .model(MockLanguageModel::new().respond_text("hello"))
+            .prompt("hi")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(result.provider_name(), "mock");
+        assert_eq!(result.model_name(), "mock-model");
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_records_duration() {
+        let delay = std::time::Duration::from_millis(20);
+        let model = MockLanguageModel::new()
+            .with_delay(delay)
+            .respond_text("hello");
+
+        let result = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("hi")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert!(result.duration() >= delay);
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_without_user_content() {
+        let model = MockLanguageModel::new().respond_text("hello");
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .system("Be brief")
+            .messages(vec![])
+            .build()
+            .generate_text()
+            .await;
+
+        assert!(
+            matches!(result, Err(Error::InvalidInput(e)) if e.contains("no prompt or user message"))
+        );
+        assert!(model.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_with_audio_only_user_message() {
+        let model = MockLanguageModel::new().respond_text("hello");
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .messages(vec![Message::User(
+                crate::core::UserMessage::new("").with_audio("audio/wav", "UklGRg=="),
+            )])
+            .build()
+            .generate_text()
+            .await;
+
+        assert_eq!(result.unwrap().text(), Some("hello".to_string()));
+        assert_eq!(model.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_from_parts() {
+        let model = MockLanguageModel::new().respond_text("hello");
+        let options = LanguageModelOptions::builder()
+            .system(Some("Be brief".to_string()))
+            .messages(vec![TaggedMessage::initial_step_msg(Message::User(
+                "hi".into(),
+            ))])
+            .build()
+            .unwrap();
+
+        let mut request = LanguageModelRequest::from_parts(model.clone(), options);
+        request.options_mut().temperature = Some(20);
+        let result = request.generate_text().await.unwrap();
+
+        assert_eq!(result.text(), Some("hello".to_string()));
+        let call = &model.calls()[0];
+        assert_eq!(call.system.as_deref(), Some("Be brief"));
+        assert_eq!(call.temperature, Some(20));
+        assert!(matches!(&call.messages()[..], [Message::User(u)] if u.content == "hi"));
+    }
+
+    #[tokio::test]
+    async fn test_logs_tool_schemas_per_step() {
+        crate::test_utils::capture_logs();
+
+        let tool = |name: &str| {
+            let mut tool = tool(name, "ok");
+            tool.input_schema = schemars::json_schema!({ "type": "object" });
+            tool
+        };
+
+        LanguageModelRequest::builder()
+            .model(MockLanguageModel::new().respond_text("done"))
+            .prompt("hi")
+            .with_tool(tool("schema_log_search"))
+            .with_tool(tool("schema_log_fetch"))
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let logs = crate::test_utils::logs("aisdk::tools");
+        let event = logs
+            .iter()
+            .find(|message| message.contains("schema_log_search"))
+            .expect("tool schemas should be logged");
+        assert!(event.starts_with("step 1 sends tools: "));
+        assert!(event.contains(r#""name":"schema_log_fetch""#));
+        assert!(event.contains(r#""input_schema":{"type":"object"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_messages_from_role_pairs() {
+        use crate::core::Role;
+
+        let model = MockLanguageModel::new().respond_text("Paris");
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .system("Answer briefly")
+            .messages_from([
+                (Role::User, "Capital of Italy?"),
+                (Role::Assistant, "Rome"),
+                (Role::User, "And France?"),
+            ])
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let sent = &model.calls()[0];
+        assert!(sent.messages.iter().all(|m| m.step_id == 0));
+        assert!(matches!(
+            &sent.messages()[..],
+            [Message::User(a), Message::Assistant(b), Message::User(c)]
+                if a.content == "Capital of Italy?" && b.text() == Some("Rome") && c.content == "And France?"
+        ));
+        assert_eq!(result.text(), Some("Paris".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_detection_stops_repeated_calls() {
+        let mut model = MockLanguageModel::new();
+        for i in 0..5 {
+            model = model.respond(Ok(tool_call("search", &format!("call_{i}"))));
+        }
+        let tool = tool("search", "no results");
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("Research rust")
+            .with_tool(tool)
+            .tool_loop_threshold(2)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(model.calls().len(), 3);
+        assert_eq!(result.tool_call_count(), 3);
+        assert!(matches!(
+            result.stop_reason(),
+            Some(StopReason::Other(reason)) if reason == "tool loop detected"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_messages_in_order_across_steps() {
+        let model = MockLanguageModel::new()
+            .respond(Ok(tool_call("lookup", "call_1")))
+            .respond_text("It is 42.");
+
+        let before = std::time::SystemTime::now();
+        let result = LanguageModelRequest::builder()
+            .model(model)
+            .system("Be brief")
+            .prompt("What is the answer?")
+            .with_tool(tool("lookup", "42"))
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let lines: Vec<String> = result.messages().iter().map(Message::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "system: Be brief",
+                "user: What is the answer?",
+                "assistant: lookup({})",
+                r#"tool lookup: "42""#,
+                "assistant: It is 42.",
+            ]
+        );
+        let steps: Vec<usize> = result.options.messages.iter().map(|m| m.step_id).collect();
+        assert!(steps.is_sorted());
+
+        // messages appended during generation get an id and a timestamp
+        let (seeded, appended) = result.options.messages.split_at(2);
+        assert!(
+            seeded
+                .iter()
+                .all(|m| m.id.is_none() && m.created_at.is_none())
+        );
+        assert_eq!(appended.len(), 3);
+        assert!(
+            appended
+                .iter()
+                .all(|m| m.created_at.is_some_and(|t| t >= before))
+        );
+        let ids: std::collections::HashSet<_> =
+            appended.iter().map(|m| m.id.clone().unwrap()).collect();
+        assert_eq!(ids.len(), appended.len());
+    }
+
+    #[tokio::test]
+    async fn test_tagged_messages_keep_their_steps() {
+        let result = LanguageModelRequest::builder()
+            .model(MockLanguageModel::new().respond_text("Paris."))
+            .tagged_messages(vec![
+                TaggedMessage::new(0, Message::System("Be brief".into())),
+                TaggedMessage::new(1, Message::User("Hi".into())),
+                TaggedMessage::new(1, Message::Assistant("Hello!".to_string().into())),
+                TaggedMessage::new(2, Message::User("Capital of France?".into())),
+            ])
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let steps: Vec<(usize, Vec<String>)> = result
+            .steps()
+            .iter()
+            .map(|s| {
+                (
+                    s.step_id,
+                    s.messages().iter().map(Message::to_string).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (0, vec!["system: Be brief".to_string()]),
+                (
+                    1,
+                    vec!["user: Hi".to_string(), "assistant: Hello!".to_string()]
+                ),
+                (2, vec!["user: Capital of France?".to_string()]),
+                (3, vec!["assistant: Paris.".to_string()]),
+            ]
+        );
+    }
 }
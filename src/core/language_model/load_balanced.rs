@@ -0,0 +1,268 @@
+//! A language model spreading requests over equivalent backends by weight.
+
+use crate::core::Message;
+use crate::core::language_model::{
+    Capabilities, LanguageModel, LanguageModelOptions, LanguageModelResponse, ProviderStream,
+    ServedBy, routed_settings_key,
+};
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+
+/// Picks one of several weighted backends, e.g. the same model behind
+/// different API keys or regions, for every request.
+///
+/// A backend with weight 3 receives about three times as many requests as a
+/// backend with weight 1. If `sticky` is set, every request of a conversation
+/// goes to the backend picked for its first request. Conversations are told
+/// apart by their first message other than the system prompt.
+///
+/// The backend that served a response is recorded in its `served_by`.
+#[derive(Debug, Default)]
+pub struct LoadBalancedModel {
+    backends: Vec<(Box<dyn LanguageModel>, u32)>,
+    sticky: bool,
+    /// The backend picked for each conversation, by `conversation_key`.
+    pinned: Mutex<HashMap<u64, usize>>,
+}
+
+impl LoadBalancedModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a backend with the given weight. Backends with weight 0 are never
+    /// picked.
+    pub fn backend(mut self, model: impl LanguageModel + 'static, weight: u32) -> Self {
+        self.backends.push((Box::new(model), weight));
+        self
+    }
+
+    /// Keeps every request of a conversation on the same backend.
+    pub fn sticky(mut self, sticky: bool) -> Self {
+        self.sticky = sticky;
+        self
+    }
+
+    /// Returns the index of the backend the conversation of `options` is
+    /// kept on, if `sticky` is set and the conversation had a request.
+    pub fn pinned(&self, options: &LanguageModelOptions) -> Option<usize> {
+        self.pinned_backends()
+            .get(&conversation_key(options))
+            .copied()
+    }
+
+    fn pinned_backends(&self) -> MutexGuard<'_, HashMap<u64, usize>> {
+        self.pinned
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Picks a backend for the next request of the conversation of `options`.
+    fn pick(&self, options: &LanguageModelOptions) -> Result<&dyn LanguageModel> {
+        let key = self.sticky.then(|| conversation_key(options));
+        let mut pinned = self.pinned_backends();
+        if let Some(&index) = key.as_ref().and_then(|key| pinned.get(key)) {
+            return Ok(self.backends[index].0.as_ref());
+        }
+
+        let total: u64 = self.backends.iter().map(|(_, w)| *w as u64).sum();
+        if total == 0 {
+            return Err(Error::InvalidInput(
+                "LoadBalancedModel has no backends with a weight above 0".to_string(),
+            ));
+        }
+
+        let mut target = fastrand::u64(0..total);
+        let index = self
+            .backends
+            .iter()
+            .position(|(_, weight)| match target.checked_sub(*weight as u64) {
+                Some(rest) => {
+                    target = rest;
+                    false
+                }
+                None => true,
+            })
+            .expect("target is below the total weight");
+
+        if let Some(key) = key {
+            pinned.insert(key, index);
+        }
+        Ok(self.backends[index].0.as_ref())
+    }
+}
+
+/// Identifies a conversation by its first message other than the system
+/// prompt, which many conversations share.
+fn conversation_key(options: &LanguageModelOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    options
+        .messages
+        .iter()
+        .find(|m| !matches!(m.message, Message::System(_) | Message::Developer(_)))
+        .map(|m| m.message.to_string())
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[async_trait]
+impl LanguageModel for LoadBalancedModel {
+    /// The name of the first backend. Responses record the backend that
+    /// served them in `served_by`.
+    fn model_name(&self) -> String {
+        self.backends
+            .first()
+            .map(|(m, _)| m.model_name())
+            .unwrap_or_default()
+    }
+
+    /// The provider of the first backend.
+    fn provider_name(&self) -> &str {
+        self.backends
+            .first()
+            .map_or("unknown", |(m, _)| m.provider_name())
+    }
+
+    /// The capabilities supported by every backend, as any of them may
+    /// serve a request.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::shared(self.backends.iter().map(|(m, _)| m.as_ref()))
+    }
+
+    /// The settings of every backend, as any of them may serve a request.
+    fn settings_key(&self) -> String {
+        routed_settings_key(self.backends.iter().map(|(m, _)| m.as_ref()))
+    }
+
+    /// The request body of the first backend.
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        match self.backends.first() {
+            Some((model, _)) => model.request_body(options),
+            None => Err(Error::InvalidInput(
+                "LoadBalancedModel has no backends".to_string(),
+            )),
+        }
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        let backend = self.pick(&options)?;
+        let mut response = backend.generate_text(options).await?;
+        response
+            .served_by
+            .get_or_insert_with(|| ServedBy::new(backend));
+        Ok(response)
+    }
+
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        self.pick(&options)?.stream_text(options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockLanguageModel;
+
+    #[tokio::test]
+    async fn test_distribution_follows_weights() {
+        let a = MockLanguageModel::new().echo();
+        let b = MockLanguageModel::new().echo();
+        let c = MockLanguageModel::new().echo();
+        let model = LoadBalancedModel::new()
+            .backend(a.clone(), 1)
+            .backend(b.clone(), 3)
+            .backend(c.clone(), 0);
+
+        let options = LanguageModelOptions {
+            messages: vec![Message::User("hi".into()).into()],
+            ..Default::default()
+        };
+        for _ in 0..2000 {
+            model.generate_text(options.clone()).await.unwrap();
+        }
+
+        let share_a = a.calls().len() as f64 / 2000.0;
+        assert!((0.2..0.3).contains(&share_a), "share of a was {share_a}");
+        assert_eq!(a.calls().len() + b.calls().len(), 2000);
+        assert!(c.calls().is_empty());
+    }
+
+    #[test]
+    fn test_capabilities_are_shared_by_every_backend() {
+        let a = MockLanguageModel::new().with_capabilities(Capabilities {
+            tools: true,
+            vision: true,
+            streaming: true,
+            ..Default::default()
+        });
+        let b = MockLanguageModel::new().with_capabilities(Capabilities {
+            tools: true,
+            reasoning: true,
+            streaming: true,
+            ..Default::default()
+        });
+        let model = LoadBalancedModel::new().backend(a, 1).backend(b, 1);
+
+        assert_eq!(
+            model.capabilities(),
+            Capabilities {
+                tools: true,
+                streaming: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sticky_keeps_each_conversation_on_one_backend() {
+        use crate::core::{AssistantMessage, TaggedMessage};
+
+        let model = LoadBalancedModel::new()
+            .backend(MockLanguageModel::new().named("a").echo(), 1)
+            .backend(MockLanguageModel::new().named("b").echo(), 1)
+            .sticky(true);
+
+        let mut served = Vec::new();
+        for conversation in 0..20 {
+            let mut options = LanguageModelOptions {
+                messages: vec![
+                    Message::System("Be brief".into()).into(),
+                    Message::User(format!("conversation {conversation}").into()).into(),
+                ],
+                ..Default::default()
+            };
+            let mut backends = Vec::new();
+            for step in 1..=3 {
+                let response = model.generate_text(options.clone()).await.unwrap();
+                backends.push(response.served_by.unwrap().model_name);
+                options.messages.extend([
+                    TaggedMessage::new(
+                        step,
+                        Message::Assistant(AssistantMessage::new("sure".to_string().into(), None)),
+                    ),
+                    TaggedMessage::new(step, Message::User("go on".into())),
+                ]);
+            }
+
+            assert!(backends.iter().all(|b| *b == backends[0]), "{backends:?}");
+            assert_eq!(
+                model.pinned(&options),
+                Some(if backends[0] == "a" { 0 } else { 1 })
+            );
+            served.push(backends.swap_remove(0));
+        }
+
+        assert!(served.contains(&"a".to_string()));
+        assert!(served.contains(&"b".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_without_weights_fails() {
+        let model = LoadBalancedModel::new().backend(MockLanguageModel::new(), 0);
+        let result = model.generate_text(LanguageModelOptions::default()).await;
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+    }
+}
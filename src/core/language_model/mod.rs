@@ -5,32 +5,46 @@
 //! underlying implementation details of different AI providers, offering a
 //! unified interface for various operations like text generation or streaming.
 
+pub mod batch;
+pub mod fallback;
 pub mod generate_text;
+pub mod load_balanced;
 pub mod request;
+pub mod retry_model;
+pub mod stream_object;
 pub mod stream_text;
+pub mod timeout_model;
+pub mod transcript;
 
+use crate::core::history::HistoryPolicy;
 use crate::core::messages::{AssistantMessage, TaggedMessage, TaggedMessageHelpers};
-use crate::core::tools::ToolList;
-use crate::core::utils;
-use crate::core::{Message, ToolCallInfo, ToolResultInfo};
+use crate::core::retry::RetryPolicy;
+use crate::core::tools::{ToolList, ToolResultPart};
+use crate::core::{BuiltinToolCallInfo, Message, ToolCallInfo, ToolResultInfo};
+use crate::core::{tokens, utils};
 use crate::error::{Error, Result};
 use async_trait::async_trait;
 use derive_builder::Builder;
 use futures::Stream;
 use schemars::Schema;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::ops::Add;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::task::{Context, Poll};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 // ============================================================================
 // Section: constants
 // ============================================================================
 pub const DEFAULT_TOOL_STEP_COUNT: usize = 3;
 
+/// The range accepted for `presence_penalty` and `frequency_penalty`.
+pub const PENALTY_RANGE: std::ops::RangeInclusive<f32> = -2.0..=2.0;
+
 // ============================================================================
 // Section: traits
 // ============================================================================
@@ -44,18 +58,42 @@ pub const DEFAULT_TOOL_STEP_COUNT: usize = 3;
 /// generation and streaming responses.
 #[async_trait]
 pub trait LanguageModel: Send + Sync + std::fmt::Debug {
-    fn name(&self) -> String;
+    /// The id of the configured model, e.g. "gpt-4o".
+    fn model_name(&self) -> String;
+
+    /// The name of the provider serving the model, e.g. "openai".
+    fn provider_name(&self) -> &str {
+        "unknown"
+    }
+
+    /// The id of the configured model.
+    #[deprecated(note = "use `model_name` instead")]
+    fn name(&self) -> String {
+        self.model_name()
+    }
+
+    /// Returns the features supported by the model.
+    ///
+    /// Defaults to no capabilities other than plain text generation.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Settings of the model that change the requests it sends besides the
+    /// options, e.g. the base url. Used to key cached responses.
+    fn settings_key(&self) -> String {
+        String::new()
+    }
+
     /// Performs a single, non-streaming text generation request.
     ///
     /// This method sends a prompt to the model and returns the entire response at once.
+    /// Takes `&self` so a single model can serve concurrent requests.
     ///
     /// # Errors
     ///
     /// Returns an `Error` if the API call fails or the request is invalid.
-    async fn generate_text(
-        &mut self,
-        options: LanguageModelOptions,
-    ) -> Result<LanguageModelResponse>;
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse>;
 
     /// Performs a streaming text generation request.
     ///
@@ -64,21 +102,157 @@ pub trait LanguageModel: Send + Sync + std::fmt::Debug {
     /// # Errors
     ///
     /// Returns an `Error` if the API call fails or the request is invalid.
-    async fn stream_text(&mut self, options: LanguageModelOptions) -> Result<ProviderStream>;
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream>;
+
+    /// Returns the body of the request `generate_text` would send for the
+    /// given options, without sending it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the model does not support previews or the
+    /// options can not be converted.
+    fn request_body(&self, _options: LanguageModelOptions) -> Result<serde_json::Value> {
+        Err(Error::Other(format!(
+            "{} does not support request previews",
+            self.provider_name()
+        )))
+    }
+}
+
+/// Allows boxed models, e.g. `Box<dyn LanguageModel>`, to be used wherever a
+/// `LanguageModel` is expected. Useful to select a provider at runtime or to
+/// store different providers in one collection.
+#[async_trait]
+impl<M: LanguageModel + ?Sized> LanguageModel for Box<M> {
+    fn provider_name(&self) -> &str {
+        (**self).provider_name()
+    }
+
+    fn model_name(&self) -> String {
+        (**self).model_name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        (**self).capabilities()
+    }
+
+    fn settings_key(&self) -> String {
+        (**self).settings_key()
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        (**self).generate_text(options).await
+    }
+
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        (**self).stream_text(options).await
+    }
+
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        (**self).request_body(options)
+    }
+}
+
+/// Allows a model to be shared between tasks, e.g. `Arc<OpenAI>`, without
+/// creating a client per task.
+#[async_trait]
+impl<M: LanguageModel + ?Sized> LanguageModel for Arc<M> {
+    fn provider_name(&self) -> &str {
+        (**self).provider_name()
+    }
+
+    fn model_name(&self) -> String {
+        (**self).model_name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        (**self).capabilities()
+    }
+
+    fn settings_key(&self) -> String {
+        (**self).settings_key()
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        (**self).generate_text(options).await
+    }
+
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        (**self).stream_text(options).await
+    }
+
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        (**self).request_body(options)
+    }
 }
 
 // ============================================================================
 // Section: hook types
 // ============================================================================
 
-pub type StopWhenHook = Arc<dyn Fn(&LanguageModelOptions) -> bool + Send + Sync>;
+pub type StopWhenHook = Arc<dyn Fn(&LanguageModelOptions) -> Option<String> + Send + Sync>;
 pub type PrepareStepHook = Arc<dyn Fn(&mut LanguageModelOptions) + Send + Sync>;
 pub type OnStepFinishHook = Arc<dyn Fn(&LanguageModelOptions) + Send + Sync>;
+pub type RedactHook = Arc<dyn Fn(&str) -> String + Send + Sync>;
 
 // ============================================================================
 // Section: structs and impls
 // ============================================================================
 
+/// Features supported by a language model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether the model can call tools.
+    pub tools: bool,
+    /// Whether the model accepts images as input.
+    pub vision: bool,
+    /// Whether the model supports reasoning, e.g. via `reasoning_effort`.
+    pub reasoning: bool,
+    /// Whether the model supports streaming responses.
+    pub streaming: bool,
+    /// Whether the model accepts and generates audio.
+    pub audio: bool,
+}
+
+impl Capabilities {
+    /// The capabilities supported by every one of `models`, for a model
+    /// routing each request to any of them. Without models nothing is
+    /// supported.
+    pub(crate) fn shared<'a>(models: impl IntoIterator<Item = &'a dyn LanguageModel>) -> Self {
+        let all: Vec<Capabilities> = models.into_iter().map(|m| m.capabilities()).collect();
+        if all.is_empty() {
+            return Self::default();
+        }
+        let every = |f: fn(&Capabilities) -> bool| all.iter().all(f);
+        Self {
+            tools: every(|c| c.tools),
+            vision: every(|c| c.vision),
+            reasoning: every(|c| c.reasoning),
+            streaming: every(|c| c.streaming),
+            audio: every(|c| c.audio),
+        }
+    }
+}
+
+/// The settings of every one of `models`, for a model routing each request
+/// to any of them.
+pub(crate) fn routed_settings_key<'a>(
+    models: impl IntoIterator<Item = &'a dyn LanguageModel>,
+) -> String {
+    models
+        .into_iter()
+        .map(|m| {
+            format!(
+                "{}/{} {}",
+                m.provider_name(),
+                m.model_name(),
+                m.settings_key()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// A "step" represents a single cycle of model interaction.
 pub struct Step {
     pub step_id: usize,
@@ -143,7 +317,12 @@ impl Step {
 
 /// Options for a language model request.
 #[derive(Clone, Default, Builder)]
-#[builder(pattern = "owned", setter(into), build_fn(error = "Error"))]
+#[builder(
+    pattern = "owned",
+    setter(into),
+    default,
+    build_fn(error = "Error", validate = "Self::validate")
+)]
 pub struct LanguageModelOptions {
     /// System prompt to be used for the request.
     pub system: Option<String>,
@@ -151,6 +330,14 @@ pub struct LanguageModelOptions {
     /// Output format schema.
     pub schema: Option<Schema>,
 
+    /// Whether the provider enforces `schema` strictly, e.g. OpenAI's strict
+    /// structured outputs. Uses the provider's default if unset.
+    pub schema_strict: Option<bool>,
+
+    /// Requests valid JSON output without a schema, using the provider's
+    /// generic JSON mode. Ignored when `schema` is set.
+    pub json_mode: Option<bool>,
+
     /// The seed (integer) to use for random sampling. If set and supported
     /// by the model, calls will generate deterministic results.
     pub seed: Option<u32>,
@@ -164,27 +351,42 @@ pub struct LanguageModelOptions {
     /// Top-k sampling.
     pub top_k: Option<u32>,
 
-    /// Maximum number of retries.
+    /// Maximum number of retries. Overrides `max_retries` of the retry policy.
     pub max_retries: Option<u32>,
 
+    /// How failed calls to the model are retried. Uses the default
+    /// `RetryPolicy` if unset.
+    pub retry_policy: Option<RetryPolicy>,
+
     /// Maxoutput tokens.
     pub max_output_tokens: Option<u32>,
 
+    /// Number of completions to generate. Only the first is added to the
+    /// conversation, the others are available as candidates on the response.
+    pub n: Option<u32>,
+
     /// Stop sequences.
     /// If set, the model will stop generating text when one of the stop sequences is generated.
     pub stop_sequences: Option<Vec<String>>,
 
     /// Presence penalty setting. It affects the likelihood of the model to
-    /// repeat information that is already in the prompt.
+    /// repeat information that is already in the prompt. Must be within
+    /// `PENALTY_RANGE`.
     pub presence_penalty: Option<f32>,
 
     /// Frequency penalty setting. It affects the likelihood of the model
-    /// to repeatedly use the same words or phrases.
+    /// to repeatedly use the same words or phrases. Must be within
+    /// `PENALTY_RANGE`.
     pub frequency_penalty: Option<f32>,
 
-    /// Hook to stop tool calling if returns true
+    /// Hook to stop tool calling, returns the reason to stop
     pub stop_when: Option<StopWhenHook>,
 
+    /// Stops the tool loop with `StopReason::Other("tool loop detected")`
+    /// once the same tool is called with the same input more than this many
+    /// times in a row.
+    pub tool_loop_threshold: Option<usize>,
+
     /// Hook called before each step (language model request)
     pub prepare_step: Option<PrepareStepHook>,
 
@@ -194,6 +396,50 @@ pub struct LanguageModelOptions {
     /// Reasoning effort
     pub reasoning_effort: Option<ReasoningEffort>,
 
+    /// Whether to return log probabilities of the output tokens.
+    pub logprobs: Option<bool>,
+
+    /// Number of most likely alternative tokens to return at each token
+    /// position. Only used when `logprobs` is enabled.
+    pub top_logprobs: Option<u8>,
+
+    /// The context window of the model in tokens. If set, requests whose
+    /// estimated size exceeds it fail before being sent.
+    pub context_limit: Option<usize>,
+
+    /// How to shorten the conversation history when it does not fit in
+    /// `context_limit`. Applied before every step, to the messages sent only.
+    pub history_policy: Option<HistoryPolicy>,
+
+    /// Hook rewriting the text of system, user and tool result messages
+    /// before every request to the model, e.g. to mask personal data.
+    pub redactor: Option<RedactHook>,
+
+    /// Extra http headers sent with every request to the model, e.g. a
+    /// tenant or trace id. Headers the provider sets itself, such as auth and
+    /// content type, are never overridden.
+    pub headers: HashMap<String, String>,
+
+    /// A stable identifier of the end user, sent to providers supporting it
+    /// for abuse monitoring.
+    pub user: Option<String>,
+
+    /// Whether the provider stores the response for later retrieval, e.g.
+    /// to continue the conversation from it with `previous_response_id`.
+    pub store: Option<bool>,
+
+    /// The id of a stored response to continue the conversation from, for
+    /// providers keeping the conversation state on the server (OpenAI).
+    pub previous_response_id: Option<String>,
+
+    /// Audio the model generates along with the text, for models
+    /// supporting audio output.
+    pub audio_output: Option<AudioOutput>,
+
+    /// Cache consulted before calling the model in `generate_text`.
+    #[cfg(feature = "cache")]
+    pub cache: Option<crate::core::cache::ResponseCache>,
+
     /// List of tools to use.
     pub(crate) tools: Option<ToolList>,
 
@@ -210,16 +456,21 @@ pub struct LanguageModelOptions {
 
 impl Debug for LanguageModelOptions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("LanguageModelOptions")
+        let mut debug = f.debug_struct("LanguageModelOptions");
+        debug
             .field("system", &self.system)
             .field("messages", &self.messages)
             .field("schema", &self.schema)
+            .field("schema_strict", &self.schema_strict)
+            .field("json_mode", &self.json_mode)
             .field("seed", &self.seed)
             .field("temperature", &self.temperature)
             .field("top_p", &self.top_p)
             .field("top_k", &self.top_k)
             .field("max_retries", &self.max_retries)
+            .field("retry_policy", &self.retry_policy)
             .field("max_output_tokens", &self.max_output_tokens)
+            .field("n", &self.n)
             .field("stop_sequences", &self.stop_sequences)
             .field("presence_penalty", &self.presence_penalty)
             .field("frequency_penalty", &self.frequency_penalty)
@@ -228,8 +479,53 @@ impl Debug for LanguageModelOptions {
             .field("stop_when", &self.stop_when.is_some())
             .field("prepare_step", &self.prepare_step.is_some())
             .field("on_step_finish", &self.on_step_finish.is_some())
-            .finish()
+            .field("tool_loop_threshold", &self.tool_loop_threshold)
+            .field("logprobs", &self.logprobs)
+            .field("top_logprobs", &self.top_logprobs)
+            .field("context_limit", &self.context_limit)
+            .field("history_policy", &self.history_policy)
+            .field("redactor", &self.redactor.is_some())
+            .field("headers", &self.headers)
+            .field("user", &self.user)
+            .field("store", &self.store)
+            .field("previous_response_id", &self.previous_response_id)
+            .field("audio_output", &self.audio_output);
+        #[cfg(feature = "cache")]
+        debug.field("cache", &self.cache);
+        debug.finish()
+    }
+}
+
+impl LanguageModelOptionsBuilder {
+    fn validate(&self) -> Result<()> {
+        validate_penalties(
+            self.presence_penalty.flatten(),
+            self.frequency_penalty.flatten(),
+        )
+    }
+}
+
+/// Returns an `Error::InvalidInput` if a penalty is outside `PENALTY_RANGE`.
+pub(crate) fn validate_penalties(
+    presence_penalty: Option<f32>,
+    frequency_penalty: Option<f32>,
+) -> Result<()> {
+    let penalties = [
+        ("presence_penalty", presence_penalty),
+        ("frequency_penalty", frequency_penalty),
+    ];
+    for (name, penalty) in penalties {
+        if let Some(penalty) = penalty
+            && !PENALTY_RANGE.contains(&penalty)
+        {
+            return Err(Error::InvalidInput(format!(
+                "{name} must be between {} and {}, got {penalty}",
+                PENALTY_RANGE.start(),
+                PENALTY_RANGE.end()
+            )));
+        }
     }
+    Ok(())
 }
 
 impl LanguageModelOptions {
@@ -237,13 +533,136 @@ impl LanguageModelOptions {
         LanguageModelOptionsBuilder::default()
     }
 
+    /// Returns the whole conversation in order, across all steps, e.g. to
+    /// send it again or render it.
     pub fn messages(&self) -> Vec<Message> {
         self.messages.iter().map(|m| m.message.clone()).collect()
     }
 
+    /// Returns true if any user message has audio parts.
+    #[cfg(any(feature = "openai", feature = "perplexity", feature = "huggingface"))]
+    pub(crate) fn has_audio_input(&self) -> bool {
+        self.messages.iter().any(|m| match &m.message {
+            Message::User(u) => u
+                .parts
+                .iter()
+                .any(|part| matches!(part, crate::core::messages::ContentPart::Audio { .. })),
+            _ => false,
+        })
+    }
+
+    /// Returns the retry policy for calls to the model, with `max_retries`
+    /// applied if set.
+    pub(crate) fn resolved_retry_policy(&self) -> RetryPolicy {
+        let mut policy = self.retry_policy.clone().unwrap_or_default();
+        if let Some(max_retries) = self.max_retries {
+            policy.max_retries = max_retries;
+        }
+        policy
+    }
+
+    /// Returns a copy of the options with the `redactor` applied to the system
+    /// prompt and to the system, user and tool result messages.
+    pub(crate) fn redacted(&self) -> LanguageModelOptions {
+        let mut options = self.clone();
+        let Some(redact) = self.redactor.as_deref() else {
+            return options;
+        };
+
+        options.system = options.system.map(|s| redact(&s));
+        for tagged in options.messages.iter_mut() {
+            match tagged.message {
+                Message::System(ref mut s) => s.content = redact(&s.content),
+                Message::User(ref mut u) => u.content = redact(&u.content),
+                Message::Developer(ref mut d) => *d = redact(d),
+                Message::Tool(ref mut info) => {
+                    match info.output {
+                        Ok(ref mut output) => redact_json(output, redact),
+                        Err(ref mut error) => redact_error(error, redact),
+                    }
+                    for part in info.parts.iter_mut() {
+                        if let ToolResultPart::Text(text) = part {
+                            *text = redact(text);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        options
+    }
+
+    /// Returns a copy of the options with the `history_policy` applied to the
+    /// messages, so the request fits `context_limit` where possible.
+    pub(crate) fn fit_history(&self) -> LanguageModelOptions {
+        let mut options = self.clone();
+        options.messages = utils::fit_history(self, options.messages);
+        options
+    }
+
+    /// Returns an error if `context_limit` is set and the estimated size of
+    /// the request exceeds it.
+    pub(crate) fn check_context_limit(&self) -> Result<()> {
+        match self.context_limit {
+            Some(limit) if !tokens::fits_context(self, limit) => Err(Error::InvalidInput(format!(
+                "Estimated request size of {} tokens exceeds the context limit of {limit} tokens",
+                tokens::count_tokens(self)
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns true if `tool_loop_threshold` is set and the last tool call
+    /// repeats the calls before it, same tool and input, more often than it.
+    pub(crate) fn tool_loop_detected(&self) -> bool {
+        let Some(threshold) = self.tool_loop_threshold else {
+            return false;
+        };
+
+        let calls: Vec<&ToolCallInfo> = self
+            .messages
+            .iter()
+            .filter_map(|tagged| tagged.message.tool_call())
+            .collect();
+        let Some(last) = calls.last() else {
+            return false;
+        };
+
+        let repeats = calls
+            .iter()
+            .rev()
+            .take_while(|call| call.tool.name == last.tool.name && call.input == last.input)
+            .count();
+        repeats > threshold
+    }
+
+    /// Logs the name and input schema of every tool sent with the current
+    /// step at debug level, under the `aisdk::tools` target.
+    pub(crate) fn log_tool_schemas(&self) {
+        let Some(tools) = &self.tools else {
+            return;
+        };
+        if !log::log_enabled!(target: "aisdk::tools", log::Level::Debug) {
+            return;
+        }
+
+        let schemas: Vec<serde_json::Value> = tools
+            .describe_all()
+            .into_iter()
+            .map(|tool| serde_json::json!({ "name": tool.name, "input_schema": tool.input_schema }))
+            .collect();
+        log::debug!(
+            target: "aisdk::tools",
+            "step {} sends tools: {}",
+            self.current_step_id,
+            serde_json::Value::Array(schemas)
+        );
+    }
+
     /// Calls the requested tools, adds tool ouput message to messages,
     /// and decrements the step count. uses the previous step id for tagging
-    /// the created messages.
+    /// the created messages. If the tool is marked `stop_after`, its output
+    /// is also added as the assistant's final text and the stop reason is set.
     pub(crate) async fn handle_tool_call(&mut self, input: &ToolCallInfo) -> &mut Self {
         if let Some(tools) = &self.tools {
             let tool_result_task = tools.execute(input.clone()).await;
@@ -255,11 +674,16 @@ impl LanguageModelOptions {
             let mut tool_output_infos = Vec::new();
 
             let mut tool_output_info = ToolResultInfo::new(&input.tool.name);
-            let output = match tool_result {
-                Ok(result) => serde_json::Value::String(result),
-                Err(err) => serde_json::Value::String(format!("Error: {}", err)),
+            let mut final_text = None;
+            match tool_result {
+                Ok(parts) => {
+                    if tools.stops_after(&input.tool.name) {
+                        final_text = Some(ToolResultPart::text_of(&parts));
+                    }
+                    tool_output_info.output_parts(parts);
+                }
+                Err(err) => tool_output_info.error(err.to_string()),
             };
-            tool_output_info.output(output);
             tool_output_info.id(&input.tool.id);
             tool_output_infos.push(tool_output_info.clone());
 
@@ -269,6 +693,15 @@ impl LanguageModelOptions {
                 Message::Tool(tool_output_info),
             ));
 
+            // a final tool's output becomes the response text
+            if let Some(text) = final_text {
+                self.messages.push(TaggedMessage::new(
+                    self.current_step_id,
+                    Message::Assistant(AssistantMessage::new(text.into(), None)),
+                ));
+                self.stop_reason = Some(StopReason::Finish);
+            }
+
             self
         } else {
             self
@@ -348,6 +781,30 @@ impl LanguageModelOptions {
         }
     }
 
+    /// Returns the explanation if the model refused to answer in its last
+    /// message.
+    pub fn refusal(&self) -> Option<String> {
+        match self.messages.last().map(|m| &m.message) {
+            Some(Message::Assistant(AssistantMessage {
+                content: LanguageModelResponseContentType::Refusal(refusal),
+                ..
+            })) => Some(refusal.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the last assistant text of the conversation, even when tool
+    /// calls or results follow it, unlike `text`.
+    pub fn final_text(&self) -> Option<String> {
+        self.messages
+            .iter()
+            .rev()
+            .find_map(|tagged| match &tagged.message {
+                Message::Assistant(assistant) => assistant.text().map(str::to_string),
+                _ => None,
+            })
+    }
+
     pub fn tool_results(&self) -> Option<Vec<ToolResultInfo>> {
         self.messages.as_slice().extract_tool_results()
     }
@@ -356,11 +813,67 @@ impl LanguageModelOptions {
         self.messages.as_slice().extract_tool_calls()
     }
 
+    pub fn builtin_tool_calls(&self) -> Option<Vec<BuiltinToolCallInfo>> {
+        self.messages.as_slice().extract_builtin_tool_calls()
+    }
+
+    /// Returns the number of steps the model responded in. The initial
+    /// messages, e.g. the prompt, are not counted.
+    pub fn step_count(&self) -> usize {
+        self.messages
+            .iter()
+            .filter(|t| matches!(t.message, Message::Assistant(_)))
+            .map(|t| t.step_id)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Returns the number of tool calls across all steps.
+    pub fn tool_call_count(&self) -> usize {
+        self.messages
+            .iter()
+            .filter(|t| t.message.tool_call().is_some())
+            .count()
+    }
+
+    /// Returns the names of the tools called, in the order of their first call.
+    pub fn distinct_tools_used(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for call in self.messages.iter().filter_map(|t| t.message.tool_call()) {
+            if !names.contains(&call.tool.name) {
+                names.push(call.tool.name.clone());
+            }
+        }
+        names
+    }
+
     pub fn stop_reason(&self) -> Option<StopReason> {
         self.stop_reason.clone()
     }
 }
 
+/// Applies `redact` to every string in `value`.
+fn redact_json(value: &mut serde_json::Value, redact: &(dyn Fn(&str) -> String + Send + Sync)) {
+    match value {
+        serde_json::Value::String(s) => *s = redact(s),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|v| redact_json(v, redact)),
+        serde_json::Value::Object(map) => map.values_mut().for_each(|v| redact_json(v, redact)),
+        _ => {}
+    }
+}
+
+/// Applies `redact` to the message of a failed tool result.
+fn redact_error(error: &mut Error, redact: &(dyn Fn(&str) -> String + Send + Sync)) {
+    match error {
+        Error::ToolCallError(message)
+        | Error::ApiError(message)
+        | Error::InvalidInput(message)
+        | Error::Timeout(message)
+        | Error::Other(message) => *message = redact(message),
+        _ => *error = Error::Other(redact(&error.to_string())),
+    }
+}
+
 // ============================================================================
 // Section: response types
 // ============================================================================
@@ -369,7 +882,18 @@ impl LanguageModelOptions {
 pub enum LanguageModelResponseContentType {
     Text(String),
     ToolCall(ToolCallInfo),
+    /// A tool call the provider executed itself, never run by the generate loop.
+    BuiltinToolCall(BuiltinToolCallInfo),
     Reasoning(String),
+    /// The model declined to answer, with its explanation.
+    Refusal(String),
+    /// Base64 encoded audio generated by the model, with its transcript if
+    /// the provider returns one.
+    Audio {
+        media_type: String,
+        data: String,
+        transcript: Option<String>,
+    },
     NotSupported(String),
 }
 
@@ -391,7 +915,27 @@ impl LanguageModelResponseContentType {
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq)]
+/// Renders the content as a readable line, e.g. `get_weather({"city":"Paris"})`
+/// for a tool call.
+impl std::fmt::Display for LanguageModelResponseContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{text}"),
+            Self::ToolCall(call) => write!(f, "{}({})", call.tool.name, call.input),
+            Self::BuiltinToolCall(call) => {
+                write!(f, "{}({}) [builtin]", call.tool.name, call.output)
+            }
+            Self::Reasoning(reasoning) => write!(f, "[reasoning] {reasoning}"),
+            Self::Refusal(refusal) => write!(f, "[refusal] {refusal}"),
+            Self::Audio { transcript, .. } => {
+                write!(f, "[audio] {}", transcript.as_deref().unwrap_or_default())
+            }
+            Self::NotSupported(content) => write!(f, "[not supported] {content}"),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Usage {
     pub input_tokens: Option<usize>,
     pub output_tokens: Option<usize>,
@@ -400,6 +944,38 @@ pub struct Usage {
     pub cached_tokens: Option<usize>,
 }
 
+impl Usage {
+    /// Returns the cost of the tokens given per token rates, counting unset
+    /// token counts as zero.
+    ///
+    /// Reasoning tokens are part of the output tokens and cached tokens part
+    /// of the input tokens, they are charged at their own rate if one is
+    /// given and at the output or input rate otherwise.
+    pub fn cost(
+        &self,
+        input_rate: f64,
+        output_rate: f64,
+        reasoning_rate: Option<f64>,
+        cached_rate: Option<f64>,
+    ) -> f64 {
+        let input = self.input_tokens.unwrap_or(0);
+        let output = self.output_tokens.unwrap_or(0);
+        let reasoning = self.reasoning_tokens.unwrap_or(0).min(output);
+        let cached = self.cached_tokens.unwrap_or(0).min(input);
+
+        let (input, cached_cost) = match cached_rate {
+            Some(rate) => (input - cached, cached as f64 * rate),
+            None => (input, 0.0),
+        };
+        let (output, reasoning_cost) = match reasoning_rate {
+            Some(rate) => (output - reasoning, reasoning as f64 * rate),
+            None => (output, 0.0),
+        };
+
+        input as f64 * input_rate + output as f64 * output_rate + cached_cost + reasoning_cost
+    }
+}
+
 impl Add for &Usage {
     type Output = Usage;
 
@@ -414,6 +990,60 @@ impl Add for &Usage {
     }
 }
 
+/// Log probability of a single output token.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct TokenLogprob {
+    /// The output token.
+    pub token: String,
+    /// The log probability of the token.
+    pub logprob: f64,
+    /// The most likely alternatives at this token position.
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// An alternative token and its log probability.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
+/// An option a provider dropped or approximated when converting a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// The name of the option, e.g. `top_k`.
+    pub option: String,
+    pub reason: String,
+}
+
+impl Warning {
+    pub fn new(option: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            option: option.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// A warning for an option the provider ignores.
+    pub fn unsupported(option: impl Into<String>, provider: &str) -> Self {
+        let option = option.into();
+        let reason = format!("{provider} does not support `{option}`, it will be ignored");
+        Self { option, reason }
+    }
+
+    /// Logs the warning at the `warn` level.
+    pub fn log(&self) {
+        log::warn!("NotSupported: {}", self.reason);
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
 /// Response from a language model.
 #[derive(Debug, Clone)]
 pub struct LanguageModelResponse {
@@ -422,6 +1052,46 @@ pub struct LanguageModelResponse {
 
     /// Usage information
     pub usage: Option<Usage>,
+
+    /// The id the provider assigned to the response, if any, e.g. to pass as
+    /// `previous_response_id` of the next request.
+    pub id: Option<String>,
+
+    /// Token log probabilities. Empty unless `logprobs` was requested
+    /// and the provider supports it.
+    pub logprobs: Vec<TokenLogprob>,
+
+    /// Source urls the response is grounded on, for providers that
+    /// search the web (e.g. Perplexity).
+    pub citations: Vec<String>,
+
+    /// Options the provider could not honor.
+    pub warnings: Vec<Warning>,
+
+    /// Why the provider stopped before completing the response, e.g.
+    /// `max_output_tokens`. `None` for complete responses.
+    pub incomplete: Option<String>,
+
+    /// The model that served the response when the model called routes
+    /// requests to other models, e.g. a `FallbackModel`.
+    pub served_by: Option<ServedBy>,
+}
+
+/// The provider and model that served a response, see
+/// `LanguageModelResponse::served_by`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServedBy {
+    pub provider_name: String,
+    pub model_name: String,
+}
+
+impl ServedBy {
+    pub fn new(model: &dyn LanguageModel) -> Self {
+        Self {
+            provider_name: model.provider_name().to_string(),
+            model_name: model.model_name(),
+        }
+    }
 }
 
 impl LanguageModelResponse {
@@ -430,6 +1100,12 @@ impl LanguageModelResponse {
         Self {
             contents: vec![LanguageModelResponseContentType::new(text.into())],
             usage: None,
+            id: None,
+            logprobs: Vec::new(),
+            citations: Vec::new(),
+            warnings: Vec::new(),
+            incomplete: None,
+            served_by: None,
         }
     }
 }
@@ -441,8 +1117,16 @@ pub enum LanguageModelStreamChunkType {
     Start,
     /// Text chunk
     Text(String),
+    /// Reasoning chunk of reasoning models, e.g. a reasoning summary, kept
+    /// apart from the answer text.
+    Reasoning(String),
+    /// Text chunk of another completion when more than one is streamed,
+    /// `index` starting at 1. The first completion streams as `Text`.
+    CandidateText { index: usize, text: String },
     /// Tool call argument chunk
     ToolCall(String),
+    /// Base64 encoded audio chunk, the transcript streams as `Text`.
+    Audio(String),
     /// The model has stopped generating text successfully.
     End(AssistantMessage),
     /// The model has failed to generate text. error specified by
@@ -450,6 +1134,10 @@ pub enum LanguageModelStreamChunkType {
     Failed(String),
     /// The model finsished generating text with incomplete response.
     Incomplete(String),
+    /// Running token usage of the current step, sent as the provider stream
+    /// is read. `approximate` is set for estimates made from the streamed
+    /// text when the provider does not report usage before the step ends.
+    UsageDelta { usage: Usage, approximate: bool },
     /// Return this for unimplemented features for a specific model.
     NotSupported(String),
 }
@@ -466,26 +1154,81 @@ pub(crate) type ProviderStream =
 
 // A mapping of `ProviderStream` to a channel like stream.
 pub struct LanguageModelStream {
-    receiver: Receiver<LanguageModelStreamChunkType>,
+    receiver: UnboundedReceiver<LanguageModelStreamChunkType>,
+    stopped: Arc<AtomicBool>,
+    finished: bool,
 }
 
 impl LanguageModelStream {
     // Creates a new MpmcStream with an associated Sender
-    pub fn new() -> (Sender<LanguageModelStreamChunkType>, LanguageModelStream) {
-        let (tx, rx) = mpsc::channel();
-        (tx, LanguageModelStream { receiver: rx })
+    pub fn new() -> (
+        UnboundedSender<LanguageModelStreamChunkType>,
+        LanguageModelStream,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (
+            tx,
+            LanguageModelStream {
+                receiver: rx,
+                stopped: Arc::new(AtomicBool::new(false)),
+                finished: false,
+            },
+        )
+    }
+
+    /// Returns a handle that can stop the stream from another task.
+    pub fn handle(&self) -> StreamHandle {
+        StreamHandle {
+            stopped: self.stopped.clone(),
+        }
+    }
+
+    /// Ends the stream right away, discarding the chunks not read yet.
+    pub(crate) fn close(&mut self) {
+        self.finished = true;
+        self.stopped.store(true, Ordering::SeqCst);
+        self.receiver.close();
+        while self.receiver.try_recv().is_ok() {}
     }
 }
 
 impl Stream for LanguageModelStream {
     type Item = LanguageModelStreamChunkType;
 
-    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.receiver.try_recv() {
-            Ok(item) => Poll::Ready(Some(item)),
-            Err(mpsc::TryRecvError::Empty) => Poll::Pending,
-            Err(mpsc::TryRecvError::Disconnected) => Poll::Ready(None),
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(None);
         }
+
+        if this.stopped.load(Ordering::SeqCst) {
+            this.finished = true;
+            return Poll::Ready(Some(LanguageModelStreamChunkType::Incomplete(
+                "Stopped by handle".to_string(),
+            )));
+        }
+
+        this.receiver.poll_recv(cx)
+    }
+}
+
+/// Stops a `LanguageModelStream`. Cloneable and safe to use from any task.
+#[derive(Debug, Clone)]
+pub struct StreamHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl StreamHandle {
+    /// Ends the stream after the chunk currently being read. The stream then
+    /// yields a final `Incomplete` chunk and no further chunks, and the task
+    /// generating the response stops reading from the provider.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if `stop` has been called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
     }
 }
 
@@ -496,8 +1239,12 @@ pub enum StopReason {
     Finish,
     // Provider specific reasons like timeout, rate limit etc
     Provider(String),
-    // The user has explicitly provided a hook causing to stop
-    Hook,
+    // The user has explicitly provided a hook causing to stop, with the
+    // reason the hook returned
+    Hook(String),
+    // The provider stopped before completing the response, e.g. when
+    // reaching `max_output_tokens`
+    Incomplete(String),
     // Problematic errors. Providers specific errors can be accessed
     // through `Error::ProviderError`
     Error(Error),
@@ -505,6 +1252,25 @@ pub enum StopReason {
     Other(String),
 }
 
+/// The voice and format of generated audio, see
+/// `LanguageModelOptions::audio_output`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AudioOutput {
+    /// The voice to speak with, e.g. `alloy`.
+    pub voice: String,
+    /// The audio format, e.g. `wav` or `mp3`.
+    pub format: String,
+}
+
+impl AudioOutput {
+    pub fn new(voice: impl Into<String>, format: impl Into<String>) -> Self {
+        Self {
+            voice: voice.into(),
+            format: format.into(),
+        }
+    }
+}
+
 // will be converted to the appropriate level of reasoning
 // for a language model
 #[derive(Debug, Clone, Copy, Default)]
@@ -515,10 +1281,152 @@ pub enum ReasoningEffort {
     High,
 }
 
+/// Named pairs of `temperature` and `top_p` for common use cases, in the
+/// same ×100 scale as the options themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingPreset {
+    /// Deterministic output, e.g. extraction or classification.
+    Precise,
+    /// General purpose chat.
+    Balanced,
+    /// Varied output, e.g. brainstorming or fiction.
+    Creative,
+}
+
+impl SamplingPreset {
+    /// Returns the `temperature` and `top_p` of the preset.
+    pub fn values(&self) -> (u32, Option<u32>) {
+        match self {
+            SamplingPreset::Precise => (0, None),
+            SamplingPreset::Balanced => (70, Some(90)),
+            SamplingPreset::Creative => (120, Some(95)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_options_builder_accepts_penalty_in_range() {
+        let options = LanguageModelOptions::builder()
+            .frequency_penalty(1.5)
+            .presence_penalty(-2.0)
+            .build()
+            .unwrap();
+        assert_eq!(options.frequency_penalty, Some(1.5));
+        assert_eq!(options.presence_penalty, Some(-2.0));
+    }
+
+    fn redact_tool_result(info: ToolResultInfo) -> ToolResultInfo {
+        let options = LanguageModelOptions {
+            redactor: Some(Arc::new(|text: &str| text.replace("bob", "[name]"))),
+            messages: vec![TaggedMessage::new(1, Message::Tool(info))],
+            ..Default::default()
+        };
+        match options.redacted().messages.remove(0).message {
+            Message::Tool(info) => info,
+            other => panic!("expected a tool result, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_redacted_masks_tool_result_parts() {
+        let info = ToolResultInfo {
+            output: Ok(serde_json::json!("found bob")),
+            parts: vec![
+                ToolResultPart::Text("found bob".to_string()),
+                ToolResultPart::image("image/png", "bob="),
+            ],
+            ..Default::default()
+        };
+
+        let redacted = redact_tool_result(info);
+
+        assert_eq!(redacted.output.unwrap(), serde_json::json!("found [name]"));
+        assert_eq!(
+            redacted.parts,
+            vec![
+                ToolResultPart::Text("found [name]".to_string()),
+                ToolResultPart::image("image/png", "bob="),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redacted_masks_tool_errors() {
+        let failed = ToolResultInfo {
+            output: Err(Error::ToolCallError("no access for bob".to_string())),
+            is_error: true,
+            ..Default::default()
+        };
+        let mut reported = ToolResultInfo::default();
+        reported.error("no access for bob");
+
+        assert_eq!(
+            redact_tool_result(failed).output,
+            Err(Error::ToolCallError("no access for [name]".to_string()))
+        );
+        assert_eq!(
+            redact_tool_result(reported).error_message().unwrap(),
+            "no access for [name]"
+        );
+    }
+
+    #[test]
+    fn test_options_builder_rejects_penalty_out_of_range() {
+        let result = LanguageModelOptions::builder()
+            .frequency_penalty(2.5)
+            .build();
+        assert!(matches!(result, Err(Error::InvalidInput(e)) if e.contains("frequency_penalty")));
+    }
+
+    #[test]
+    fn test_request_builder_rejects_penalty_out_of_range() {
+        let request = request::LanguageModelRequest::builder()
+            .model(crate::test_utils::MockLanguageModel::new())
+            .prompt("hi")
+            .frequency_penalty(3.0)
+            .presence_penalty(-0.5)
+            .build();
+        let expected = LanguageModelOptions::builder()
+            .frequency_penalty(3.0)
+            .build()
+            .unwrap_err();
+
+        assert_eq!(request.resolve_options().unwrap_err(), expected);
+    }
+
+    #[test]
+    fn test_request_builder_sampling_presets() {
+        let sampled = |preset| {
+            let request = request::LanguageModelRequest::builder()
+                .model(crate::test_utils::MockLanguageModel::new())
+                .prompt("hi")
+                .sampling(preset)
+                .build();
+            (request.temperature, request.top_p)
+        };
+
+        assert_eq!(sampled(SamplingPreset::Precise), (Some(0), None));
+        assert_eq!(sampled(SamplingPreset::Balanced), (Some(70), Some(90)));
+        assert_eq!(sampled(SamplingPreset::Creative), (Some(120), Some(95)));
+    }
+
+    #[test]
+    fn test_request_builder_explicit_sampling_overrides_preset() {
+        let request = request::LanguageModelRequest::builder()
+            .model(crate::test_utils::MockLanguageModel::new())
+            .prompt("hi")
+            .temperature(20_u32)
+            .sampling(SamplingPreset::Creative)
+            .top_p(50_u32)
+            .build();
+        assert_eq!(request.temperature, Some(20));
+        assert_eq!(request.top_p, Some(50));
+    }
+
     #[test]
     fn test_usage_add_both_some() {
         let u1 = Usage {
@@ -929,4 +1837,80 @@ mod tests {
             assert_eq!(result.tool.name, format!("tool{}", i));
         }
     }
+
+    fn options_with(messages: Vec<Message>) -> LanguageModelOptions {
+        LanguageModelOptions {
+            messages: messages
+                .into_iter()
+                .map(|m| TaggedMessage::new(1, m))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_final_text_skips_trailing_tool_messages() {
+        let mut call = ToolCallInfo::new("search");
+        call.id("call_1");
+        let options = options_with(vec![
+            Message::User("hi".to_string().into()),
+            Message::Assistant(AssistantMessage::new(
+                "Let me search".to_string().into(),
+                None,
+            )),
+            Message::Assistant(AssistantMessage::new(
+                LanguageModelResponseContentType::ToolCall(call),
+                None,
+            )),
+            Message::Tool(ToolResultInfo::new("search")),
+        ]);
+
+        assert_eq!(options.text(), None);
+        assert_eq!(options.final_text(), Some("Let me search".to_string()));
+    }
+
+    #[test]
+    fn test_final_text_ending_in_assistant_text() {
+        let options = options_with(vec![
+            Message::User("hi".to_string().into()),
+            Message::Tool(ToolResultInfo::new("search")),
+            Message::Assistant(AssistantMessage::new("Answer".to_string().into(), None)),
+        ]);
+
+        assert_eq!(options.text(), Some("Answer".to_string()));
+        assert_eq!(options.final_text(), Some("Answer".to_string()));
+        assert_eq!(LanguageModelOptions::default().final_text(), None);
+    }
+
+    #[test]
+    fn test_usage_cost_with_all_counts() {
+        let usage = Usage {
+            input_tokens: Some(1000),
+            output_tokens: Some(500),
+            total_tokens: Some(1500),
+            reasoning_tokens: Some(200),
+            cached_tokens: Some(400),
+        };
+
+        // 600 * 2 + 400 * 1 + 300 * 8 + 200 * 10
+        assert_eq!(usage.cost(2.0, 8.0, Some(10.0), Some(1.0)), 6000.0);
+        // reasoning and cached tokens at the output and input rates
+        assert_eq!(usage.cost(2.0, 8.0, None, None), 1000.0 * 2.0 + 500.0 * 8.0);
+    }
+
+    #[test]
+    fn test_usage_cost_with_missing_counts() {
+        let usage = Usage {
+            input_tokens: Some(1000),
+            output_tokens: None,
+            cached_tokens: Some(250),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            usage.cost(2.0, 8.0, Some(10.0), Some(0.5)),
+            750.0 * 2.0 + 250.0 * 0.5
+        );
+        assert_eq!(Usage::default().cost(2.0, 8.0, Some(10.0), Some(1.0)), 0.0);
+    }
 }
@@ -5,9 +5,15 @@
 //! underlying implementation details of different AI providers, offering a
 //! unified interface for various operations like text generation or streaming.
 
-use crate::core::Message;
-use crate::core::language_model::{LanguageModel, LanguageModelOptions};
+use crate::core::history::HistoryPolicy;
+use crate::core::language_model::{
+    AudioOutput, LanguageModel, LanguageModelOptions, validate_penalties,
+};
+use crate::core::retry::RetryPolicy;
 use crate::core::tools::Tool;
+use crate::core::utils::resolve_message;
+use crate::core::{Message, Role, TaggedMessage};
+use crate::error::{Error, Result};
 use schemars::{JsonSchema, schema_for};
 use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
@@ -16,8 +22,9 @@ use std::sync::Arc;
 /// Options for text generation requests such as `generate_text` and `stream_text`.
 #[derive(Debug, Clone)]
 pub struct LanguageModelRequest<M: LanguageModel> {
-    /// The Language Model to use.
-    pub model: M,
+    /// The Language Model to use, shared with the task generating a
+    /// streamed response.
+    pub model: Arc<M>,
 
     /// The prompt to generate text from.
     /// Only one of prompt or messages should be set.
@@ -31,6 +38,74 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
     pub fn builder() -> LanguageModelRequestBuilder<M> {
         LanguageModelRequestBuilder::default()
     }
+
+    /// Creates a request from already built options, bypassing the staged
+    /// builder. The system prompt and messages are taken from `options`.
+    pub fn from_parts(model: M, options: LanguageModelOptions) -> Self {
+        Self {
+            model: Arc::new(model),
+            prompt: None,
+            options,
+        }
+    }
+
+    /// Returns the options of the request for modification.
+    pub fn options_mut(&mut self) -> &mut LanguageModelOptions {
+        &mut self.options
+    }
+
+    /// Returns the body of the first request `generate_text` would send to
+    /// the provider, without sending it.
+    ///
+    /// Returns an `Error` if the model does not support previews.
+    pub fn preview(&self) -> Result<serde_json::Value> {
+        let mut options = self.resolve_options()?;
+        options.current_step_id += 1;
+        if let Some(hook) = options.prepare_step.clone() {
+            hook(&mut options);
+        }
+
+        self.model.request_body(options.redacted().fit_history())
+    }
+
+    /// Returns the options for the first step, with the prompt and system
+    /// prompt resolved into messages.
+    ///
+    /// Returns an `Error` if there is neither a prompt nor a user message, or
+    /// a penalty is out of range.
+    pub(crate) fn resolve_options(&self) -> Result<LanguageModelOptions> {
+        validate_penalties(
+            self.options.presence_penalty,
+            self.options.frequency_penalty,
+        )?;
+
+        let (system_prompt, messages) = resolve_message(&self.options, &self.prompt);
+
+        if !messages
+            .iter()
+            .any(|m| matches!(m.message, Message::User(ref u) if !u.content.is_empty() || !u.parts.is_empty()))
+        {
+            return Err(Error::InvalidInput(
+                "Request has no prompt or user message to respond to".to_string(),
+            ));
+        }
+
+        // pre-seeded steps are complete, generation continues after them
+        let current_step_id = messages
+            .iter()
+            .map(|m| m.step_id)
+            .max()
+            .unwrap_or_default()
+            .max(self.options.current_step_id);
+
+        Ok(LanguageModelOptions {
+            system: system_prompt,
+            messages,
+            stop_reason: None,
+            current_step_id,
+            ..self.options.clone()
+        })
+    }
 }
 
 impl<M: LanguageModel> Deref for LanguageModelRequest<M> {
@@ -147,6 +222,39 @@ impl<M: LanguageModel> LanguageModelRequestBuilder<M, SystemStage> {
             state: std::marker::PhantomData,
         }
     }
+
+    /// Sets messages tagged with their own step ids, e.g. a history grouped
+    /// into steps by an earlier conversation. `steps()` keeps that grouping
+    /// and generation continues after the last step.
+    pub fn tagged_messages(
+        self,
+        messages: Vec<TaggedMessage>,
+    ) -> LanguageModelRequestBuilder<M, OptionsStage> {
+        LanguageModelRequestBuilder {
+            model: self.model,
+            prompt: self.prompt,
+            options: LanguageModelOptions {
+                messages,
+                ..self.options
+            },
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the messages from `(role, text)` pairs, e.g.
+    /// `.messages_from([(Role::User, "hi"), (Role::Assistant, "hello")])`.
+    pub fn messages_from<I, S>(self, messages: I) -> LanguageModelRequestBuilder<M, OptionsStage>
+    where
+        I: IntoIterator<Item = (Role, S)>,
+        S: Into<String>,
+    {
+        self.messages(
+            messages
+                .into_iter()
+                .map(|(role, text)| Message::from_role(role, text))
+                .collect(),
+        )
+    }
 }
 
 /// ConversationStage Builder
@@ -171,6 +279,39 @@ impl<M: LanguageModel> LanguageModelRequestBuilder<M, ConversationStage> {
             state: std::marker::PhantomData,
         }
     }
+
+    /// Sets messages tagged with their own step ids, e.g. a history grouped
+    /// into steps by an earlier conversation. `steps()` keeps that grouping
+    /// and generation continues after the last step.
+    pub fn tagged_messages(
+        self,
+        messages: Vec<TaggedMessage>,
+    ) -> LanguageModelRequestBuilder<M, OptionsStage> {
+        LanguageModelRequestBuilder {
+            model: self.model,
+            prompt: self.prompt,
+            options: LanguageModelOptions {
+                messages,
+                ..self.options
+            },
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the messages from `(role, text)` pairs, e.g.
+    /// `.messages_from([(Role::User, "hi"), (Role::Assistant, "hello")])`.
+    pub fn messages_from<I, S>(self, messages: I) -> LanguageModelRequestBuilder<M, OptionsStage>
+    where
+        I: IntoIterator<Item = (Role, S)>,
+        S: Into<String>,
+    {
+        self.messages(
+            messages
+                .into_iter()
+                .map(|(role, text)| Message::from_role(role, text))
+                .collect(),
+        )
+    }
 }
 /// OptionsStage Builder
 impl<M: LanguageModel> LanguageModelRequestBuilder<M, OptionsStage> {
@@ -178,6 +319,21 @@ impl<M: LanguageModel> LanguageModelRequestBuilder<M, OptionsStage> {
         self.schema = Some(schema_for!(T));
         self
     }
+    /// Sets whether the provider enforces the schema strictly. Strict mode
+    /// guarantees valid output but rejects some schemas, e.g. OpenAI requires
+    /// every field to be required.
+    pub fn schema_strict(mut self, strict: bool) -> Self {
+        self.schema_strict = Some(strict);
+        self
+    }
+
+    /// Requests valid JSON output without a schema, e.g. to parse with
+    /// `into_schema` into a `serde_json::Value`.
+    pub fn json_mode(mut self, json_mode: bool) -> Self {
+        self.json_mode = Some(json_mode);
+        self
+    }
+
     pub fn seed(mut self, seed: impl Into<u32>) -> Self {
         self.seed = Some(seed.into());
         self
@@ -193,11 +349,27 @@ impl<M: LanguageModel> LanguageModelRequestBuilder<M, OptionsStage> {
         self
     }
 
+    /// Sets `temperature` and `top_p` from a preset. Values set explicitly,
+    /// before or after, take precedence.
+    pub fn sampling(mut self, preset: crate::core::language_model::SamplingPreset) -> Self {
+        let (temperature, top_p) = preset.values();
+        self.temperature.get_or_insert(temperature);
+        if let Some(top_p) = top_p {
+            self.top_p.get_or_insert(top_p);
+        }
+        self
+    }
+
     pub fn top_k(mut self, top_k: impl Into<u32>) -> Self {
         self.top_k = Some(top_k.into());
         self
     }
 
+    pub fn n(mut self, n: impl Into<u32>) -> Self {
+        self.n = Some(n.into());
+        self
+    }
+
     pub fn stop_sequences(mut self, stop_sequences: impl Into<Vec<String>>) -> Self {
         self.stop_sequences = Some(stop_sequences.into());
         self
@@ -208,19 +380,52 @@ impl<M: LanguageModel> LanguageModelRequestBuilder<M, OptionsStage> {
         self
     }
 
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Sets the frequency penalty. Requests with a penalty outside
+    /// `PENALTY_RANGE` fail with an `Error::InvalidInput`.
     pub fn frequency_penalty(mut self, frequency_penalty: impl Into<f32>) -> Self {
         self.frequency_penalty = Some(frequency_penalty.into());
         self
     }
 
+    /// Sets the presence penalty. Requests with a penalty outside
+    /// `PENALTY_RANGE` fail with an `Error::InvalidInput`.
+    pub fn presence_penalty(mut self, presence_penalty: impl Into<f32>) -> Self {
+        self.presence_penalty = Some(presence_penalty.into());
+        self
+    }
+
     pub fn with_tool(mut self, tool: Tool) -> Self {
         self.tools.get_or_insert_default().add_tool(tool);
         self
     }
 
+    /// Stops the tool loop once the same tool call repeats more than
+    /// `threshold` times in a row.
+    pub fn tool_loop_threshold(mut self, threshold: usize) -> Self {
+        self.tool_loop_threshold = Some(threshold);
+        self
+    }
+
     pub fn stop_when<F>(mut self, hook: F) -> Self
     where
         F: Fn(&LanguageModelOptions) -> bool + Send + Sync + 'static,
+    {
+        self.stop_when = Some(Arc::new(move |options| {
+            hook(options).then(|| "Stopped by hook".to_string())
+        }));
+        self
+    }
+
+    /// Like `stop_when`, the hook returns the reason to stop, recorded as
+    /// `StopReason::Hook(reason)`, or `None` to continue.
+    pub fn stop_when_with_reason<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&LanguageModelOptions) -> Option<String> + Send + Sync + 'static,
     {
         self.stop_when = Some(Arc::new(hook));
         self
@@ -242,6 +447,14 @@ impl<M: LanguageModel> LanguageModelRequestBuilder<M, OptionsStage> {
         self
     }
 
+    pub fn redactor<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.redactor = Some(Arc::new(hook));
+        self
+    }
+
     pub fn reasoning_effort(
         mut self,
         reasoning_effort: impl Into<crate::core::language_model::ReasoningEffort>,
@@ -250,13 +463,71 @@ impl<M: LanguageModel> LanguageModelRequestBuilder<M, OptionsStage> {
         self
     }
 
+    pub fn logprobs(mut self, logprobs: bool) -> Self {
+        self.logprobs = Some(logprobs);
+        self
+    }
+
+    pub fn top_logprobs(mut self, top_logprobs: impl Into<u8>) -> Self {
+        self.top_logprobs = Some(top_logprobs.into());
+        self
+    }
+
+    pub fn context_limit(mut self, context_limit: usize) -> Self {
+        self.context_limit = Some(context_limit);
+        self
+    }
+
+    pub fn history_policy(mut self, history_policy: HistoryPolicy) -> Self {
+        self.history_policy = Some(history_policy);
+        self
+    }
+
+    /// Sets a stable identifier of the end user for abuse monitoring.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Sets whether the provider stores the response for later retrieval.
+    pub fn store(mut self, store: bool) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Continues the conversation from a response stored by the provider.
+    pub fn previous_response_id(mut self, id: impl Into<String>) -> Self {
+        self.previous_response_id = Some(id.into());
+        self
+    }
+
+    /// Requests audio along with the text, e.g. `audio_output("alloy", "wav")`.
+    /// Providers without audio output warn and return text only.
+    pub fn audio_output(mut self, voice: impl Into<String>, format: impl Into<String>) -> Self {
+        self.audio_output = Some(AudioOutput::new(voice, format));
+        self
+    }
+
+    /// Sets the cache consulted before calling the model in `generate_text`.
+    #[cfg(feature = "cache")]
+    pub fn cache(mut self, cache: crate::core::cache::ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Adds an http header sent with every request to the model.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
     pub fn build(self) -> LanguageModelRequest<M> {
         let model = self
             .model
             .unwrap_or_else(|| unreachable!("Model must be set"));
 
         LanguageModelRequest {
-            model,
+            model: Arc::new(model),
             prompt: self.prompt,
             options: self.options,
         }
@@ -0,0 +1,150 @@
+//! A language model retrying failed requests of another model.
+
+use crate::core::language_model::{
+    Capabilities, LanguageModel, LanguageModelOptions, LanguageModelResponse, ProviderStream,
+};
+use crate::core::retry::RetryPolicy;
+use crate::error::Result;
+use async_trait::async_trait;
+
+/// Wraps a model, retrying its requests with backoff according to a
+/// `RetryPolicy` (retryable errors, up to two retries by default).
+///
+/// The retries of a request happen on top of the `retry_policy` of the
+/// request itself, set it to `RetryPolicy::none()` to only retry here.
+#[derive(Debug)]
+pub struct RetryModel<M: LanguageModel> {
+    model: M,
+    policy: RetryPolicy,
+}
+
+impl<M: LanguageModel> RetryModel<M> {
+    /// Creates a model retrying requests of `model` with the default policy.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets the policy deciding which errors are retried and how often.
+    pub fn policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the wrapped model.
+    pub fn inner(&self) -> &M {
+        &self.model
+    }
+
+    async fn sleep_before_retry(&self, attempt: u32, error: &crate::error::Error) {
+        let delay = self.policy.delay(attempt);
+        log::warn!(
+            "{}/{} failed, retrying in {delay:?}: {error}",
+            self.model.provider_name(),
+            self.model.model_name()
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[async_trait]
+impl<M: LanguageModel> LanguageModel for RetryModel<M> {
+    fn provider_name(&self) -> &str {
+        self.model.provider_name()
+    }
+
+    fn model_name(&self) -> String {
+        self.model.model_name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.model.capabilities()
+    }
+
+    fn settings_key(&self) -> String {
+        self.model.settings_key()
+    }
+
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        self.model.request_body(options)
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        let mut attempt = 0;
+        loop {
+            match self.model.generate_text(options.clone()).await {
+                Err(e) if self.policy.should_retry(&e, attempt) => {
+                    self.sleep_before_retry(attempt, &e).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Retries failures to open the stream, errors once streaming has
+    /// started are not retried.
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        let mut attempt = 0;
+        loop {
+            match self.model.stream_text(options.clone()).await {
+                Err(e) if self.policy.should_retry(&e, attempt) => {
+                    self.sleep_before_retry(attempt, &e).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::language_model::LanguageModelResponseContentType;
+    use crate::error::Error;
+    use crate::test_utils::MockLanguageModel;
+    use std::time::Duration;
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_succeeds_after_retries() {
+        let flaky = MockLanguageModel::new()
+            .respond(Err(Error::Timeout("first".to_string())))
+            .respond(Err(Error::Timeout("second".to_string())))
+            .respond_text("hello");
+
+        let model = RetryModel::new(flaky.clone()).policy(fast_policy());
+        let response = model
+            .generate_text(LanguageModelOptions::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            response.contents.as_slice(),
+            [LanguageModelResponseContentType::Text(text)] if text == "hello"
+        ));
+        assert_eq!(flaky.calls().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_other_errors() {
+        let flaky = MockLanguageModel::new()
+            .respond(Err(Error::InvalidInput("bad".to_string())))
+            .respond_text("hello");
+
+        let model = RetryModel::new(flaky.clone()).policy(fast_policy());
+        let result = model.generate_text(LanguageModelOptions::default()).await;
+
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        assert_eq!(flaky.calls().len(), 1);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::core::language_model::{
+    LanguageModel, LanguageModelOptions, request::LanguageModelRequest, stream_text::StreamOutcome,
+};
+use crate::error::{Error, Result};
+use futures::{Stream, StreamExt};
+use schemars::{JsonSchema, schema_for};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+
+impl<M: LanguageModel> LanguageModelRequest<M> {
+    /// Streams a structured object of type `T` using a specified language model.
+    ///
+    /// The schema of `T` is sent to the model and the streamed text is parsed
+    /// after each delta, yielding partial snapshots of the object followed by
+    /// the final, fully deserialized `T`.
+    ///
+    /// Returns an `Error` if the underlying model fails to generate a response.
+    pub async fn stream_object<T>(&mut self) -> Result<StreamObjectResponse<T>>
+    where
+        M: 'static,
+        T: JsonSchema + DeserializeOwned + Send + 'static,
+    {
+        self.options.schema = Some(schema_for!(T));
+
+        let response = self.stream_text().await?;
+        let (options, outcome) = (response.options.clone(), response.outcome.clone());
+        let deltas = Box::pin(response.text_stream());
+
+        let stream = futures::stream::unfold(
+            (deltas, String::new(), None::<Value>, false),
+            |(mut deltas, mut buffer, mut last, done)| async move {
+                if done {
+                    return None;
+                }
+
+                loop {
+                    match deltas.next().await {
+                        Some(Ok(delta)) => {
+                            buffer.push_str(&delta);
+                            if let Some(value) = parse_partial_json(&buffer)
+                                && last.as_ref() != Some(&value)
+                            {
+                                last = Some(value.clone());
+                                return Some((
+                                    Ok(ObjectStreamChunk::Partial(value)),
+                                    (deltas, buffer, last, false),
+                                ));
+                            }
+                        }
+                        Some(Err(e)) => return Some((Err(e), (deltas, buffer, last, true))),
+                        None => {
+                            let object = serde_json::from_str::<T>(&buffer)
+                                .map(ObjectStreamChunk::Object)
+                                .map_err(|e| Error::Schema {
+                                    message: e.to_string(),
+                                    raw_text: buffer.clone(),
+                                });
+                            return Some((object, (deltas, buffer, last, true)));
+                        }
+                    }
+                }
+            },
+        );
+
+        Ok(StreamObjectResponse {
+            stream: Box::pin(stream),
+            options,
+            outcome,
+        })
+    }
+}
+
+// ============================================================================
+// Section: response types
+// ============================================================================
+
+/// A chunk of a streamed object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectStreamChunk<T> {
+    /// A snapshot of the object parsed from the text received so far.
+    /// Fields that have not been streamed yet are missing and strings
+    /// may be cut short.
+    Partial(Value),
+    /// The complete object, deserialized into `T`.
+    Object(T),
+}
+
+// Response from a stream call on `StreamObject`.
+pub struct StreamObjectResponse<T> {
+    /// A stream of partial snapshots followed by the final object.
+    pub stream: Pin<Box<dyn Stream<Item = Result<ObjectStreamChunk<T>>> + Send>>,
+    /// The options the stream started with, until the outcome is published.
+    options: LanguageModelOptions,
+    /// The state of the response once generating it finished.
+    outcome: Arc<OnceLock<StreamOutcome>>,
+}
+
+impl<T> Deref for StreamObjectResponse<T> {
+    type Target = LanguageModelOptions;
+
+    fn deref(&self) -> &Self::Target {
+        self.outcome.get().map_or(&self.options, |o| &o.options)
+    }
+}
+
+/// Parses possibly incomplete JSON, closing any open strings, arrays and
+/// objects. Trailing input that can not be completed, such as a dangling key
+/// or a partial literal, is dropped. Returns `None` if nothing could be parsed.
+pub(crate) fn parse_partial_json(input: &str) -> Option<Value> {
+    if let Ok(value) = serde_json::from_str(input) {
+        return Some(value);
+    }
+
+    let mut ends: Vec<usize> = input.char_indices().map(|(i, _)| i).collect();
+    ends.push(input.len());
+
+    ends.into_iter()
+        .rev()
+        .filter(|&end| end > 0)
+        .find_map(|end| serde_json::from_str(&complete_json(&input[..end])).ok())
+}
+
+/// Appends the closing quotes and brackets needed to complete `input`.
+fn complete_json(input: &str) -> String {
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in input.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                closers.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let mut completed = input.to_string();
+    if in_string {
+        completed.push('"');
+    }
+    completed.extend(closers.into_iter().rev());
+    completed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::language_model::LanguageModelResponse;
+    use crate::test_utils::MockLanguageModel;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct Person {
+        name: String,
+        age: u8,
+        hobbies: Vec<String>,
+    }
+
+    #[test]
+    fn test_parse_partial_json() {
+        assert_eq!(parse_partial_json(""), None);
+        assert_eq!(parse_partial_json("{"), Some(json!({})));
+        assert_eq!(
+            parse_partial_json(r#"{"name": "Al"#),
+            Some(json!({"name": "Al"}))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"name": "Alice", "ag"#),
+            Some(json!({"name": "Alice"}))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"name": "Alice", "age": 3"#),
+            Some(json!({"name": "Alice", "age": 3}))
+        );
+        assert_eq!(parse_partial_json(r#"{"ok": tr"#), Some(json!({})));
+        assert_eq!(
+            parse_partial_json(r#"{"tags": ["a", "b"#),
+            Some(json!({"tags": ["a", "b"]}))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"quote": "say \"hi\"#),
+            Some(json!({"quote": "say \"hi"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_object_yields_partials_then_object() {
+        let deltas = [
+            r#"{"name": "Ali"#,
+            r#"ce", "age": 3"#,
+            r#"0, "hobbies": ["chess""#,
+            r#", "go"]}"#,
+        ];
+
+        let mut response = LanguageModelResponse::new("");
+        response.contents = deltas.iter().map(|d| d.to_string().into()).collect();
+        let model = MockLanguageModel::new().respond(Ok(response));
+
+        let mut response = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("Describe a person")
+            .build()
+            .stream_object::<Person>()
+            .await
+            .unwrap();
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = response.stream.next().await {
+            chunks.push(chunk.unwrap());
+        }
+
+        assert_eq!(
+            chunks,
+            vec![
+                ObjectStreamChunk::Partial(json!({"name": "Ali"})),
+                ObjectStreamChunk::Partial(json!({"name": "Alice", "age": 3})),
+                ObjectStreamChunk::Partial(
+                    json!({"name": "Alice", "age": 30, "hobbies": ["chess"]})
+                ),
+                ObjectStreamChunk::Partial(
+                    json!({"name": "Alice", "age": 30, "hobbies": ["chess", "go"]})
+                ),
+                ObjectStreamChunk::Object(Person {
+                    name: "Alice".to_string(),
+                    age: 30,
+                    hobbies: vec!["chess".to_string(), "go".to_string()],
+                }),
+            ]
+        );
+        assert!(model.calls()[0].schema.is_some());
+    }
+}
@@ -1,15 +1,29 @@
+#[cfg(feature = "otel")]
+use crate::core::telemetry::{self, GenerationSpan};
 use crate::core::{
     AssistantMessage, LanguageModelStreamChunkType, Message,
     language_model::{
         LanguageModel, LanguageModelOptions, LanguageModelResponseContentType, LanguageModelStream,
-        LanguageModelStreamChunk, StopReason, request::LanguageModelRequest,
+        LanguageModelStreamChunk, ProviderStream, StopReason, StreamHandle, Usage,
+        generate_text::GenerateTextResponse, request::LanguageModelRequest,
     },
     messages::TaggedMessage,
-    utils::resolve_message,
+    tokens::CHARS_PER_TOKEN,
+    tools::ToolCallInfo,
 };
-use crate::error::Result;
-use futures::StreamExt;
+use crate::error::{Error, Result};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::json;
 use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+
+/// Number of estimated output tokens between two approximate `UsageDelta`
+/// chunks.
+const USAGE_ESTIMATE_INTERVAL: usize = 16;
 
 impl<M: LanguageModel> LanguageModelRequest<M> {
     /// Generates Streaming text using a specified language model.
@@ -17,142 +31,353 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
     /// Generate a text and call tools for a given prompt using a language model.
     /// This function streams the output. If you do not want to stream the output, use `GenerateText` instead.
     ///
+    /// The model is called before this returns, its response is then read
+    /// by a spawned task as the stream is consumed, running tool calls and
+    /// further steps. The task stops reading from the provider once the
+    /// stream is stopped, aborted or dropped.
+    ///
     /// Returns an `Error` if the underlying model fails to generate a response.
-    pub async fn stream_text(&mut self) -> Result<StreamTextResponse> {
-        let (system_prompt, messages) = resolve_message(&self.options, &self.prompt);
-
-        let mut options = LanguageModelOptions {
-            system: Some(system_prompt),
-            messages,
-            schema: self.options.schema.to_owned(),
-            stop_sequences: self.options.stop_sequences.to_owned(),
-            tools: self.options.tools.to_owned(),
-            stop_when: self.options.stop_when.clone(),
-            prepare_step: self.options.prepare_step.clone(),
-            on_step_finish: self.options.on_step_finish.clone(),
-            stop_reason: None,
-            ..self.options
-        };
+    /// Failures of later steps end the stream with a `Failed` chunk.
+    pub async fn stream_text(&mut self) -> Result<StreamTextResponse>
+    where
+        M: 'static,
+    {
+        let mut options = self.resolve_options()?;
+
+        let started = Instant::now();
+        let step = start_step(&*self.model, &mut options).await?;
 
         let (tx, stream) = LanguageModelStream::new();
         let _ = tx.send(LanguageModelStreamChunkType::Start);
 
-        loop {
-            // Update the current step
-            options.current_step_id += 1;
+        let outcome = Arc::new(OnceLock::new());
+        let producer = tokio::spawn(produce(
+            Arc::clone(&self.model),
+            options.clone(),
+            step,
+            tx,
+            stream.handle(),
+            Arc::clone(&outcome),
+            started,
+        ));
+
+        Ok(StreamTextResponse {
+            stream,
+            options,
+            outcome,
+            producer: Some(producer),
+            started,
+            provider_name: self.model.provider_name().to_string(),
+            model_name: self.model.model_name(),
+        })
+    }
+}
 
-            // Prepare the next step
-            if let Some(hook) = options.prepare_step.clone() {
-                hook(&mut options);
+/// The provider stream of a step, with the span of its call.
+struct Step {
+    response: ProviderStream,
+    #[cfg(feature = "otel")]
+    span: GenerationSpan,
+}
+
+/// Starts the next step, calling the model as often as the retry policy
+/// allows.
+async fn start_step<M: LanguageModel + ?Sized>(
+    model: &M,
+    options: &mut LanguageModelOptions,
+) -> Result<Step> {
+    // Update the current step
+    options.current_step_id += 1;
+
+    // Prepare the next step
+    if let Some(hook) = options.prepare_step.clone() {
+        hook(options);
+    }
+
+    // the history policy only shortens what is sent, the response
+    // keeps every message.
+    let request = options.redacted().fit_history();
+    request.check_context_limit()?;
+    options.log_tool_schemas();
+
+    let retry_policy = options.resolved_retry_policy();
+    let mut attempt = 0;
+    loop {
+        // the span of the provider call ends once its stream is read,
+        // with the usage and finish reason of the step.
+        #[cfg(feature = "otel")]
+        let span = GenerationSpan::start(model.provider_name(), &model.model_name(), &request);
+        match model.stream_text(request.clone()).await {
+            Ok(response) => {
+                return Ok(Step {
+                    response,
+                    #[cfg(feature = "otel")]
+                    span,
+                });
+            }
+            Err(e) if retry_policy.should_retry(&e, attempt) => {
+                #[cfg(feature = "otel")]
+                span.fail(&e);
+                tokio::time::sleep(retry_policy.delay(attempt)).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                #[cfg(feature = "otel")]
+                span.fail(&e);
+                options.stop_reason = Some(StopReason::Error(e.clone()));
+                return Err(e);
             }
+        }
+    }
+}
 
-            let mut response = self
-                .model
-                .stream_text(options.clone())
-                .await
-                .inspect_err(|e| {
-                    options.stop_reason = Some(StopReason::Error(e.clone()));
-                })?;
+/// Reads the provider stream of each step into `tx`, running tool calls and
+/// further steps, until generating finishes or the stream is stopped, aborted
+/// or dropped. The outcome is published before the stream ends.
+async fn produce<M: LanguageModel + ?Sized>(
+    model: Arc<M>,
+    mut options: LanguageModelOptions,
+    mut step: Step,
+    tx: UnboundedSender<LanguageModelStreamChunkType>,
+    handle: StreamHandle,
+    outcome: Arc<OnceLock<StreamOutcome>>,
+    started: Instant,
+) {
+    let mut time_to_first_token = None;
+    // with multiple completions the text of each one is assembled by
+    // its index, the first one streaming as `Text`.
+    let multiple = options.n.is_some_and(|n| n > 1);
+    let mut candidates: Vec<String> = Vec::new();
+    let mut failure = None;
 
-            while let Some(ref chunk) = response.next().await {
-                match chunk {
-                    Ok(chunk) => {
-                        for output in chunk {
-                            match output {
-                                LanguageModelStreamChunk::Done(final_msg) => {
-                                    match final_msg.content {
-                                        LanguageModelResponseContentType::Text(_) => {
-                                            let assistant_msg =
-                                                Message::Assistant(AssistantMessage {
-                                                    content: final_msg.content.clone(),
-                                                    usage: final_msg.usage.clone(),
-                                                });
-                                            options.messages.push(TaggedMessage::new(
-                                                options.current_step_id,
-                                                assistant_msg,
-                                            ));
-                                            options.stop_reason = Some(StopReason::Finish);
-                                        }
-                                        LanguageModelResponseContentType::Reasoning(ref reason) => {
-                                            options.messages.push(TaggedMessage::new(
-                                                options.current_step_id,
-                                                Message::Assistant(AssistantMessage {
-                                                    content:
-                                                        LanguageModelResponseContentType::Reasoning(
-                                                            reason.clone(),
-                                                        ),
-                                                    usage: final_msg.usage.clone(),
-                                                }),
-                                            ))
-                                        }
-                                        LanguageModelResponseContentType::ToolCall(
-                                            ref tool_info,
-                                        ) => {
-                                            // add tool message
-                                            let usage = final_msg.usage.clone();
-                                            let _ = &options.messages.push(TaggedMessage::new(
-                                                options.current_step_id.to_owned(),
-                                                Message::Assistant(AssistantMessage::new(
-                                                    LanguageModelResponseContentType::ToolCall(
-                                                        tool_info.clone(),
+    loop {
+        candidates.clear();
+
+        let mut response = step.response;
+        #[cfg(feature = "otel")]
+        let mut step_span = Some(step.span);
+        #[cfg(feature = "otel")]
+        let (mut step_usage, mut step_finish_reason) = (None, "stop".to_string());
+
+        // output usage is estimated from the streamed text unless the
+        // provider reports it while streaming.
+        let mut streamed_chars = 0;
+        let mut estimated_tokens = 0;
+        let mut provider_usage = false;
+
+        loop {
+            // nothing is pulled from the provider once nobody reads the stream
+            if tx.is_closed() || handle.is_stopped() {
+                options.stop_reason = Some(StopReason::Other("stopped".to_string()));
+                break;
+            }
+            let Some(ref chunk) = response.next().await else {
+                break;
+            };
+
+            match chunk {
+                Ok(chunk) => {
+                    for output in chunk {
+                        match output {
+                            LanguageModelStreamChunk::Done(final_msg) => {
+                                #[cfg(feature = "otel")]
+                                {
+                                    step_usage = final_msg.usage.clone().or(step_usage);
+                                    let reason = telemetry::finish_reason(&final_msg.content);
+                                    if reason != "stop" {
+                                        step_finish_reason = reason.to_string();
+                                    }
+                                }
+                                match final_msg.content {
+                                    LanguageModelResponseContentType::Text(_) => {
+                                        let assistant_msg = Message::Assistant(AssistantMessage {
+                                            content: final_msg.content.clone(),
+                                            usage: final_msg.usage.clone(),
+                                        });
+                                        options.messages.push(TaggedMessage::new(
+                                            options.current_step_id,
+                                            assistant_msg,
+                                        ));
+                                        options.stop_reason = Some(StopReason::Finish);
+                                    }
+                                    LanguageModelResponseContentType::Reasoning(ref reason) => {
+                                        options.messages.push(TaggedMessage::new(
+                                            options.current_step_id,
+                                            Message::Assistant(AssistantMessage {
+                                                content:
+                                                    LanguageModelResponseContentType::Reasoning(
+                                                        reason.clone(),
                                                     ),
-                                                    usage,
-                                                )),
-                                            ));
-                                            options.handle_tool_call(tool_info).await;
-                                        }
-                                        _ => {}
+                                                usage: final_msg.usage.clone(),
+                                            }),
+                                        ))
                                     }
+                                    LanguageModelResponseContentType::ToolCall(ref tool_info) => {
+                                        // add tool message
+                                        let usage = final_msg.usage.clone();
+                                        let _ = &options.messages.push(TaggedMessage::new(
+                                            options.current_step_id.to_owned(),
+                                            Message::Assistant(AssistantMessage::new(
+                                                LanguageModelResponseContentType::ToolCall(
+                                                    tool_info.clone(),
+                                                ),
+                                                usage,
+                                            )),
+                                        ));
+                                        options.handle_tool_call(tool_info).await;
 
-                                    // Finish the step
-                                    if let Some(ref hook) = options.on_step_finish {
-                                        hook(&options);
+                                        // a final tool's output is the response text
+                                        if options.stop_reason.is_some()
+                                            && let Some(text) = options.text()
+                                        {
+                                            let _ =
+                                                tx.send(LanguageModelStreamChunkType::Text(text));
+                                        }
                                     }
-
-                                    // Stop If
-                                    if let Some(hook) = &options.stop_when.clone()
-                                        && hook(&options)
-                                    {
-                                        let _ = tx.send(LanguageModelStreamChunkType::Incomplete(
-                                            "Stopped by hook".to_string(),
+                                    LanguageModelResponseContentType::BuiltinToolCall(_) => {
+                                        options.messages.push(TaggedMessage::new(
+                                            options.current_step_id,
+                                            Message::Assistant(final_msg.clone()),
+                                        ))
+                                    }
+                                    LanguageModelResponseContentType::Refusal(_)
+                                    | LanguageModelResponseContentType::Audio { .. } => {
+                                        options.messages.push(TaggedMessage::new(
+                                            options.current_step_id,
+                                            Message::Assistant(final_msg.clone()),
                                         ));
-                                        options.stop_reason = Some(StopReason::Hook);
-                                        break;
+                                        options.stop_reason = Some(StopReason::Finish);
                                     }
+                                    _ => {}
+                                }
+
+                                // Finish the step
+                                if let Some(ref hook) = options.on_step_finish {
+                                    hook(&options);
+                                }
+
+                                // Stop If
+                                if let Some(hook) = &options.stop_when.clone()
+                                    && let Some(reason) = hook(&options)
+                                {
+                                    let _ = tx.send(LanguageModelStreamChunkType::Incomplete(
+                                        reason.clone(),
+                                    ));
+                                    options.stop_reason = Some(StopReason::Hook(reason));
+                                    break;
+                                }
 
-                                    let _ = tx
-                                        .send(LanguageModelStreamChunkType::End(final_msg.clone()));
+                                if options.tool_loop_detected() {
+                                    let _ = tx.send(LanguageModelStreamChunkType::Incomplete(
+                                        "Tool loop detected".to_string(),
+                                    ));
+                                    options.stop_reason =
+                                        Some(StopReason::Other("tool loop detected".to_string()));
+                                    break;
                                 }
-                                LanguageModelStreamChunk::Delta(other) => {
-                                    let _ = tx.send(other.clone()); // propagate chunks
+
+                                let _ =
+                                    tx.send(LanguageModelStreamChunkType::End(final_msg.clone()));
+                            }
+                            LanguageModelStreamChunk::Delta(other) => {
+                                if time_to_first_token.is_none()
+                                    && matches!(other, LanguageModelStreamChunkType::Text(_))
+                                {
+                                    time_to_first_token = Some(started.elapsed());
+                                }
+                                let _ = tx.send(other.clone()); // propagate chunks
+
+                                if multiple {
+                                    match other {
+                                        LanguageModelStreamChunkType::Text(text) => {
+                                            push_candidate(&mut candidates, 0, text)
+                                        }
+                                        LanguageModelStreamChunkType::CandidateText {
+                                            index,
+                                            text,
+                                        } => push_candidate(&mut candidates, *index, text),
+                                        _ => {}
+                                    }
+                                }
+
+                                match other {
+                                    LanguageModelStreamChunkType::Incomplete(reason) => {
+                                        #[cfg(feature = "otel")]
+                                        {
+                                            step_finish_reason = reason.clone();
+                                        }
+                                        options.stop_reason =
+                                            Some(StopReason::Incomplete(reason.clone()));
+                                    }
+                                    LanguageModelStreamChunkType::UsageDelta {
+                                        approximate: false,
+                                        ..
+                                    } => provider_usage = true,
+                                    LanguageModelStreamChunkType::Text(text) if !provider_usage => {
+                                        streamed_chars += text.chars().count();
+                                        let estimate = streamed_chars.div_ceil(CHARS_PER_TOKEN);
+                                        if estimate >= estimated_tokens + USAGE_ESTIMATE_INTERVAL {
+                                            estimated_tokens = estimate;
+                                            let _ =
+                                                tx.send(LanguageModelStreamChunkType::UsageDelta {
+                                                    usage: Usage {
+                                                        output_tokens: Some(estimate),
+                                                        ..Default::default()
+                                                    },
+                                                    approximate: true,
+                                                });
+                                        }
+                                    }
+                                    _ => {}
                                 }
                             }
                         }
                     }
-                    Err(e) => {
-                        let _ = tx.send(LanguageModelStreamChunkType::Failed(e.to_string()));
-                        options.stop_reason = Some(StopReason::Error(e.clone()));
-                        break;
+                }
+                Err(e) => {
+                    #[cfg(feature = "otel")]
+                    if let Some(span) = step_span.take() {
+                        span.fail(e);
                     }
+                    failure = Some(e.clone());
+                    options.stop_reason = Some(StopReason::Error(e.clone()));
+                    break;
                 }
+            }
 
-                match options.stop_reason {
-                    None => {}
-                    _ => break,
-                };
+            if options.stop_reason.is_some() {
+                break;
             }
+        }
 
-            match options.stop_reason {
-                None => {}
-                _ => break,
-            };
+        #[cfg(feature = "otel")]
+        if let Some(span) = step_span {
+            span.end(step_usage.as_ref(), &step_finish_reason);
         }
 
-        drop(tx);
+        if options.stop_reason.is_some() {
+            break;
+        }
 
-        let result = StreamTextResponse { stream, options };
+        step = match start_step(&*model, &mut options).await {
+            Ok(step) => step,
+            Err(e) => {
+                failure = Some(e.clone());
+                options.stop_reason = Some(StopReason::Error(e));
+                break;
+            }
+        };
+    }
 
-        Ok(result)
+    // the outcome is read once the stream ends or fails
+    let _ = outcome.set(StreamOutcome {
+        options,
+        candidates,
+        time_to_first_token,
+        total_duration: started.elapsed(),
+    });
+    if let Some(e) = failure {
+        let _ = tx.send(LanguageModelStreamChunkType::Failed(e.to_string()));
     }
 }
 
@@ -164,21 +389,895 @@ impl<M: LanguageModel> LanguageModelRequest<M> {
 pub struct StreamTextResponse {
     /// A stream of responses from the language model.
     pub stream: LanguageModelStream,
-    /// The reason the model stopped generating text.
-    options: LanguageModelOptions,
+    /// The options the stream started with, until the outcome is published.
+    pub(crate) options: LanguageModelOptions,
+    /// The state of the response once generating it finished.
+    pub(crate) outcome: Arc<OnceLock<StreamOutcome>>,
+    /// The task generating the response.
+    producer: Option<JoinHandle<()>>,
+    /// When the stream started.
+    started: Instant,
+    /// The provider that generated the response.
+    provider_name: String,
+    /// The model that generated the response.
+    model_name: String,
+}
+
+/// The state of a streamed response once generating it finished.
+#[derive(Debug)]
+pub(crate) struct StreamOutcome {
+    /// The final options, with every message and the stop reason.
+    pub(crate) options: LanguageModelOptions,
+    /// Text completions of the last step when more than one was requested.
+    candidates: Vec<String>,
+    /// Time until the first text chunk was received.
+    time_to_first_token: Option<Duration>,
+    /// Time until the stream completed.
+    total_duration: Duration,
+}
+
+impl StreamOutcome {
+    /// The error that ended the stream, if any.
+    fn error(&self) -> Option<Error> {
+        match &self.options.stop_reason {
+            Some(StopReason::Error(e)) => Some(e.clone()),
+            _ => None,
+        }
+    }
 }
 
 impl StreamTextResponse {
     #[cfg(any(test, feature = "test-access"))]
     pub fn step_ids(&self) -> Vec<usize> {
-        self.options.messages.iter().map(|t| t.step_id).collect()
+        self.messages.iter().map(|t| t.step_id).collect()
+    }
+
+    /// Returns a handle that stops the stream from another task, e.g. when a
+    /// client forwarded the chunks disconnects. Generating stops too, no
+    /// further chunks are read from the provider.
+    pub fn handle(&self) -> StreamHandle {
+        self.stream.handle()
+    }
+
+    /// Ends the stream early. Unlike `StreamHandle::stop`, no final
+    /// `Incomplete` chunk is yielded and the chunks not read yet are
+    /// discarded.
+    ///
+    /// Generating stops as it does when the response is dropped: the task
+    /// reading the provider stream ends before pulling its next chunk.
+    pub fn abort(&mut self) {
+        self.stream.close();
+    }
+
+    /// Returns the name of the provider that generated the response.
+    pub fn provider_name(&self) -> &str {
+        &self.provider_name
+    }
+
+    /// Returns the id of the model that generated the response.
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    /// Returns every text completion of the last step when `n` completions
+    /// were requested, assembled from the deltas of each completion. The
+    /// first one is the response `text`. Empty until the stream ended.
+    pub fn candidates(&self) -> &[String] {
+        self.outcome.get().map_or(&[], |o| &o.candidates)
+    }
+
+    /// Returns the time until the first text chunk was received, or `None`
+    /// if no text was generated or the stream has not ended yet.
+    pub fn time_to_first_token(&self) -> Option<Duration> {
+        self.outcome.get().and_then(|o| o.time_to_first_token)
+    }
+
+    /// Returns the time until the stream completed, including tool calls,
+    /// or the time elapsed so far while it is read.
+    pub fn total_duration(&self) -> Duration {
+        self.outcome
+            .get()
+            .map_or_else(|| self.started.elapsed(), |o| o.total_duration)
+    }
+
+    /// Waits for the task generating the response to finish.
+    async fn finished(&mut self) -> Result<()> {
+        if let Some(producer) = self.producer.take() {
+            producer.await.map_err(|e| Error::Other(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Consumes the response, draining the stream, and returns the same
+    /// response `generate_text` would, e.g. to share one streaming path
+    /// between a UI and code needing the final result.
+    ///
+    /// Returns the error that ended the stream, if any.
+    pub async fn collect(mut self) -> Result<GenerateTextResponse> {
+        while self.stream.next().await.is_some() {}
+        self.finished().await?;
+
+        if let Some(StopReason::Error(e)) = &self.stop_reason {
+            return Err(e.clone());
+        }
+
+        Ok(GenerateTextResponse::from_stream(
+            self.deref().clone(),
+            self.candidates().to_vec(),
+            self.total_duration(),
+            self.provider_name,
+            self.model_name,
+        ))
     }
+
+    /// Consumes the response and returns a stream of the generated text deltas.
+    ///
+    /// Control chunks such as `Start`, `End` and tool call arguments are
+    /// skipped. A `Failed` chunk is yielded as an `Error`.
+    pub fn text_stream(self) -> impl Stream<Item = Result<String>> {
+        self.stream.filter_map(|chunk| async move {
+            match chunk {
+                LanguageModelStreamChunkType::Text(text) => Some(Ok(text)),
+                LanguageModelStreamChunkType::Failed(error) => Some(Err(Error::Other(error))),
+                _ => None,
+            }
+        })
+    }
+
+    /// Consumes the response and returns a stream of typed events, hiding the
+    /// `Start`, `End` and usage chunks.
+    ///
+    /// The stream ends with `StreamEvent::Finish`, or with a
+    /// `StreamEvent::Error` if generating failed.
+    pub fn events(self) -> impl Stream<Item = StreamEvent> {
+        let StreamTextResponse {
+            stream,
+            options,
+            outcome,
+            producer,
+            ..
+        } = self;
+
+        let published = outcome.clone();
+        // whether the reasoning being generated was streamed as deltas, in
+        // which case its finished message is not sent again
+        let mut streamed_reasoning = false;
+        let events = stream.filter_map(move |chunk| {
+            // the outcome is published before the `Failed` chunk is sent
+            let error = match chunk {
+                LanguageModelStreamChunkType::Failed(_) => {
+                    published.get().and_then(StreamOutcome::error)
+                }
+                _ => None,
+            };
+            let reasoning_streamed = streamed_reasoning;
+            match chunk {
+                LanguageModelStreamChunkType::Reasoning(_) => streamed_reasoning = true,
+                LanguageModelStreamChunkType::End(_) => streamed_reasoning = false,
+                _ => {}
+            }
+            async move {
+                match chunk {
+                    LanguageModelStreamChunkType::Text(text) => Some(StreamEvent::TextDelta(text)),
+                    LanguageModelStreamChunkType::Reasoning(reasoning) => {
+                        Some(StreamEvent::ReasoningDelta(reasoning))
+                    }
+                    LanguageModelStreamChunkType::End(AssistantMessage { content, .. }) => {
+                        match content {
+                            LanguageModelResponseContentType::ToolCall(call) => {
+                                Some(StreamEvent::ToolCall(call))
+                            }
+                            LanguageModelResponseContentType::Reasoning(reasoning)
+                                if !reasoning_streamed =>
+                            {
+                                Some(StreamEvent::ReasoningDelta(reasoning))
+                            }
+                            _ => None,
+                        }
+                    }
+                    LanguageModelStreamChunkType::Failed(message) => {
+                        Some(StreamEvent::Error(error.unwrap_or(Error::Other(message))))
+                    }
+                    _ => None,
+                }
+            }
+        });
+
+        let finish = futures::stream::once(async move {
+            if let Some(producer) = producer {
+                let _ = producer.await;
+            }
+            let options = outcome.get().map_or(&options, |o| &o.options);
+            StreamEvent::Finish {
+                stop_reason: options.stop_reason.clone().unwrap_or_default(),
+                usage: options.usage(),
+            }
+        });
+
+        // nothing follows an error, not even the finish event
+        events.chain(finish).scan(false, |failed, event| {
+            let next = (!*failed).then(|| {
+                *failed = matches!(event, StreamEvent::Error(_));
+                event
+            });
+            async move { next }
+        })
+    }
+
+    /// Consumes the response and returns a stream of Server-Sent Events that
+    /// web servers can forward to a client as is.
+    ///
+    /// Text deltas are sent as `data: {"type":"text","delta":...}` frames,
+    /// finished tool calls as `tool_call` events and failures as `error`
+    /// events. The stream always ends with `data: [DONE]`.
+    pub fn into_sse(self) -> impl Stream<Item = Result<Bytes>> {
+        let frames = self.stream.filter_map(|chunk| async move {
+            let frame = match chunk {
+                LanguageModelStreamChunkType::Text(delta) => {
+                    sse_frame(None, json!({ "type": "text", "delta": delta }))
+                }
+                LanguageModelStreamChunkType::Reasoning(delta) => {
+                    sse_frame(None, json!({ "type": "reasoning", "delta": delta }))
+                }
+                LanguageModelStreamChunkType::Audio(delta) => {
+                    sse_frame(None, json!({ "type": "audio", "delta": delta }))
+                }
+                LanguageModelStreamChunkType::End(AssistantMessage {
+                    content: LanguageModelResponseContentType::ToolCall(call),
+                    ..
+                }) => sse_frame(
+                    Some("tool_call"),
+                    json!({ "id": call.tool.id, "name": call.tool.name, "input": call.input }),
+                ),
+                LanguageModelStreamChunkType::Failed(error) => {
+                    sse_frame(Some("error"), json!({ "error": error }))
+                }
+                LanguageModelStreamChunkType::Incomplete(reason) => {
+                    sse_frame(Some("incomplete"), json!({ "reason": reason }))
+                }
+                LanguageModelStreamChunkType::UsageDelta { usage, approximate } => sse_frame(
+                    Some("usage"),
+                    json!({ "usage": usage, "approximate": approximate }),
+                ),
+                _ => return None,
+            };
+            Some(Ok(frame))
+        });
+
+        frames.chain(futures::stream::once(async {
+            Ok(Bytes::from_static(b"data: [DONE]\n\n"))
+        }))
+    }
+}
+
+/// A typed event of a streamed response, see `StreamTextResponse::events`.
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// A chunk of the generated text.
+    TextDelta(String),
+    /// A finished tool call, executed before the next step.
+    ToolCall(ToolCallInfo),
+    /// Reasoning of the model.
+    ReasoningDelta(String),
+    /// The stream finished, with the usage of all steps.
+    Finish {
+        stop_reason: StopReason,
+        usage: Usage,
+    },
+    /// Generating failed, no events follow.
+    Error(Error),
+}
+
+/// Appends a text delta to the completion at `index`.
+fn push_candidate(candidates: &mut Vec<String>, index: usize, text: &str) {
+    if candidates.len() <= index {
+        candidates.resize(index + 1, String::new());
+    }
+    candidates[index].push_str(text);
+}
+
+/// Formats a single Server-Sent Event.
+fn sse_frame(event: Option<&str>, data: serde_json::Value) -> Bytes {
+    let mut frame = String::new();
+    if let Some(event) = event {
+        frame.push_str(&format!("event: {event}\n"));
+    }
+    frame.push_str(&format!("data: {data}\n\n"));
+    Bytes::from(frame)
 }
 
 impl Deref for StreamTextResponse {
     type Target = LanguageModelOptions;
 
     fn deref(&self) -> &Self::Target {
-        &self.options
+        self.outcome.get().map_or(&self.options, |o| &o.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(chunks: Vec<LanguageModelStreamChunkType>) -> StreamTextResponse {
+        let (tx, stream) = LanguageModelStream::new();
+        for chunk in chunks {
+            tx.send(chunk).unwrap();
+        }
+        StreamTextResponse {
+            stream,
+            options: LanguageModelOptions::default(),
+            outcome: Arc::new(OnceLock::new()),
+            producer: None,
+            started: Instant::now(),
+            provider_name: String::new(),
+            model_name: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_text_stream_yields_only_text() {
+        let response = response(vec![
+            LanguageModelStreamChunkType::Start,
+            LanguageModelStreamChunkType::Text("Hello".to_string()),
+            LanguageModelStreamChunkType::ToolCall("{\"a\":".to_string()),
+            LanguageModelStreamChunkType::Text(" world".to_string()),
+            LanguageModelStreamChunkType::NotSupported("image".to_string()),
+            LanguageModelStreamChunkType::End(AssistantMessage::new(
+                LanguageModelResponseContentType::Text("Hello world".to_string()),
+                None,
+            )),
+        ]);
+
+        let texts: Vec<String> = response
+            .text_stream()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(texts, vec!["Hello".to_string(), " world".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_into_sse_frames_deltas_and_tool_calls() {
+        let mut call = crate::core::ToolCallInfo::new("search");
+        call.id("call_1");
+        call.input(json!({"q": "rust"}));
+
+        let response = response(vec![
+            LanguageModelStreamChunkType::Start,
+            LanguageModelStreamChunkType::Text("Hel".to_string()),
+            LanguageModelStreamChunkType::Text("lo\n".to_string()),
+            LanguageModelStreamChunkType::End(AssistantMessage::new(
+                LanguageModelResponseContentType::ToolCall(call),
+                None,
+            )),
+        ]);
+
+        let frames: Vec<Bytes> = response
+            .into_sse()
+            .map(|frame| frame.unwrap())
+            .collect()
+            .await;
+        let output: String = frames
+            .iter()
+            .map(|f| String::from_utf8(f.to_vec()).unwrap())
+            .collect();
+
+        assert_eq!(
+            output,
+            concat!(
+                "data: {\"delta\":\"Hel\",\"type\":\"text\"}\n\n",
+                "data: {\"delta\":\"lo\\n\",\"type\":\"text\"}\n\n",
+                "event: tool_call\n",
+                "data: {\"id\":\"call_1\",\"input\":{\"q\":\"rust\"},\"name\":\"search\"}\n\n",
+                "data: [DONE]\n\n",
+            )
+        );
+        assert!(output.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_text_stream_propagates_failure() {
+        let response = response(vec![
+            LanguageModelStreamChunkType::Text("partial".to_string()),
+            LanguageModelStreamChunkType::Failed("connection reset".to_string()),
+        ]);
+
+        let chunks: Vec<Result<String>> = response.text_stream().collect().await;
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].as_ref().unwrap(), "partial");
+        assert!(matches!(&chunks[1], Err(Error::Other(e)) if e == "connection reset"));
+    }
+
+    async fn usage_deltas(response: StreamTextResponse) -> Vec<(Usage, bool)> {
+        response
+            .stream
+            .filter_map(|chunk| async move {
+                match chunk {
+                    LanguageModelStreamChunkType::UsageDelta { usage, approximate } => {
+                        Some((usage, approximate))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_stream_text_estimates_usage_from_text() {
+        use crate::test_utils::MockLanguageModel;
+
+        let text = "word ".repeat(40);
+        let model = MockLanguageModel::new().respond_stream(vec![
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(text.clone())),
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text("!".to_string())),
+            LanguageModelStreamChunk::Done(AssistantMessage::new(text.into(), None)),
+        ]);
+
+        let response = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("hi")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        let deltas = usage_deltas(response).await;
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].0.output_tokens, Some(50));
+        assert!(deltas[0].1);
+    }
+
+    #[tokio::test]
+    async fn test_usage_estimates_arrive_while_streaming() {
+        use crate::test_utils::MockLanguageModel;
+
+        let chunks: Vec<_> = (0..10)
+            .map(|_| {
+                LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(
+                    "word ".repeat(20),
+                ))
+            })
+            .collect();
+        let model = MockLanguageModel::new()
+            .respond_stream(chunks)
+            .with_chunk_delay(Duration::from_millis(10));
+
+        let mut response = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("hi")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        while !matches!(
+            response.stream.next().await,
+            Some(LanguageModelStreamChunkType::UsageDelta {
+                approximate: true,
+                ..
+            })
+        ) {}
+        assert!(model.pulled_chunks() < 10);
+    }
+
+    #[tokio::test]
+    async fn test_stream_text_forwards_provider_usage() {
+        use crate::test_utils::MockLanguageModel;
+
+        // an anthropic style stream reporting usage with every `message_delta`
+        let usage = |output_tokens| {
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::UsageDelta {
+                usage: Usage {
+                    output_tokens: Some(output_tokens),
+                    ..Default::default()
+                },
+                approximate: false,
+            })
+        };
+        let text = "word ".repeat(40);
+        let model = MockLanguageModel::new().respond_stream(vec![
+            usage(1),
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(text.clone())),
+            usage(41),
+            LanguageModelStreamChunk::Done(AssistantMessage::new(text.into(), None)),
+        ]);
+
+        let response = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("hi")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        let deltas = usage_deltas(response).await;
+        let output_tokens: Vec<_> = deltas.iter().map(|(u, _)| u.output_tokens).collect();
+        assert_eq!(output_tokens, vec![Some(1), Some(41)]);
+        assert!(deltas.iter().all(|(_, approximate)| !approximate));
+    }
+
+    #[tokio::test]
+    async fn test_collect_matches_generate_text() {
+        use crate::core::language_model::LanguageModelResponse;
+        use crate::test_utils::MockLanguageModel;
+
+        let usage = Usage {
+            input_tokens: Some(3),
+            output_tokens: Some(2),
+            total_tokens: Some(5),
+            ..Default::default()
+        };
+        let mut expected = LanguageModelResponse::new("Hello world");
+        expected.usage = Some(usage.clone());
+
+        let model = MockLanguageModel::new()
+            .respond_stream(vec![
+                LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(
+                    "Hello".to_string(),
+                )),
+                LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(
+                    " world".to_string(),
+                )),
+                LanguageModelStreamChunk::Done(AssistantMessage::new(
+                    "Hello world".to_string().into(),
+                    Some(usage),
+                )),
+            ])
+            .respond(Ok(expected));
+
+        let request = || {
+            LanguageModelRequest::builder()
+                .model(model.clone())
+                .prompt("hi")
+                .build()
+        };
+        let collected = request()
+            .stream_text()
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let generated = request().generate_text().await.unwrap();
+
+        assert_eq!(collected.text(), Some("Hello world".to_string()));
+        assert_eq!(collected.text(), generated.text());
+        assert_eq!(collected.usage(), generated.usage());
+        assert_eq!(collected.stop_reason(), generated.stop_reason());
+        assert_eq!(collected.provider_name(), "mock");
+    }
+
+    #[tokio::test]
+    async fn test_stream_text_records_timings() {
+        use crate::test_utils::MockLanguageModel;
+
+        let delay = Duration::from_millis(20);
+        let model = MockLanguageModel::new()
+            .with_delay(delay)
+            .respond_text("hello");
+
+        let mut response = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("hi")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+        while response.stream.next().await.is_some() {}
+
+        let time_to_first_token = response.time_to_first_token().unwrap();
+        assert!(time_to_first_token >= delay);
+        assert!(time_to_first_token <= response.total_duration());
+    }
+
+    #[tokio::test]
+    async fn test_stream_handle_stops_stream_with_incomplete() {
+        use crate::test_utils::MockLanguageModel;
+
+        let chunks: Vec<_> = (0..10)
+            .map(|i| {
+                LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(i.to_string()))
+            })
+            .collect();
+        let model = MockLanguageModel::new()
+            .respond_stream(chunks)
+            .with_chunk_delay(Duration::from_millis(10));
+
+        let mut response = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("count")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        let handle = response.handle();
+        assert!(matches!(
+            response.stream.next().await,
+            Some(LanguageModelStreamChunkType::Start)
+        ));
+        assert!(matches!(
+            response.stream.next().await,
+            Some(LanguageModelStreamChunkType::Text(ref t)) if t == "0"
+        ));
+
+        tokio::spawn(async move { handle.stop() }).await.unwrap();
+
+        let rest: Vec<LanguageModelStreamChunkType> = response.stream.by_ref().collect().await;
+        assert_eq!(rest.len(), 1);
+        assert!(matches!(
+            rest[0],
+            LanguageModelStreamChunkType::Incomplete(_)
+        ));
+        assert!(response.handle().is_stopped());
+        assert!(response.stream.next().await.is_none());
+
+        // the chunk being read when stopping is the last one pulled
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let pulled = model.pulled_chunks();
+        assert!(pulled <= 2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(model.pulled_chunks(), pulled);
+    }
+
+    #[tokio::test]
+    async fn test_abort_ends_stream_without_chunks() {
+        let mut response = response(vec![
+            LanguageModelStreamChunkType::Start,
+            LanguageModelStreamChunkType::Text("one".to_string()),
+            LanguageModelStreamChunkType::Text("two".to_string()),
+        ]);
+
+        assert!(matches!(
+            response.stream.next().await,
+            Some(LanguageModelStreamChunkType::Start)
+        ));
+        response.abort();
+
+        assert!(response.handle().is_stopped());
+        assert!(response.stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dropping_partially_read_stream_stops_producer() {
+        use crate::test_utils::MockLanguageModel;
+
+        let chunks: Vec<_> = (0..10)
+            .map(|i| {
+                LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(i.to_string()))
+            })
+            .collect();
+        let model = MockLanguageModel::new()
+            .respond_stream(chunks)
+            .with_chunk_delay(Duration::from_millis(10));
+
+        let mut response = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("count")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        while !matches!(
+            response.stream.next().await,
+            Some(LanguageModelStreamChunkType::Text(_))
+        ) {}
+        drop(response);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let pulled = model.pulled_chunks();
+        assert!(pulled <= 2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(model.pulled_chunks(), pulled);
+    }
+
+    #[tokio::test]
+    async fn test_events_map_stream_chunks() {
+        use crate::test_utils::{MockLanguageModel, tool};
+
+        let mut call = ToolCallInfo::new("get_weather");
+        call.id("call_1");
+        call.input(serde_json::json!({ "city": "Paris" }));
+        let usage = Usage {
+            input_tokens: Some(8),
+            output_tokens: Some(3),
+            total_tokens: Some(11),
+            ..Default::default()
+        };
+        let text =
+            |t: &str| LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(t.into()));
+        let model = MockLanguageModel::new()
+            .respond_stream(vec![LanguageModelStreamChunk::Done(AssistantMessage::new(
+                LanguageModelResponseContentType::ToolCall(call),
+                None,
+            ))])
+            .respond_stream(vec![
+                LanguageModelStreamChunk::Done(AssistantMessage::new(
+                    LanguageModelResponseContentType::Reasoning("Checking".into()),
+                    None,
+                )),
+                text("It is "),
+                text("sunny"),
+                LanguageModelStreamChunk::Done(AssistantMessage::new(
+                    "It is sunny".to_string().into(),
+                    Some(usage.clone()),
+                )),
+            ]);
+        let tool = tool("get_weather", "sunny");
+
+        let response = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("Weather in Paris?")
+            .with_tool(tool)
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+        let events: Vec<StreamEvent> = response.events().collect().await;
+
+        assert!(matches!(
+            &events[..],
+            [
+                StreamEvent::ToolCall(call),
+                StreamEvent::ReasoningDelta(reasoning),
+                StreamEvent::TextDelta(first),
+                StreamEvent::TextDelta(second),
+                StreamEvent::Finish { stop_reason: StopReason::Finish, usage: total },
+            ] if call.tool.name == "get_weather"
+                && reasoning == "Checking"
+                && first == "It is "
+                && second == "sunny"
+                && *total == usage
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_events_do_not_repeat_streamed_reasoning() {
+        let response = response(vec![
+            LanguageModelStreamChunkType::Reasoning("Check".to_string()),
+            LanguageModelStreamChunkType::Reasoning("ing".to_string()),
+            LanguageModelStreamChunkType::End(AssistantMessage::new(
+                LanguageModelResponseContentType::Reasoning("Checking".into()),
+                None,
+            )),
+            LanguageModelStreamChunkType::Text("sunny".to_string()),
+        ]);
+
+        let events: Vec<StreamEvent> = response.events().collect().await;
+
+        assert!(matches!(
+            &events[..],
+            [
+                StreamEvent::ReasoningDelta(first),
+                StreamEvent::ReasoningDelta(second),
+                StreamEvent::TextDelta(text),
+                StreamEvent::Finish { .. },
+            ] if first == "Check" && second == "ing" && text == "sunny"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_events_end_with_error() {
+        let response = response(vec![
+            LanguageModelStreamChunkType::Text("partial".to_string()),
+            LanguageModelStreamChunkType::Failed("connection reset".to_string()),
+        ]);
+
+        let events: Vec<StreamEvent> = response.events().collect().await;
+
+        assert!(matches!(
+            &events[..],
+            [StreamEvent::TextDelta(text), StreamEvent::Error(Error::Other(message))]
+                if text == "partial" && message == "connection reset"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_stream_stops_with_reason() {
+        use crate::test_utils::MockLanguageModel;
+
+        let model = MockLanguageModel::new().respond_stream(vec![
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text("Once".into())),
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Incomplete(
+                "max_output_tokens".into(),
+            )),
+        ]);
+        let mut response = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("Write a long story")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        let chunks: Vec<_> = response.stream.by_ref().collect().await;
+        assert!(matches!(
+            chunks.last(),
+            Some(LanguageModelStreamChunkType::Incomplete(reason)) if reason == "max_output_tokens"
+        ));
+        assert_eq!(
+            response.stop_reason(),
+            Some(StopReason::Incomplete("max_output_tokens".to_string()))
+        );
+        assert_eq!(model.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_streams_apart_from_text() {
+        use crate::test_utils::MockLanguageModel;
+
+        let delta = LanguageModelStreamChunk::Delta;
+        let model = MockLanguageModel::new().respond_stream(vec![
+            delta(LanguageModelStreamChunkType::Reasoning("The user ".into())),
+            delta(LanguageModelStreamChunkType::Reasoning("greets.".into())),
+            delta(LanguageModelStreamChunkType::Text("Hi!".into())),
+            LanguageModelStreamChunk::Done(AssistantMessage::new("Hi!".to_string().into(), None)),
+        ]);
+        let response = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("Hello")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        let deltas: Vec<(&str, String)> = response
+            .stream
+            .filter_map(|chunk| async move {
+                match chunk {
+                    LanguageModelStreamChunkType::Reasoning(r) => Some(("reasoning", r)),
+                    LanguageModelStreamChunkType::Text(t) => Some(("text", t)),
+                    _ => None,
+                }
+            })
+            .collect()
+            .await;
+        assert_eq!(
+            deltas,
+            vec![
+                ("reasoning", "The user ".to_string()),
+                ("reasoning", "greets.".to_string()),
+                ("text", "Hi!".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_candidates_are_assembled_by_index() {
+        use crate::test_utils::MockLanguageModel;
+
+        let text =
+            |t: &str| LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(t.into()));
+        let candidate = |index: usize, text: &str| {
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::CandidateText {
+                index,
+                text: text.into(),
+            })
+        };
+        let model = MockLanguageModel::new().respond_stream(vec![
+            text("Hel"),
+            candidate(1, "Wor"),
+            candidate(2, "Hey"),
+            candidate(1, "ld"),
+            text("lo"),
+            LanguageModelStreamChunk::Done(AssistantMessage::new("Hello".to_string().into(), None)),
+        ]);
+        let mut response = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("Greet me")
+            .n(3_u32)
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        assert!(response.candidates().is_empty());
+        while response.stream.next().await.is_some() {}
+        assert_eq!(response.candidates(), ["Hello", "World", "Hey"]);
+        let response = response.collect().await.unwrap();
+        assert_eq!(response.text(), Some("Hello".to_string()));
+        assert_eq!(response.candidates(), ["Hello", "World", "Hey"]);
     }
 }
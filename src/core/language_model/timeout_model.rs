@@ -0,0 +1,157 @@
+//! A language model enforcing timeouts on the requests of another model.
+
+use crate::core::language_model::{
+    Capabilities, LanguageModel, LanguageModelOptions, LanguageModelResponse, ProviderStream,
+};
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::time::Duration;
+
+/// Wraps a model, failing its requests with `Error::Timeout` when they take
+/// too long.
+///
+/// `generate_text` and opening a stream are limited to `timeout` in total,
+/// while an open stream fails once no chunk arrived for `idle_timeout`, which
+/// defaults to `timeout`.
+#[derive(Debug)]
+pub struct TimeoutModel<M: LanguageModel> {
+    model: M,
+    timeout: Duration,
+    idle_timeout: Duration,
+}
+
+impl<M: LanguageModel> TimeoutModel<M> {
+    /// Creates a model limiting requests of `model` to `timeout`.
+    pub fn new(model: M, timeout: Duration) -> Self {
+        Self {
+            model,
+            timeout,
+            idle_timeout: timeout,
+        }
+    }
+
+    /// Sets the longest time to wait for the next chunk of a stream.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Returns the wrapped model.
+    pub fn inner(&self) -> &M {
+        &self.model
+    }
+
+    fn timed_out(&self, what: &str, after: Duration) -> Error {
+        Error::Timeout(format!(
+            "{}/{} {what} after {after:?}",
+            self.model.provider_name(),
+            self.model.model_name()
+        ))
+    }
+}
+
+#[async_trait]
+impl<M: LanguageModel> LanguageModel for TimeoutModel<M> {
+    fn provider_name(&self) -> &str {
+        self.model.provider_name()
+    }
+
+    fn model_name(&self) -> String {
+        self.model.model_name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.model.capabilities()
+    }
+
+    fn settings_key(&self) -> String {
+        self.model.settings_key()
+    }
+
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        self.model.request_body(options)
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        tokio::time::timeout(self.timeout, self.model.generate_text(options))
+            .await
+            .map_err(|_| self.timed_out("did not respond", self.timeout))?
+    }
+
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        let stream = tokio::time::timeout(self.timeout, self.model.stream_text(options))
+            .await
+            .map_err(|_| self.timed_out("did not start streaming", self.timeout))??;
+
+        let idle_timeout = self.idle_timeout;
+        let idle_error = self.timed_out("sent no chunk", idle_timeout);
+
+        // ends the stream after the first timeout
+        let stream = futures::stream::unfold(
+            (stream, Some(idle_error)),
+            move |(mut stream, idle_error)| async move {
+                let idle_error = idle_error?;
+                match tokio::time::timeout(idle_timeout, stream.next()).await {
+                    Ok(Some(item)) => Some((item, (stream, Some(idle_error)))),
+                    Ok(None) => None,
+                    Err(_) => Some((Err(idle_error), (stream, None))),
+                }
+            },
+        );
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::language_model::{LanguageModelStreamChunk, LanguageModelStreamChunkType};
+    use crate::test_utils::MockLanguageModel;
+
+    #[tokio::test]
+    async fn test_generate_text_times_out() {
+        let slow = MockLanguageModel::new()
+            .respond_text("hello")
+            .with_delay(Duration::from_secs(5));
+
+        let model = TimeoutModel::new(slow, Duration::from_millis(20));
+        let result = model.generate_text(LanguageModelOptions::default()).await;
+
+        assert!(matches!(result, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_within_timeout() {
+        let model = TimeoutModel::new(
+            MockLanguageModel::new().respond_text("hello"),
+            Duration::from_secs(5),
+        );
+
+        assert!(
+            model
+                .generate_text(LanguageModelOptions::default())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_fails_on_idle_chunk() {
+        let text =
+            |t: &str| LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(t.into()));
+        let slow = MockLanguageModel::new()
+            .respond_stream(vec![text("a"), text("b")])
+            .with_chunk_delay(Duration::from_secs(5));
+
+        let model =
+            TimeoutModel::new(slow, Duration::from_secs(5)).idle_timeout(Duration::from_millis(20));
+        let mut stream = model
+            .stream_text(LanguageModelOptions::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(stream.next().await, Some(Err(Error::Timeout(_)))));
+        assert!(stream.next().await.is_none());
+    }
+}
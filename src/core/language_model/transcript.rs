@@ -0,0 +1,454 @@
+//! JSON Lines transcripts of a conversation.
+//!
+//! Every message is written as one JSON object per line with its step id,
+//! role and content, so a finished conversation can be stored for auditing or
+//! turned into a fine-tuning dataset independent of the provider used.
+
+use crate::core::language_model::{LanguageModelResponseContentType, Usage};
+use crate::core::messages::{AssistantMessage, ContentPart, Message, TaggedMessage, UserMessage};
+use crate::core::tools::{
+    BuiltinToolCallInfo, ToolCallInfo, ToolDetails, ToolResultInfo, ToolResultPart,
+};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// The role of a transcript entry.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TranscriptRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+    Developer,
+}
+
+/// A tool call made by the assistant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TranscriptToolCall {
+    id: String,
+    name: String,
+    input: Value,
+}
+
+/// A tool call executed by the provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TranscriptBuiltinToolCall {
+    id: String,
+    name: String,
+    output: Value,
+}
+
+/// Base64 encoded audio sent by the user or generated by the assistant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TranscriptAudio {
+    media_type: String,
+    data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transcript: Option<String>,
+}
+
+/// The result of a tool call. Either `output` or `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TranscriptToolResult {
+    id: String,
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    parts: Vec<TranscriptToolResultPart>,
+}
+
+/// A part of a tool result whose output contains more than text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TranscriptToolResultPart {
+    Text { text: String },
+    Image { media_type: String, data: String },
+}
+
+impl From<&ToolResultPart> for TranscriptToolResultPart {
+    fn from(part: &ToolResultPart) -> Self {
+        match part {
+            ToolResultPart::Text(text) => Self::Text { text: text.clone() },
+            ToolResultPart::Image { media_type, data } => Self::Image {
+                media_type: media_type.clone(),
+                data: data.clone(),
+            },
+        }
+    }
+}
+
+impl From<TranscriptToolResultPart> for ToolResultPart {
+    fn from(part: TranscriptToolResultPart) -> Self {
+        match part {
+            TranscriptToolResultPart::Text { text } => Self::Text(text),
+            TranscriptToolResultPart::Image { media_type, data } => {
+                Self::Image { media_type, data }
+            }
+        }
+    }
+}
+
+/// A single line of a transcript.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TranscriptEntry {
+    step_id: usize,
+    role: TranscriptRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// Milliseconds since the unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reasoning: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refusal: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    audio: Vec<TranscriptAudio>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    not_supported: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call: Option<TranscriptToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    builtin_tool_call: Option<TranscriptBuiltinToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_result: Option<TranscriptToolResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+}
+
+impl TranscriptEntry {
+    fn new(step_id: usize, role: TranscriptRole) -> Self {
+        Self {
+            step_id,
+            role,
+            id: None,
+            created_at: None,
+            content: None,
+            reasoning: None,
+            refusal: None,
+            audio: Vec::new(),
+            not_supported: None,
+            tool_call: None,
+            builtin_tool_call: None,
+            tool_result: None,
+            usage: None,
+        }
+    }
+}
+
+impl From<&TaggedMessage> for TranscriptEntry {
+    fn from(tagged: &TaggedMessage) -> Self {
+        let step_id = tagged.step_id;
+        let entry = match &tagged.message {
+            Message::System(s) => TranscriptEntry {
+                content: Some(s.content.clone()),
+                ..TranscriptEntry::new(step_id, TranscriptRole::System)
+            },
+            Message::User(u) => TranscriptEntry {
+                content: Some(u.content.clone()),
+                audio: u
+                    .parts
+                    .iter()
+                    .map(|ContentPart::Audio { media_type, data }| TranscriptAudio {
+                        media_type: media_type.clone(),
+                        data: data.clone(),
+                        transcript: None,
+                    })
+                    .collect(),
+                ..TranscriptEntry::new(step_id, TranscriptRole::User)
+            },
+            Message::Developer(d) => TranscriptEntry {
+                content: Some(d.clone()),
+                ..TranscriptEntry::new(step_id, TranscriptRole::Developer)
+            },
+            Message::Assistant(a) => {
+                let mut entry = TranscriptEntry {
+                    usage: a.usage.clone(),
+                    ..TranscriptEntry::new(step_id, TranscriptRole::Assistant)
+                };
+                match &a.content {
+                    LanguageModelResponseContentType::Text(text) => {
+                        entry.content = Some(text.clone())
+                    }
+                    LanguageModelResponseContentType::Reasoning(reason) => {
+                        entry.reasoning = Some(reason.clone())
+                    }
+                    LanguageModelResponseContentType::Refusal(refusal) => {
+                        entry.refusal = Some(refusal.clone())
+                    }
+                    LanguageModelResponseContentType::Audio {
+                        media_type,
+                        data,
+                        transcript,
+                    } => {
+                        entry.audio = vec![TranscriptAudio {
+                            media_type: media_type.clone(),
+                            data: data.clone(),
+                            transcript: transcript.clone(),
+                        }]
+                    }
+                    LanguageModelResponseContentType::NotSupported(other) => {
+                        entry.not_supported = Some(other.clone())
+                    }
+                    LanguageModelResponseContentType::ToolCall(call) => {
+                        entry.tool_call = Some(TranscriptToolCall {
+                            id: call.tool.id.clone(),
+                            name: call.tool.name.clone(),
+                            input: call.input.clone(),
+                        })
+                    }
+                    LanguageModelResponseContentType::BuiltinToolCall(call) => {
+                        entry.builtin_tool_call = Some(TranscriptBuiltinToolCall {
+                            id: call.tool.id.clone(),
+                            name: call.tool.name.clone(),
+                            output: call.output.clone(),
+                        })
+                    }
+                }
+                entry
+            }
+            Message::Tool(result) => {
+                let (output, error) = match result.error_message() {
+                    Some(error) => (None, Some(error)),
+                    None => (result.output.clone().ok(), None),
+                };
+                TranscriptEntry {
+                    tool_result: Some(TranscriptToolResult {
+                        id: result.tool.id.clone(),
+                        name: result.tool.name.clone(),
+                        output,
+                        error,
+                        parts: result.parts.iter().map(Into::into).collect(),
+                    }),
+                    ..TranscriptEntry::new(step_id, TranscriptRole::Tool)
+                }
+            }
+        };
+        TranscriptEntry {
+            id: tagged.id.clone(),
+            created_at: tagged
+                .created_at
+                .map(|t| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64),
+            ..entry
+        }
+    }
+}
+
+impl TryFrom<TranscriptEntry> for TaggedMessage {
+    type Error = Error;
+
+    fn try_from(entry: TranscriptEntry) -> Result<Self> {
+        let missing = |field: &str| {
+            Error::InvalidInput(format!(
+                "{:?} transcript entry is missing `{field}`",
+                entry.role
+            ))
+        };
+
+        let message = match entry.role {
+            TranscriptRole::System => Message::System(
+                entry
+                    .content
+                    .clone()
+                    .ok_or_else(|| missing("content"))?
+                    .into(),
+            ),
+            TranscriptRole::User => Message::User(UserMessage {
+                content: entry.content.clone().ok_or_else(|| missing("content"))?,
+                parts: entry
+                    .audio
+                    .iter()
+                    .map(|audio| ContentPart::audio(&audio.media_type, &audio.data))
+                    .collect(),
+            }),
+            TranscriptRole::Developer => {
+                Message::Developer(entry.content.clone().ok_or_else(|| missing("content"))?)
+            }
+            TranscriptRole::Assistant => {
+                let content = if let Some(call) = entry.tool_call.clone() {
+                    LanguageModelResponseContentType::ToolCall(ToolCallInfo {
+                        tool: ToolDetails {
+                            name: call.name,
+                            id: call.id,
+                        },
+                        input: call.input,
+                    })
+                } else if let Some(call) = entry.builtin_tool_call.clone() {
+                    LanguageModelResponseContentType::BuiltinToolCall(BuiltinToolCallInfo {
+                        tool: ToolDetails {
+                            name: call.name,
+                            id: call.id,
+                        },
+                        output: call.output,
+                    })
+                } else if let Some(reason) = entry.reasoning.clone() {
+                    LanguageModelResponseContentType::Reasoning(reason)
+                } else if let Some(refusal) = entry.refusal.clone() {
+                    LanguageModelResponseContentType::Refusal(refusal)
+                } else if let Some(audio) = entry.audio.first().cloned() {
+                    LanguageModelResponseContentType::Audio {
+                        media_type: audio.media_type,
+                        data: audio.data,
+                        transcript: audio.transcript,
+                    }
+                } else if let Some(other) = entry.not_supported.clone() {
+                    LanguageModelResponseContentType::NotSupported(other)
+                } else {
+                    LanguageModelResponseContentType::Text(
+                        entry.content.clone().ok_or_else(|| missing("content"))?,
+                    )
+                };
+                Message::Assistant(AssistantMessage::new(content, entry.usage.clone()))
+            }
+            TranscriptRole::Tool => {
+                let result = entry
+                    .tool_result
+                    .clone()
+                    .ok_or_else(|| missing("tool_result"))?;
+                let mut info = ToolResultInfo::new(result.name);
+                info.id(result.id);
+                match result.error {
+                    Some(error) => info.error(error),
+                    None => info.output(result.output.unwrap_or(Value::Null)),
+                }
+                info.parts = result.parts.into_iter().map(Into::into).collect();
+                Message::Tool(info)
+            }
+        };
+
+        Ok(TaggedMessage {
+            step_id: entry.step_id,
+            message,
+            created_at: entry
+                .created_at
+                .map(|millis| UNIX_EPOCH + Duration::from_millis(millis)),
+            id: entry.id,
+        })
+    }
+}
+
+/// Serializes messages to JSON Lines, one message per line.
+pub(crate) fn to_jsonl(messages: &[TaggedMessage]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            serde_json::to_string(&TranscriptEntry::from(m))
+                .expect("transcript entries are always serializable")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parses messages from JSON Lines. Empty lines are skipped.
+pub(crate) fn from_jsonl(jsonl: &str) -> Result<Vec<TaggedMessage>> {
+    jsonl
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let entry: TranscriptEntry = serde_json::from_str(line).map_err(|e| {
+                Error::InvalidInput(format!("Invalid transcript line {}: {e}", i + 1))
+            })?;
+            entry.try_into()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_jsonl_rejects_invalid_lines() {
+        let result = from_jsonl("{\"step_id\":0,\"role\":\"user\",\"content\":\"hi\"}\nnot json");
+        assert!(matches!(result, Err(Error::InvalidInput(e)) if e.contains("line 2")));
+
+        let result = from_jsonl("{\"step_id\":0,\"role\":\"tool\"}");
+        assert!(matches!(result, Err(Error::InvalidInput(e)) if e.contains("tool_result")));
+    }
+
+    #[test]
+    fn test_tool_error_round_trip() {
+        let mut result = ToolResultInfo::new("search");
+        result.tool.id = "call_1".to_string();
+        result.output = Err(Error::ToolCallError("timeout".to_string()));
+
+        let jsonl = to_jsonl(&[TaggedMessage::new(1, Message::Tool(result))]);
+        let messages = from_jsonl(&jsonl).unwrap();
+
+        match &messages[0].message {
+            Message::Tool(info) => {
+                assert_eq!(info.tool.id, "call_1");
+                assert!(info.is_error);
+                assert_eq!(info.error_message().unwrap(), "Tool error: timeout");
+            }
+            other => panic!("expected tool message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_tool_result_parts_round_trip() {
+        let mut result = ToolResultInfo::new("screenshot");
+        result.tool.id = "call_1".to_string();
+        result.output_parts(vec![
+            ToolResultPart::Text("The page".to_string()),
+            ToolResultPart::image("image/png", "iVBORw0KGgo="),
+        ]);
+
+        let jsonl = to_jsonl(&[TaggedMessage::new(1, Message::Tool(result.clone()))]);
+        let messages = from_jsonl(&jsonl).unwrap();
+
+        match &messages[0].message {
+            Message::Tool(info) => {
+                assert_eq!(info.parts, result.parts);
+                assert_eq!(info.output.as_ref().unwrap(), "The page");
+            }
+            other => panic!("expected tool message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let appended = TaggedMessage::new(1, Message::User("hi".into()));
+        let jsonl = to_jsonl(&[
+            appended.clone(),
+            TaggedMessage::initial_step_msg(Message::User("hello".into())),
+        ]);
+        assert!(
+            jsonl
+                .lines()
+                .nth(1)
+                .is_some_and(|line| !line.contains("created_at"))
+        );
+
+        let messages = from_jsonl(&jsonl).unwrap();
+        assert_eq!(messages[0].id, appended.id);
+        assert_eq!(
+            messages[0]
+                .created_at
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+            appended
+                .created_at
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        );
+        assert!(messages[1].id.is_none() && messages[1].created_at.is_none());
+
+        // transcripts written before the metadata existed still load
+        let messages = from_jsonl("{\"step_id\":0,\"role\":\"user\",\"content\":\"hi\"}").unwrap();
+        assert!(messages[0].id.is_none());
+    }
+}
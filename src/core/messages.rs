@@ -1,5 +1,8 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::core::{
-    ToolCallInfo, ToolResultInfo,
+    BuiltinToolCallInfo, ToolCallInfo, ToolResultInfo,
     language_model::{LanguageModelResponseContentType, Usage},
 };
 
@@ -61,6 +64,62 @@ impl Message {
     pub fn builder() -> MessageBuilder<Initial> {
         MessageBuilder::default()
     }
+
+    /// Creates a text message with the given role.
+    ///
+    /// # Example
+    /// ```
+    /// use aisdk::core::{Message, Role};
+    ///
+    /// let message = Message::from_role(Role::User, "Hello!");
+    /// assert_eq!(message.text(), Some("Hello!"));
+    /// ```
+    pub fn from_role(role: Role, content: impl Into<String>) -> Self {
+        let content = content.into();
+        match role {
+            Role::System => Message::System(content.into()),
+            Role::User => Message::User(content.into()),
+            Role::Assistant => Message::Assistant(content.into()),
+        }
+    }
+
+    /// Returns the text of the message. `None` for assistant turns that are
+    /// not text, e.g. tool calls, and for tool results.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Message::System(s) => Some(&s.content),
+            Message::User(u) => Some(&u.content),
+            Message::Assistant(a) => a.text(),
+            Message::Developer(d) => Some(d),
+            Message::Tool(_) => None,
+        }
+    }
+
+    /// Returns the call if the message is an assistant tool call turn.
+    pub fn tool_call(&self) -> Option<&ToolCallInfo> {
+        match self {
+            Message::Assistant(a) => a.tool_call(),
+            _ => None,
+        }
+    }
+}
+
+/// Renders the message as a transcript line prefixed with its role, e.g.
+/// `user: Hello!` or `tool get_weather: "sunny"`.
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::System(s) => write!(f, "system: {}", s.content),
+            Message::User(u) => write!(f, "user: {}", u.content),
+            Message::Assistant(a) => write!(f, "assistant: {}", a.content),
+            Message::Developer(d) => write!(f, "developer: {d}"),
+            Message::Tool(result) => match (result.error_message(), &result.output) {
+                (Some(error), _) => write!(f, "tool {} failed: {error}", result.tool.name),
+                (None, Ok(output)) => write!(f, "tool {}: {output}", result.tool.name),
+                (None, Err(_)) => write!(f, "tool {}", result.tool.name),
+            },
+        }
+    }
 }
 
 /// System message.
@@ -93,12 +152,38 @@ impl From<&str> for SystemMessage {
 #[derive(Debug, Clone)]
 pub struct UserMessage {
     pub content: String,
+    /// Parts sent along with the text, e.g. a recorded question.
+    pub parts: Vec<ContentPart>,
 }
 
 impl UserMessage {
     pub fn new(content: impl Into<String>) -> Self {
         Self {
             content: content.into(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Adds base64 encoded audio, e.g. `audio/wav`, to the message.
+    pub fn with_audio(mut self, media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        self.parts.push(ContentPart::audio(media_type, data));
+        self
+    }
+}
+
+/// A non-text part of a user message. Providers without support for a part
+/// warn and send the text only.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentPart {
+    /// Base64 encoded audio.
+    Audio { media_type: String, data: String },
+}
+
+impl ContentPart {
+    pub fn audio(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self::Audio {
+            media_type: media_type.into(),
+            data: data.into(),
         }
     }
 }
@@ -139,6 +224,26 @@ impl AssistantMessage {
     pub fn new(content: LanguageModelResponseContentType, usage: Option<Usage>) -> Self {
         Self { content, usage }
     }
+
+    /// Returns the text if this is a text turn.
+    pub fn text(&self) -> Option<&str> {
+        match &self.content {
+            LanguageModelResponseContentType::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the call if this is a tool call turn.
+    pub fn tool_call(&self) -> Option<&ToolCallInfo> {
+        match &self.content {
+            LanguageModelResponseContentType::ToolCall(call) => Some(call),
+            _ => None,
+        }
+    }
+
+    pub fn is_tool_call(&self) -> bool {
+        self.tool_call().is_some()
+    }
 }
 
 /// Message State for type safe message list construction.
@@ -218,25 +323,66 @@ impl MessageBuilder<Conversation> {
             state: std::marker::PhantomData,
         }
     }
+
+    /// Adds an assistant turn calling a tool, e.g. to restore a stored history.
+    pub fn tool_call(mut self, call: ToolCallInfo) -> MessageBuilder<Conversation> {
+        self.messages.push(Message::Assistant(AssistantMessage::new(
+            LanguageModelResponseContentType::ToolCall(call),
+            None,
+        )));
+        MessageBuilder {
+            messages: self.messages,
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Adds the result of a tool call.
+    pub fn tool_result(mut self, result: ToolResultInfo) -> MessageBuilder<Conversation> {
+        self.messages.push(Message::Tool(result));
+        MessageBuilder {
+            messages: self.messages,
+            state: std::marker::PhantomData,
+        }
+    }
 }
 
 /// A message tagged with its step id in a list of messages
 /// used for tracking steps in a conversation
 #[derive(Debug, Clone)]
-pub(crate) struct TaggedMessage {
+pub struct TaggedMessage {
     pub step_id: usize,
     pub message: Message,
+    /// When the message was appended, unset for pre-supplied messages.
+    pub created_at: Option<SystemTime>,
+    /// A unique id of the message, unset for pre-supplied messages.
+    pub id: Option<String>,
 }
 
 impl TaggedMessage {
+    /// Tags a message appended during generation with a timestamp and an id.
     pub fn new(step_id: usize, message: Message) -> Self {
-        Self { step_id, message }
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let created_at = SystemTime::now();
+        let millis = created_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let id = format!("msg_{millis:x}_{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        Self {
+            step_id,
+            message,
+            created_at: Some(created_at),
+            id: Some(id),
+        }
     }
 
     pub fn initial_step_msg(message: Message) -> Self {
         Self {
             step_id: 0,
             message,
+            created_at: None,
+            id: None,
         }
     }
 }
@@ -258,18 +404,27 @@ impl From<TaggedMessage> for Message {
 /// Helper trait for extracting messages from TaggedMessage collections
 pub(crate) trait TaggedMessageHelpers {
     fn extract_tool_calls(&self) -> Option<Vec<ToolCallInfo>>;
+    fn extract_builtin_tool_calls(&self) -> Option<Vec<BuiltinToolCallInfo>>;
     fn extract_tool_results(&self) -> Option<Vec<ToolResultInfo>>;
 }
 
 impl TaggedMessageHelpers for [TaggedMessage] {
     fn extract_tool_calls(&self) -> Option<Vec<ToolCallInfo>> {
         let calls: Vec<ToolCallInfo> = self
+            .iter()
+            .filter_map(|msg| msg.message.tool_call().cloned())
+            .collect();
+        if calls.is_empty() { None } else { Some(calls) }
+    }
+
+    fn extract_builtin_tool_calls(&self) -> Option<Vec<BuiltinToolCallInfo>> {
+        let calls: Vec<BuiltinToolCallInfo> = self
             .iter()
             .filter_map(|msg| match msg.message {
                 Message::Assistant(AssistantMessage {
-                    content: LanguageModelResponseContentType::ToolCall(ref tool_info),
+                    content: LanguageModelResponseContentType::BuiltinToolCall(ref info),
                     ..
-                }) => Some(tool_info.clone()),
+                }) => Some(info.clone()),
                 _ => None,
             })
             .collect();
@@ -291,3 +446,132 @@ impl TaggedMessageHelpers for [TaggedMessage] {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|m| match (m, m.text(), m.tool_call()) {
+                (Message::User(_), Some(text), _) => format!("user: {text}"),
+                (Message::Assistant(_), Some(text), _) => format!("assistant: {text}"),
+                (_, _, Some(call)) => format!("call: {}({})", call.tool.name, call.input),
+                (Message::Tool(result), ..) => {
+                    format!("result: {}", result.output.as_ref().unwrap())
+                }
+                (other, ..) => panic!("unexpected message {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_history_with_text_and_tool_call_turns() {
+        let mut call = ToolCallInfo::new("get_weather");
+        call.id("call_1");
+        call.input(serde_json::json!({ "city": "Paris" }));
+        let mut result = ToolResultInfo::new("get_weather");
+        result.id("call_1");
+        result.output(serde_json::json!("sunny"));
+
+        let messages = Message::conversation_builder()
+            .user("Weather in Paris?")
+            .assistant("Let me check.")
+            .tool_call(call)
+            .tool_result(result)
+            .assistant("It is sunny.")
+            .build();
+
+        assert_eq!(
+            render(&messages),
+            vec![
+                "user: Weather in Paris?",
+                "assistant: Let me check.",
+                "call: get_weather({\"city\":\"Paris\"})",
+                "result: \"sunny\"",
+                "assistant: It is sunny.",
+            ]
+        );
+
+        let tagged: Vec<TaggedMessage> = messages.into_iter().map(Into::into).collect();
+        let calls = tagged.extract_tool_calls().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].tool.id, "call_1");
+    }
+
+    #[test]
+    fn test_assistant_message_accessors() {
+        let text = AssistantMessage::from("hello".to_string());
+        assert_eq!(text.text(), Some("hello"));
+        assert!(text.tool_call().is_none() && !text.is_tool_call());
+
+        let call = AssistantMessage::new(
+            LanguageModelResponseContentType::ToolCall(ToolCallInfo::new("search")),
+            None,
+        );
+        assert_eq!(call.text(), None);
+        assert!(call.is_tool_call());
+        assert_eq!(call.tool_call().unwrap().tool.name, "search");
+    }
+
+    #[test]
+    fn test_from_role() {
+        let messages: Vec<Message> = [
+            (Role::System, "Be brief"),
+            (Role::User, "hi"),
+            (Role::Assistant, "hello"),
+        ]
+        .into_iter()
+        .map(|(role, text)| Message::from_role(role, text))
+        .collect();
+
+        assert!(matches!(
+            &messages[..],
+            [Message::System(_), Message::User(_), Message::Assistant(_)]
+        ));
+        let texts: Vec<_> = messages.iter().filter_map(Message::text).collect();
+        assert_eq!(texts, vec!["Be brief", "hi", "hello"]);
+    }
+
+    #[test]
+    fn test_display_renders_transcript_lines() {
+        let mut call = ToolCallInfo::new("get_weather");
+        call.input(serde_json::json!({ "city": "Paris" }));
+        let mut result = ToolResultInfo::new("get_weather");
+        result.output(serde_json::json!("sunny"));
+        let mut failed = ToolResultInfo::new("get_weather");
+        failed.error("city not found");
+
+        let lines: Vec<String> = [
+            Message::System("Be brief".into()),
+            Message::User("Weather in Paris?".into()),
+            Message::Assistant(AssistantMessage::new(
+                LanguageModelResponseContentType::ToolCall(call),
+                None,
+            )),
+            Message::Tool(result),
+            Message::Tool(failed),
+            Message::Assistant("It is sunny.".to_string().into()),
+        ]
+        .iter()
+        .map(Message::to_string)
+        .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "system: Be brief",
+                "user: Weather in Paris?",
+                r#"assistant: get_weather({"city":"Paris"})"#,
+                r#"tool get_weather: "sunny""#,
+                "tool get_weather failed: city not found",
+                "assistant: It is sunny.",
+            ]
+        );
+        assert_eq!(
+            LanguageModelResponseContentType::Reasoning("thinking".into()).to_string(),
+            "[reasoning] thinking"
+        );
+    }
+}
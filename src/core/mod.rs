@@ -7,9 +7,18 @@
 //! Key types like `GenerateTextCallOptions` and `GenerateTextResponse` are also
 //! re-exported for convenient access.
 
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod history;
+pub mod image;
 pub mod language_model;
 pub mod messages;
 pub mod provider;
+pub mod retry;
+pub mod stop_when;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod tokens;
 pub mod tools;
 pub mod utils;
 
@@ -17,10 +26,23 @@ pub use aisdk_macros::tool;
 
 // Re-export key components to provide a clean public API.
 pub use language_model::{
-    LanguageModel, LanguageModelStreamChunkType, generate_text::GenerateTextResponse,
-    request::LanguageModelRequest, stream_text::StreamTextResponse,
+    Capabilities, LanguageModel, LanguageModelStreamChunkType, ServedBy, StreamHandle,
+    batch::{compare, generate_batch},
+    fallback::FallbackModel,
+    generate_text::GenerateTextResponse,
+    load_balanced::LoadBalancedModel,
+    request::LanguageModelRequest,
+    retry_model::RetryModel,
+    stream_object::{ObjectStreamChunk, StreamObjectResponse},
+    stream_text::{StreamEvent, StreamTextResponse},
+    timeout_model::TimeoutModel,
 };
 
-pub use messages::{AssistantMessage, Message, Role, SystemMessage, UserMessage};
+pub use history::HistoryPolicy;
+pub use image::{GeneratedImage, ImageModel, ImageOptions, ImageResponse};
+pub use messages::{
+    AssistantMessage, ContentPart, Message, Role, SystemMessage, TaggedMessage, UserMessage,
+};
 pub use provider::Provider;
-pub use tools::{Tool, ToolCallInfo, ToolResultInfo};
+pub use retry::RetryPolicy;
+pub use tools::{BuiltinToolCallInfo, Tool, ToolCallInfo, ToolResultInfo, ToolResultPart};
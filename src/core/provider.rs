@@ -4,6 +4,13 @@
 //! different AI providers like OpenAI, Anthropic, or Google.
 
 use crate::core::language_model::LanguageModel;
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Hook called with the final json body of a provider request just before it
+/// is sent. The body can be inspected or modified in place.
+pub type BeforeRequestHook = Arc<dyn Fn(&mut serde_json::Value) + Send + Sync>;
 
 /// A marker trait representing a fully configured AI provider.
 ///
@@ -12,4 +19,16 @@ use crate::core::language_model::LanguageModel;
 ///
 /// By implementing `Provider`, a type signals that it is a complete and ready-to-use
 /// client for interacting with a specific AI service.
-pub trait Provider: Send + Sync + LanguageModel {}
+#[async_trait]
+pub trait Provider: Send + Sync + LanguageModel {
+    /// Lists the ids of the models available to the configured credentials.
+    ///
+    /// This is a cheap way to verify an api key before sending larger
+    /// requests. Providers without a models endpoint return an error.
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Err(Error::Other(format!(
+            "Listing models is not supported by {}",
+            self.provider_name()
+        )))
+    }
+}
@@ -0,0 +1,170 @@
+//! Configurable retries for failed language model calls.
+//!
+//! A `RetryPolicy` controls how many times a failed call is retried, how long
+//! to wait between attempts and which errors are worth retrying. Delays grow
+//! exponentially from `base_delay` up to `max_delay`, with optional jitter to
+//! avoid many clients retrying in lockstep.
+
+use crate::error::Error;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Decides whether a failed call should be retried.
+pub type RetryOnHook = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+
+/// Retry behaviour for language model calls.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the first attempt.
+    pub max_retries: u32,
+
+    /// Delay before the first retry. Doubles with every attempt.
+    pub base_delay: Duration,
+
+    /// Upper bound for the delay between attempts.
+    pub max_delay: Duration,
+
+    /// Fraction of each delay, between 0.0 and 1.0, that is randomly
+    /// subtracted from it.
+    pub jitter: f64,
+
+    /// Returns true if an error should be retried. Defaults to
+    /// `Error::is_retryable`.
+    pub retry_on: RetryOnHook,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.2,
+            retry_on: Arc::new(Error::is_retryable),
+        }
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_retries", &self.max_retries)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the predicate deciding which errors are retried.
+    pub fn retry_on<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.retry_on = Arc::new(hook);
+        self
+    }
+
+    /// Returns true if the call that failed with `error` on the zero based
+    /// `attempt` should be retried.
+    pub fn should_retry(&self, error: &Error, attempt: u32) -> bool {
+        attempt < self.max_retries && (self.retry_on)(error)
+    }
+
+    /// Returns the delay to wait after the zero based `attempt` failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+
+        delay.mul_f64(1.0 - jitter * fastrand::f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ProviderError;
+
+    #[test]
+    fn test_delay_grows_exponentially_without_jitter() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter: 0.0,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(40), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_delay_with_jitter_stays_within_bounds() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        for attempt in 0..5 {
+            let upper = Duration::from_millis(100 * 2_u64.pow(attempt)).min(policy.max_delay);
+            let lower = upper.mul_f64(0.5);
+            for _ in 0..100 {
+                let delay = policy.delay(attempt);
+                assert!(delay >= lower && delay <= upper, "{delay:?} out of bounds");
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_retry_respects_max_retries() {
+        let policy = RetryPolicy::default().retry_on(|_| true);
+        let error = Error::Other("boom".to_string());
+
+        assert!(policy.should_retry(&error, 0));
+        assert!(policy.should_retry(&error, 1));
+        assert!(!policy.should_retry(&error, 2));
+        assert!(!RetryPolicy::none().should_retry(&error, 0));
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("service unavailable")]
+    struct Unavailable;
+
+    impl ProviderError for Unavailable {
+        fn is_retryable(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_custom_retry_on_prevents_retry() {
+        let error = Error::ProviderError(Arc::new(Unavailable));
+        assert!(error.is_retryable());
+        assert!(RetryPolicy::default().should_retry(&error, 0));
+
+        let policy = RetryPolicy::default().retry_on(|_| false);
+        assert!(!policy.should_retry(&error, 0));
+    }
+}
@@ -0,0 +1,91 @@
+//! Ready made conditions for `stop_when`.
+//!
+//! ```
+//! use aisdk::core::stop_when;
+//!
+//! let hook = stop_when::all_tools_called(["search", "fetch"]);
+//! ```
+
+use crate::core::language_model::LanguageModelOptions;
+
+/// Returns a `stop_when` hook that stops once every named tool has produced
+/// a result at least once.
+pub fn all_tools_called<I, S>(names: I) -> impl Fn(&LanguageModelOptions) -> bool + Send + Sync
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let names: Vec<String> = names.into_iter().map(Into::into).collect();
+
+    move |options: &LanguageModelOptions| {
+        let results = options.tool_results().unwrap_or_default();
+        names
+            .iter()
+            .all(|name| results.iter().any(|result| &result.tool.name == name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::LanguageModelRequest;
+    use crate::core::language_model::StopReason;
+    use crate::test_utils::{MockLanguageModel, tool, tool_call};
+
+    #[tokio::test]
+    async fn test_all_tools_called_stops_loop() {
+        // a third round would fail as no response is queued
+        let model = MockLanguageModel::new()
+            .respond(Ok(tool_call("search", "call_1")))
+            .respond(Ok(tool_call("fetch", "call_2")));
+
+        let result = LanguageModelRequest::builder()
+            .model(model.clone())
+            .prompt("Research rust")
+            .with_tool(tool("search", "ok"))
+            .with_tool(tool("fetch", "ok"))
+            .stop_when(all_tools_called(["search", "fetch"]))
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(model.calls().len(), 2);
+        assert_eq!(
+            result.stop_reason(),
+            Some(StopReason::Hook("Stopped by hook".to_string()))
+        );
+        assert_eq!(result.tool_results().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_all_tools_called_without_results() {
+        let hook = all_tools_called(["search"]);
+        assert!(!hook(&LanguageModelOptions::default()));
+    }
+
+    #[tokio::test]
+    async fn test_stop_when_with_reason_is_recorded() {
+        let model = MockLanguageModel::new().respond(Ok(tool_call("search", "call_1")));
+
+        let result = LanguageModelRequest::builder()
+            .model(model)
+            .prompt("Research rust")
+            .with_tool(tool("search", "ok"))
+            .stop_when_with_reason(|options| {
+                options
+                    .tool_results()
+                    .is_some()
+                    .then(|| "search budget used up".to_string())
+            })
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.stop_reason(),
+            Some(StopReason::Hook("search budget used up".to_string()))
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! OpenTelemetry spans for generations, enabled by the `otel` feature.
+//!
+//! Each call to a provider in `generate_text` and `stream_text` is recorded
+//! as a client span with the GenAI semantic convention attributes (`gen_ai.*`):
+//! the provider, the requested model and sampling options, the token usage
+//! and the finish reason. Spans are created with the global tracer named
+//! `TRACER_NAME`, install a tracer provider with
+//! `opentelemetry::global::set_tracer_provider` to export them.
+//!
+//! Responses served from a cache are not recorded, as no provider is called.
+
+use crate::core::language_model::{
+    LanguageModelOptions, LanguageModelResponse, LanguageModelResponseContentType, Usage,
+};
+use crate::error::Error;
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::{Array, KeyValue, StringValue, Value};
+
+/// The name of the tracer generation spans are created with.
+pub const TRACER_NAME: &str = "aisdk";
+
+/// The span of a single provider call.
+pub(crate) struct GenerationSpan {
+    span: BoxedSpan,
+}
+
+impl GenerationSpan {
+    /// Starts the span of a call to `model` of `provider` with `options`.
+    pub(crate) fn start(provider: &str, model: &str, options: &LanguageModelOptions) -> Self {
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.operation.name", "chat"),
+            KeyValue::new("gen_ai.system", provider.to_string()),
+            KeyValue::new("gen_ai.provider.name", provider.to_string()),
+            KeyValue::new("gen_ai.request.model", model.to_string()),
+        ];
+        if let Some(max_tokens) = options.max_output_tokens {
+            attributes.push(KeyValue::new(
+                "gen_ai.request.max_tokens",
+                i64::from(max_tokens),
+            ));
+        }
+        if let Some(temperature) = options.temperature {
+            attributes.push(KeyValue::new(
+                "gen_ai.request.temperature",
+                f64::from(temperature) / 100.0,
+            ));
+        }
+        if let Some(top_p) = options.top_p {
+            attributes.push(KeyValue::new(
+                "gen_ai.request.top_p",
+                f64::from(top_p) / 100.0,
+            ));
+        }
+
+        let tracer = global::tracer(TRACER_NAME);
+        let span = tracer
+            .span_builder(format!("chat {model}"))
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes)
+            .start(&tracer);
+        Self { span }
+    }
+
+    /// Ends the span with the usage and finish reason of a response.
+    pub(crate) fn end_response(self, response: &LanguageModelResponse) {
+        let reason = match &response.incomplete {
+            Some(reason) => reason.as_str(),
+            None => response
+                .contents
+                .iter()
+                .map(finish_reason)
+                .find(|reason| *reason != "stop")
+                .unwrap_or("stop"),
+        };
+        self.end(response.usage.as_ref(), reason);
+    }
+
+    /// Ends the span with the usage and finish reason of the call.
+    pub(crate) fn end(mut self, usage: Option<&Usage>, finish_reason: &str) {
+        if let Some(usage) = usage {
+            if let Some(input_tokens) = usage.input_tokens {
+                self.span.set_attribute(KeyValue::new(
+                    "gen_ai.usage.input_tokens",
+                    input_tokens as i64,
+                ));
+            }
+            if let Some(output_tokens) = usage.output_tokens {
+                self.span.set_attribute(KeyValue::new(
+                    "gen_ai.usage.output_tokens",
+                    output_tokens as i64,
+                ));
+            }
+        }
+        self.span.set_attribute(KeyValue::new(
+            "gen_ai.response.finish_reasons",
+            Value::Array(Array::String(vec![StringValue::from(
+                finish_reason.to_string(),
+            )])),
+        ));
+        self.span.end();
+    }
+
+    /// Ends the span of a failed call.
+    pub(crate) fn fail(mut self, error: &Error) {
+        self.span.set_status(Status::error(error.to_string()));
+        self.span.end();
+    }
+}
+
+/// Returns the finish reason of a generated content.
+pub(crate) fn finish_reason(content: &LanguageModelResponseContentType) -> &'static str {
+    match content {
+        LanguageModelResponseContentType::ToolCall(_) => "tool_calls",
+        LanguageModelResponseContentType::Refusal(_) => "content_filter",
+        _ => "stop",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::LanguageModelRequest;
+    use crate::core::language_model::LanguageModelResponse;
+    use crate::test_utils::MockLanguageModel;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+    #[tokio::test]
+    async fn test_generation_span_attributes() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        global::set_tracer_provider(provider);
+
+        let mut response = LanguageModelResponse::new("Hello!");
+        response.usage = Some(Usage {
+            input_tokens: Some(11),
+            output_tokens: Some(7),
+            ..Default::default()
+        });
+        LanguageModelRequest::builder()
+            .model(MockLanguageModel::new().respond(Ok(response)))
+            .prompt("Hi")
+            .top_p(90_u32)
+            .temperature(20_u32)
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans
+            .iter()
+            .find(|span| {
+                span.attributes
+                    .contains(&KeyValue::new("gen_ai.usage.input_tokens", 11_i64))
+            })
+            .expect("generation span should be exported");
+
+        assert_eq!(span.name, "chat mock-model");
+        assert_eq!(span.span_kind, SpanKind::Client);
+        assert_eq!(span.instrumentation_scope.name(), TRACER_NAME);
+        for attribute in [
+            KeyValue::new("gen_ai.operation.name", "chat"),
+            KeyValue::new("gen_ai.system", "mock"),
+            KeyValue::new("gen_ai.request.model", "mock-model"),
+            KeyValue::new("gen_ai.request.top_p", 0.9),
+            KeyValue::new("gen_ai.request.temperature", 0.2),
+            KeyValue::new("gen_ai.usage.output_tokens", 7_i64),
+            KeyValue::new(
+                "gen_ai.response.finish_reasons",
+                Value::Array(Array::String(vec!["stop".into()])),
+            ),
+        ] {
+            assert!(
+                span.attributes.contains(&attribute),
+                "missing {attribute:?} in {:?}",
+                span.attributes
+            );
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Heuristic token counting for estimating the size of a request before it
+//! is sent to a language model.
+//!
+//! The estimates are tokenizer agnostic. They assume roughly four characters
+//! per token, which is close enough for english text with most providers, and
+//! are meant for budget checks rather than exact accounting.
+
+use crate::core::Message;
+use crate::core::language_model::{LanguageModelOptions, LanguageModelResponseContentType};
+use crate::core::messages::TaggedMessage;
+use crate::core::tools::ToolResultPart;
+
+/// Average number of characters per token used by the estimator.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens added for every message to account for role and formatting overhead.
+pub const TOKENS_PER_MESSAGE: usize = 4;
+
+/// Tokens counted for every image, about what providers charge for a
+/// medium sized one.
+pub const TOKENS_PER_IMAGE: usize = 765;
+
+/// Estimates the number of tokens in a piece of text.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Estimates the number of tokens in a single message, including overhead.
+pub fn count_message_tokens(message: &Message) -> usize {
+    let content = match message {
+        Message::System(s) => estimate_tokens(&s.content),
+        Message::User(u) => estimate_tokens(&u.content),
+        Message::Developer(d) => estimate_tokens(d),
+        Message::Assistant(a) => match &a.content {
+            LanguageModelResponseContentType::Text(t)
+            | LanguageModelResponseContentType::Reasoning(t)
+            | LanguageModelResponseContentType::Refusal(t) => estimate_tokens(t),
+            LanguageModelResponseContentType::ToolCall(info) => {
+                estimate_tokens(&info.tool.name) + estimate_tokens(&info.input.to_string())
+            }
+            LanguageModelResponseContentType::Audio { transcript, .. } => {
+                estimate_tokens(transcript.as_deref().unwrap_or_default())
+            }
+            LanguageModelResponseContentType::BuiltinToolCall(_)
+            | LanguageModelResponseContentType::NotSupported(_) => 0,
+        },
+        Message::Tool(info) => {
+            let output = match &info.output {
+                Ok(output) => estimate_tokens(&output.to_string()),
+                Err(err) => estimate_tokens(&err.to_string()),
+            };
+            // the text parts are counted as part of `output`
+            let images = info
+                .parts
+                .iter()
+                .filter(|part| matches!(part, ToolResultPart::Image { .. }))
+                .count();
+            output + images * TOKENS_PER_IMAGE
+        }
+    };
+
+    content + TOKENS_PER_MESSAGE
+}
+
+/// Estimates the number of input tokens a request will use. Counts the system
+/// prompt, every message and the name, description and schema of each tool.
+/// A system prompt that is also one of the messages is counted once.
+pub fn count_tokens(options: &LanguageModelOptions) -> usize {
+    let messages: usize = options
+        .messages
+        .iter()
+        .map(|m| count_message_tokens(&m.message))
+        .sum();
+
+    count_system_tokens(options.system.as_deref(), &options.messages)
+        + messages
+        + count_tool_tokens(options)
+}
+
+/// Estimates the number of tokens in the system prompt, or zero if
+/// `messages` already contain it as a system message.
+pub(crate) fn count_system_tokens(system: Option<&str>, messages: &[TaggedMessage]) -> usize {
+    match system {
+        Some(system)
+            if !messages
+                .iter()
+                .any(|m| matches!(m.message, Message::System(ref s) if s.content == system)) =>
+        {
+            estimate_tokens(system) + TOKENS_PER_MESSAGE
+        }
+        _ => 0,
+    }
+}
+
+/// Estimates the number of tokens in the name, description and schema of
+/// each tool of a request.
+pub fn count_tool_tokens(options: &LanguageModelOptions) -> usize {
+    options
+        .tools
+        .as_ref()
+        .map(|list| {
+            list.tools
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .iter()
+                .map(|tool| {
+                    estimate_tokens(&tool.name)
+                        + estimate_tokens(&tool.description)
+                        + estimate_tokens(&tool.input_schema.as_value().to_string())
+                })
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns true if the estimated request size fits within `context_window` tokens.
+pub fn fits_context(options: &LanguageModelOptions, context_window: usize) -> bool {
+    count_tokens(options) <= context_window
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tools::{Tool, ToolList};
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abc"), 1);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[test]
+    fn test_count_tokens_known_message_set() {
+        let options = LanguageModelOptions {
+            system: Some("You are helpful.".to_string()), // 16 chars -> 4 tokens
+            messages: vec![
+                TaggedMessage::initial_step_msg(Message::User("What is the time?".into())), // 17 chars -> 5 tokens
+                TaggedMessage::initial_step_msg(Message::Assistant(
+                    "It is noon.".to_string().into(), // 11 chars -> 3 tokens
+                )),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(count_tokens(&options), 4 + 5 + 3 + 3 * TOKENS_PER_MESSAGE);
+    }
+
+    #[test]
+    fn test_count_message_tokens_counts_tool_result_images() {
+        let mut result = crate::core::ToolResultInfo::new("screenshot");
+        result.output_parts(vec![
+            ToolResultPart::Text("The page".to_string()), // "\"The page\"" 10 chars -> 3 tokens
+            ToolResultPart::image("image/png", "iVBORw0KGgo="),
+        ]);
+
+        assert_eq!(
+            count_message_tokens(&Message::Tool(result)),
+            3 + TOKENS_PER_IMAGE + TOKENS_PER_MESSAGE
+        );
+    }
+
+    #[test]
+    fn test_count_tokens_counts_system_prompt_once() {
+        let options = LanguageModelOptions {
+            system: Some("You are helpful.".to_string()), // 16 chars -> 4 tokens
+            messages: vec![
+                TaggedMessage::initial_step_msg(Message::System("You are helpful.".into())),
+                TaggedMessage::initial_step_msg(Message::User("What is the time?".into())), // 17 chars -> 5 tokens
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(count_tokens(&options), 4 + 5 + 2 * TOKENS_PER_MESSAGE);
+    }
+
+    #[test]
+    fn test_count_tokens_includes_tools() {
+        let mut tool = Tool::new();
+        tool.name = "get_time".to_string();
+        tool.description = "Returns the time".to_string();
+
+        let without_tools = LanguageModelOptions::default();
+        let with_tools = LanguageModelOptions {
+            tools: Some(ToolList::new(vec![tool])),
+            ..Default::default()
+        };
+
+        assert!(count_tokens(&with_tools) > count_tokens(&without_tools));
+    }
+
+    #[test]
+    fn test_fits_context() {
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User(
+                "a".repeat(400).into(),
+            ))],
+            ..Default::default()
+        };
+
+        assert!(fits_context(&options, 200));
+        assert!(!fits_context(&options, 50));
+    }
+}
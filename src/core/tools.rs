@@ -9,17 +9,35 @@ use tokio::task::JoinHandle;
 
 pub type ToolFn = Box<dyn Fn(Value) -> std::result::Result<String, String> + Send + Sync>;
 
+/// A tool function returning content parts, e.g. text and images.
+pub type ToolPartsFn =
+    Box<dyn Fn(Value) -> std::result::Result<Vec<ToolResultPart>, String> + Send + Sync>;
+
 #[derive(Clone)]
 pub struct ToolExecute {
-    inner: Arc<ToolFn>,
+    inner: Arc<ToolPartsFn>,
 }
 
 impl ToolExecute {
+    /// Calls the tool, returning the text of its output.
     pub fn call(&self, map: Value) -> Result<String> {
+        self.call_parts(map)
+            .map(|parts| ToolResultPart::text_of(&parts))
+    }
+
+    /// Calls the tool, returning every part of its output.
+    pub fn call_parts(&self, map: Value) -> Result<Vec<ToolResultPart>> {
         (*self.inner)(map).map_err(Error::ToolCallError)
     }
 
     pub fn new(f: ToolFn) -> Self {
+        Self::with_parts(Box::new(move |map| {
+            f(map).map(|text| vec![ToolResultPart::Text(text)])
+        }))
+    }
+
+    /// Creates a tool whose output can contain parts other than text.
+    pub fn with_parts(f: ToolPartsFn) -> Self {
         Self { inner: Arc::new(f) }
     }
 }
@@ -55,10 +73,16 @@ pub struct Tool {
     pub name: String,
     /// AI friendly description
     pub description: String,
-    /// The input schema of the tool as json schema
+    /// The input schema of the tool as json schema. Schemas generated by
+    /// `#[tool]` and `from_schema` have their keys sorted, so the same tool
+    /// always serializes to the same bytes.
     pub input_schema: Schema,
     /// The output schema of the tool. AI will use this to generate outputs.
     pub execute: ToolExecute,
+    /// If true, a successful call ends generation and the tool output is
+    /// returned as the final response text instead of another model round.
+    #[builder(default)]
+    pub stop_after: bool,
 }
 
 impl Debug for Tool {
@@ -66,17 +90,61 @@ impl Debug for Tool {
         f.debug_struct("Tool")
             .field("name", &self.name)
             .field("description", &self.description)
+            .field("stop_after", &self.stop_after)
             .finish()
     }
 }
 
+/// The public description of a tool, e.g. to list the tools of a request in
+/// docs or a UI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolDescription {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Schema,
+}
+
 impl Tool {
+    /// Returns the name, description and input schema of the tool.
+    pub fn describe(&self) -> ToolDescription {
+        ToolDescription {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            input_schema: self.input_schema.clone(),
+        }
+    }
+
+    /// Creates a tool from a name, description and json schema known only at
+    /// runtime, e.g. loaded from a config file, without the `#[tool]` macro.
+    ///
+    /// Returns an `Error::InvalidInput` if `schema` is neither an object nor
+    /// a boolean.
+    pub fn from_schema(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        schema: Value,
+        execute: ToolExecute,
+    ) -> Result<Self> {
+        let mut schema = schema;
+        schema.sort_all_objects();
+        let input_schema = Schema::try_from(schema)
+            .map_err(|e| Error::InvalidInput(format!("Invalid tool input schema: {e}")))?;
+        Ok(Self {
+            name: name.into(),
+            description: description.into(),
+            input_schema,
+            execute,
+            stop_after: false,
+        })
+    }
+
     pub fn new() -> Self {
         Self {
             name: "".to_string(),
             description: "".to_string(),
             input_schema: Schema::default(),
             execute: ToolExecute::default(),
+            stop_after: false,
         }
     }
 }
@@ -100,7 +168,29 @@ impl ToolList {
             .push(tool);
     }
 
-    pub async fn execute(&self, tool_info: ToolCallInfo) -> JoinHandle<Result<String>> {
+    /// Returns the descriptions of all tools in the list.
+    pub fn describe_all(&self) -> Vec<ToolDescription> {
+        self.tools
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(Tool::describe)
+            .collect()
+    }
+
+    /// Returns true if the tool with the given name ends generation after a call.
+    pub fn stops_after(&self, name: &str) -> bool {
+        self.tools
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .any(|tool| tool.name == name && tool.stop_after)
+    }
+
+    pub async fn execute(
+        &self,
+        tool_info: ToolCallInfo,
+    ) -> JoinHandle<Result<Vec<ToolResultPart>>> {
         let tools = self.tools.clone();
         tokio::spawn(async move {
             let tools = tools
@@ -109,7 +199,7 @@ impl ToolList {
             let tool = tools.iter().find(|tool| tool.name == tool_info.tool.name);
 
             match tool {
-                Some(tool) => tool.execute.call(tool_info.input),
+                Some(tool) => tool.execute.call_parts(tool_info.input),
                 None => Err(crate::error::Error::ToolCallError(
                     "Tool not found".to_string(),
                 )),
@@ -158,11 +248,48 @@ impl ToolCallInfo {
     }
 }
 
+/// A part of a tool's output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolResultPart {
+    Text(String),
+    /// A base64 encoded image, e.g. a screenshot.
+    Image {
+        media_type: String,
+        data: String,
+    },
+}
+
+impl ToolResultPart {
+    pub fn image(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self::Image {
+            media_type: media_type.into(),
+            data: data.into(),
+        }
+    }
+
+    /// Joins the text parts of an output.
+    pub fn text_of(parts: &[ToolResultPart]) -> String {
+        parts
+            .iter()
+            .filter_map(|part| match part {
+                ToolResultPart::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 /// Contains information from a tool
 #[derive(Debug, Clone)]
 pub struct ToolResultInfo {
     pub tool: ToolDetails,
     pub output: Result<serde_json::Value>,
+    /// Whether the tool failed. `output` then holds the error message, which
+    /// is sent to the model as an error result.
+    pub is_error: bool,
+    /// The parts of the output if it contains more than text. `output` then
+    /// holds the text parts only.
+    pub parts: Vec<ToolResultPart>,
 }
 
 impl Default for ToolResultInfo {
@@ -170,6 +297,8 @@ impl Default for ToolResultInfo {
         Self {
             tool: ToolDetails::default(),
             output: Ok(serde_json::Value::Null),
+            is_error: false,
+            parts: Vec::new(),
         }
     }
 }
@@ -196,6 +325,62 @@ impl ToolResultInfo {
     pub fn output(&mut self, inp: serde_json::Value) {
         self.output = Ok(inp);
     }
+
+    /// Sets the output from the parts returned by a tool, keeping the parts
+    /// only if there is more than text.
+    pub fn output_parts(&mut self, parts: Vec<ToolResultPart>) {
+        self.output = Ok(serde_json::Value::String(ToolResultPart::text_of(&parts)));
+        if parts
+            .iter()
+            .any(|part| !matches!(part, ToolResultPart::Text(_)))
+        {
+            self.parts = parts;
+        }
+    }
+
+    /// Marks the result as failed with the given error message.
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.output = Ok(serde_json::Value::String(message.into()));
+        self.is_error = true;
+    }
+
+    /// Returns the error message if the tool failed.
+    pub fn error_message(&self) -> Option<String> {
+        match &self.output {
+            Err(e) => Some(e.to_string()),
+            Ok(serde_json::Value::String(message)) if self.is_error => Some(message.clone()),
+            Ok(output) if self.is_error => Some(output.to_string()),
+            Ok(_) => None,
+        }
+    }
+}
+
+/// A call to a tool executed by the provider itself, e.g. OpenAI's web
+/// search. `output` holds the provider's output item as returned.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct BuiltinToolCallInfo {
+    pub tool: ToolDetails,
+    pub output: serde_json::Value,
+}
+
+impl BuiltinToolCallInfo {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            tool: ToolDetails {
+                name: name.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn id(&mut self, id: impl Into<String>) {
+        self.tool.id = id.into();
+    }
+
+    pub fn output(&mut self, output: serde_json::Value) {
+        self.output = output;
+    }
 }
 
 // tests
@@ -258,6 +443,33 @@ mod tests {
         );
     }
 
+    #[tool]
+    /// Repeats a word.
+    pub fn repeat_word(word: String, times: u32, shout: bool) -> Tool {
+        let word = if shout { word.to_uppercase() } else { word };
+        Ok(vec![word; times as usize].join(" "))
+    }
+
+    #[test]
+    fn test_tool_macro_coerces_stringified_arguments() {
+        let tool = repeat_word();
+        let call = |input| tool.execute.call(input).unwrap();
+
+        assert_eq!(
+            call(serde_json::json!({ "word": "hi", "times": "3", "shout": "true" })),
+            "HI HI HI"
+        );
+        assert_eq!(
+            call(serde_json::json!({ "word": 7, "times": 2, "shout": false })),
+            "7 7"
+        );
+        // unparseable and missing arguments fall back to the default
+        assert_eq!(
+            call(serde_json::json!({ "word": "hi", "times": "many" })),
+            ""
+        );
+    }
+
     #[tool(name = "the-name-for-this-tool")]
     pub fn my_example_tool_with_name(name: String, a: u8, b: Option<u8>) -> Tool {
         Ok(format!("{}{}", a, b.unwrap_or(0)))
@@ -320,6 +532,72 @@ mod tests {
         assert_eq!(tool.description, "the-description-for-this-tool");
     }
 
+    #[test]
+    fn test_describe_tool() {
+        let description = my_example_tool_with_name_and_description().describe();
+        assert_eq!(description.name, "the-name-for-this-tool");
+        assert_eq!(description.description, "the-description-for-this-tool");
+        let properties = description.input_schema.get("properties").unwrap();
+        assert!(properties.get("a").is_some() && properties.get("b").is_some());
+
+        let list = ToolList::new(vec![my_example_tool(), my_example_tool_with_name()]);
+        let names: Vec<String> = list.describe_all().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, vec!["my_example_tool", "the-name-for-this-tool"]);
+    }
+
     #[test]
     fn test_argument_json_schema() {}
+
+    #[test]
+    fn test_tool_from_schema() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"]
+        });
+        let tool = Tool::from_schema(
+            "get_weather",
+            "Returns the weather of a city",
+            schema.clone(),
+            ToolExecute::new(Box::new(|input| {
+                Ok(format!(
+                    "sunny in {}",
+                    input["city"].as_str().unwrap_or("?")
+                ))
+            })),
+        )
+        .unwrap();
+
+        assert_eq!(tool.name, "get_weather");
+        assert_eq!(tool.input_schema.as_value(), &schema);
+        assert_eq!(
+            serde_json::to_string(&tool.input_schema).unwrap(),
+            r#"{"type":"object","properties":{"city":{"type":"string"}},"required":["city"]}"#
+        );
+        assert_eq!(
+            tool.execute
+                .call(serde_json::json!({ "city": "Paris" }))
+                .unwrap(),
+            "sunny in Paris"
+        );
+
+        let result = Tool::from_schema(
+            "bad",
+            "",
+            serde_json::json!("string"),
+            ToolExecute::default(),
+        );
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_tool_schema_serialization_is_stable() {
+        let first = serde_json::to_string(&my_example_tool().input_schema).unwrap();
+        let second = serde_json::to_string(&my_example_tool().input_schema).unwrap();
+        assert_eq!(first, second);
+
+        // a schema read back from json serializes to the same bytes
+        let reparsed: Schema = serde_json::from_str(&first).unwrap();
+        assert_eq!(serde_json::to_string(&reparsed).unwrap(), first);
+    }
 }
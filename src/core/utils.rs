@@ -1,40 +1,58 @@
-use crate::core::{Message, language_model::LanguageModelOptions, messages::TaggedMessage};
+use crate::core::{Message, language_model::LanguageModelOptions, messages::TaggedMessage, tokens};
 
 /// Resolves the message to be used for text generation.
 ///
 /// This function takes a prompt and a list of messages and returns a vector of
 /// messages that can be used for LanguageModelCallOptions.
 /// if no messages are provided, a default message is created with the prompt and system prompt.
+/// No system prompt is returned, and no system message is created, unless the
+/// options set one or the messages contain one.
+/// If both a `history_policy` and a `context_limit` are set, the messages are
+/// shortened to fit the limit.
 pub(crate) fn resolve_message(
     options: &LanguageModelOptions,
     prompt: &Option<String>,
-) -> (String, Vec<TaggedMessage>) {
+) -> (Option<String>, Vec<TaggedMessage>) {
     let messages = if options.messages.is_empty() {
-        vec![
-            TaggedMessage::initial_step_msg(Message::System(
-                options.system.to_owned().unwrap_or_default().into(),
-            )),
-            TaggedMessage::initial_step_msg(Message::User(
-                prompt.to_owned().unwrap_or_default().into(),
-            )),
-        ]
+        options
+            .system
+            .iter()
+            .map(|system| Message::System(system.to_owned().into()))
+            .chain([Message::User(prompt.to_owned().unwrap_or_default().into())])
+            .map(TaggedMessage::initial_step_msg)
+            .collect()
     } else {
         options.messages.to_vec()
     };
 
-    let system = options.system.to_owned().unwrap_or_else(|| {
-        messages
-            .iter()
-            .find_map(|m| match m.message {
-                Message::System(ref s) => Some(s.content.to_string()),
-                _ => None,
-            })
-            .unwrap_or_default()
+    let system = options.system.to_owned().or_else(|| {
+        messages.iter().find_map(|m| match m.message {
+            Message::System(ref s) => Some(s.content.to_string()),
+            _ => None,
+        })
     });
 
+    let messages = fit_history(options, messages);
+
     (system, messages)
 }
 
+/// Applies the `history_policy` of the options to `messages` if a
+/// `context_limit` is set. The tool definitions, and a system prompt that is
+/// not one of the messages, are reserved from the limit.
+pub(crate) fn fit_history(
+    options: &LanguageModelOptions,
+    messages: Vec<TaggedMessage>,
+) -> Vec<TaggedMessage> {
+    let (Some(policy), Some(limit)) = (&options.history_policy, options.context_limit) else {
+        return messages;
+    };
+
+    let reserved = tokens::count_tool_tokens(options)
+        + tokens::count_system_tokens(options.system.as_deref(), &messages);
+    policy.apply(messages, limit.saturating_sub(reserved))
+}
+
 pub fn sum_options(a: Option<usize>, b: Option<usize>) -> Option<usize> {
     match (a, b) {
         (Some(x), Some(y)) => Some(x + y),
@@ -42,10 +60,70 @@ pub fn sum_options(a: Option<usize>, b: Option<usize>) -> Option<usize> {
     }
 }
 
+/// Extracts the JSON in a model's text output, stripping markdown code
+/// fences and surrounding prose. Of the objects and arrays in the text the
+/// longest is returned, so brackets in the prose, e.g. a `[1]` citation, are
+/// skipped. Without valid JSON the text from the first bracket on is
+/// returned, and `None` if there is no object or array in the text.
+pub(crate) fn extract_json(text: &str) -> Option<&str> {
+    let mut text = text.trim();
+
+    // the content of the first fenced block, with or without a language tag
+    if let Some((_, rest)) = text.split_once("```") {
+        let rest = rest.split_once('\n').map_or(rest, |(_, body)| body);
+        text = rest.split_once("```").map_or(rest, |(body, _)| body).trim();
+    }
+
+    let mut longest: Option<&str> = None;
+    let mut from = 0;
+    while let Some(offset) = text[from..].find(['{', '[']) {
+        let start = from + offset;
+        let mut values =
+            serde_json::Deserializer::from_str(&text[start..]).into_iter::<serde_json::Value>();
+        from = match values.next() {
+            Some(Ok(_)) => {
+                let json = &text[start..start + values.byte_offset()];
+                if longest.is_none_or(|longest| json.len() > longest.len()) {
+                    longest = Some(json);
+                }
+                // values nested in this one are shorter
+                start + values.byte_offset()
+            }
+            _ => start + 1,
+        };
+    }
+    if longest.is_some() {
+        return longest;
+    }
+
+    let start = text.find(['{', '['])?;
+    let end = text
+        .rfind(['}', ']'])
+        .filter(|end| *end > start)
+        .map_or(text.len(), |end| end + 1);
+    Some(&text[start..end])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_extract_json() {
+        assert_eq!(extract_json("{\"a\": 1}"), Some("{\"a\": 1}"));
+        assert_eq!(extract_json("```json\n{\"a\": 1}\n```"), Some("{\"a\": 1}"));
+        assert_eq!(
+            extract_json("Here you go:\n```\n[1, 2]\n```\nAnything else?"),
+            Some("[1, 2]")
+        );
+        assert_eq!(
+            extract_json("The result is {\"a\": 1}."),
+            Some("{\"a\": 1}")
+        );
+        assert_eq!(extract_json("See [1]: {\"a\": 1}"), Some("{\"a\": 1}"));
+        assert_eq!(extract_json("I could not do that."), None);
+    }
+
     #[test]
     fn test_sum_options_both_some() {
         assert_eq!(sum_options(Some(1), Some(2)), Some(3));
@@ -69,4 +147,61 @@ mod tests {
     fn test_sum_options_both_none() {
         assert_eq!(sum_options(None, None), None);
     }
+
+    #[test]
+    fn test_resolve_message_applies_history_policy() {
+        use crate::core::HistoryPolicy;
+
+        let messages: Vec<TaggedMessage> = ["first", "second", "third", "fourth"]
+            .into_iter()
+            .map(|text| TaggedMessage::initial_step_msg(Message::User(text.into())))
+            .collect();
+        let per_message = tokens::count_message_tokens(&messages[0].message);
+
+        let options = LanguageModelOptions {
+            system: Some("sys".to_string()),
+            messages,
+            context_limit: Some(per_message * 3),
+            history_policy: Some(HistoryPolicy::TruncateOldest { keep_system: true }),
+            ..Default::default()
+        };
+
+        let (system, messages) = resolve_message(&options, &None);
+        assert_eq!(system.as_deref(), Some("sys"));
+        let texts: Vec<String> = messages
+            .into_iter()
+            .map(|m| match m.message {
+                Message::User(u) => u.content,
+                other => panic!("unexpected message {other:?}"),
+            })
+            .collect();
+        assert_eq!(texts, vec!["third", "fourth"]);
+    }
+
+    #[test]
+    fn test_resolve_message_without_system_prompt() {
+        let options = LanguageModelOptions::default();
+
+        let (system, messages) = resolve_message(&options, &Some("hi".to_string()));
+        assert_eq!(system, None);
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0].message, Message::User(_)));
+    }
+
+    #[test]
+    fn test_resolve_message_ignores_history_policy_without_limit() {
+        use crate::core::HistoryPolicy;
+
+        let options = LanguageModelOptions {
+            messages: vec![
+                TaggedMessage::initial_step_msg(Message::User("first".into())),
+                TaggedMessage::initial_step_msg(Message::User("second".into())),
+            ],
+            history_policy: Some(HistoryPolicy::TruncateOldest { keep_system: true }),
+            ..Default::default()
+        };
+
+        let (_, messages) = resolve_message(&options, &None);
+        assert_eq!(messages.len(), 2);
+    }
 }
@@ -19,7 +19,19 @@ use std::sync::Arc;
 use derive_builder::UninitializedFieldError;
 
 /// A marker trait for provider-specific errors.
-pub trait ProviderError: std::error::Error + Send + Sync {}
+pub trait ProviderError: std::error::Error + Send + Sync {
+    /// Returns true if the failed request may succeed when sent again,
+    /// e.g. after a network error or a temporary server error.
+    fn is_retryable(&self) -> bool {
+        false
+    }
+
+    /// Returns the id the provider assigned to the failed request, e.g. the
+    /// `x-request-id` header, to quote in support tickets.
+    fn request_id(&self) -> Option<String> {
+        None
+    }
+}
 
 impl PartialEq for dyn ProviderError {
     fn eq(&self, other: &dyn ProviderError) -> bool {
@@ -48,6 +60,15 @@ pub enum Error {
     #[error("Tool error: {0}")]
     ToolCallError(String),
 
+    /// The model output could not be deserialized into the requested schema.
+    /// `raw_text` holds the text returned by the model, if any.
+    #[error("Schema error: {message}")]
+    Schema { message: String, raw_text: String },
+
+    /// The request to the provider timed out.
+    #[error("Request timed out: {0}")]
+    Timeout(String),
+
     /// A catch-all for other miscellaneous errors.
     #[error("AI SDK error: {0}")]
     Other(String),
@@ -65,6 +86,28 @@ impl From<UninitializedFieldError> for Error {
     }
 }
 
+impl Error {
+    /// Returns true if the error is transient and the request may be retried.
+    /// Timeouts are retryable, other provider errors as classified by the
+    /// provider.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Timeout(_) => true,
+            Error::ProviderError(error) => error.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Returns the id the provider assigned to the failed request, if the
+    /// provider reported one.
+    pub fn request_id(&self) -> Option<String> {
+        match self {
+            Error::ProviderError(error) => error.request_id(),
+            _ => None,
+        }
+    }
+}
+
 impl From<Error> for String {
     fn from(value: Error) -> String {
         match value {
@@ -72,8 +115,39 @@ impl From<Error> for String {
             Error::ApiError(error) => format!("API error: {error}"),
             Error::InvalidInput(error) => format!("Invalid input: {error}"),
             Error::ToolCallError(error) => format!("Tool error: {error}"),
+            Error::Schema { message, .. } => format!("Schema error: {message}"),
+            Error::Timeout(error) => format!("Timeout: {error}"),
             Error::Other(error) => format!("Other error: {error}"),
             Error::ProviderError(error) => format!("Provider error: {error}"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("rate limited")]
+    struct RateLimited {
+        request_id: Option<String>,
+    }
+
+    impl ProviderError for RateLimited {
+        fn request_id(&self) -> Option<String> {
+            self.request_id.clone()
+        }
+    }
+
+    #[test]
+    fn test_request_id_is_preserved() {
+        let error = Error::ProviderError(Arc::new(RateLimited {
+            request_id: Some("req_123".to_string()),
+        }));
+        assert_eq!(error.request_id().as_deref(), Some("req_123"));
+
+        let error = Error::ProviderError(Arc::new(RateLimited { request_id: None }));
+        assert_eq!(error.request_id(), None);
+        assert_eq!(Error::Other("boom".to_string()).request_id(), None);
+    }
+}
@@ -3,6 +3,8 @@ pub mod error;
 #[cfg(feature = "prompt")]
 pub mod prompt;
 pub mod providers;
+#[cfg(test)]
+pub(crate) mod test_utils;
 
 // re-exports
 pub use error::{Error, Result};
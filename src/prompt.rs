@@ -28,6 +28,8 @@ use std::fs;
 use std::path::PathBuf;
 use tera::{Context, Tera};
 
+use crate::core::Message;
+
 /// Represents the environment for prompt management.
 /// It contains the Tera instance for template rendering and can be configured.
 #[derive(Clone)]
@@ -246,6 +248,98 @@ impl Promptable for Prompt {
     }
 }
 
+/// A few-shot prompt built from a task instruction and example
+/// `(input, output)` pairs.
+///
+/// The prompt can be rendered as messages, with the instruction as system
+/// message and each example as a user and assistant turn, or as a single
+/// formatted prompt.
+///
+/// # Examples
+///
+/// ```rust
+/// use aisdk::prompt::FewShot;
+///
+/// let few_shot = FewShot::new("Classify the sentiment.")
+///     .example("I love it", "positive")
+///     .example("It broke", "negative");
+///
+/// let messages = few_shot.messages("Works fine");
+/// let prompt = few_shot.render("Works fine");
+/// ```
+#[derive(Debug, Clone)]
+pub struct FewShot {
+    // The task instruction, placed before the examples.
+    instruction: String,
+    // The example input and output pairs, in order.
+    examples: Vec<(String, String)>,
+    // The labels of inputs and outputs in a rendered prompt.
+    input_label: String,
+    output_label: String,
+    // The separator between the instruction and examples in a rendered prompt.
+    delimiter: String,
+}
+
+impl FewShot {
+    /// Creates a few-shot prompt with the given task instruction, labeling
+    /// examples with `Input:` and `Output:` separated by a blank line.
+    pub fn new(instruction: &str) -> Self {
+        FewShot {
+            instruction: instruction.to_string(),
+            examples: Vec::new(),
+            input_label: "Input".to_string(),
+            output_label: "Output".to_string(),
+            delimiter: "\n\n".to_string(),
+        }
+    }
+
+    /// Adds an example input and its expected output.
+    pub fn example(mut self, input: &str, output: &str) -> Self {
+        self.examples.push((input.to_string(), output.to_string()));
+        self
+    }
+
+    /// Sets the labels of inputs and outputs in a rendered prompt.
+    pub fn with_labels(mut self, input: &str, output: &str) -> Self {
+        self.input_label = input.to_string();
+        self.output_label = output.to_string();
+        self
+    }
+
+    /// Sets the separator between the instruction and examples in a rendered
+    /// prompt.
+    pub fn with_delimiter(mut self, delimiter: &str) -> Self {
+        self.delimiter = delimiter.to_string();
+        self
+    }
+
+    /// Returns the prompt as messages for `.messages(...)`: the instruction as
+    /// system message, a user and assistant message for each example and
+    /// `input` as the final user message.
+    pub fn messages(&self, input: &str) -> Vec<Message> {
+        let mut messages = vec![Message::System(self.instruction.as_str().into())];
+        for (example_input, example_output) in &self.examples {
+            messages.push(Message::User(example_input.as_str().into()));
+            messages.push(Message::Assistant(example_output.clone().into()));
+        }
+        messages.push(Message::User(input.into()));
+        messages
+    }
+
+    /// Returns the prompt as a single text: the instruction, each labeled
+    /// example and `input` with an empty output label for the model to fill.
+    pub fn render(&self, input: &str) -> String {
+        let (input_label, output_label) = (&self.input_label, &self.output_label);
+        std::iter::once(self.instruction.clone())
+            .chain(self.examples.iter().map(|(example_input, example_output)| {
+                format!("{input_label}: {example_input}\n{output_label}: {example_output}")
+            }))
+            .chain([format!("{input_label}: {input}\n{output_label}:")])
+            .collect::<Vec<_>>()
+            .join(&self.delimiter)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -376,4 +470,54 @@ mod tests {
         let generated_string = prompt.generate();
         assert_eq!(generated_string, "Override: World");
     }
+
+    fn few_shot() -> FewShot {
+        FewShot::new("Classify the sentiment.")
+            .example("I love it", "positive")
+            .example("It broke", "negative")
+    }
+
+    #[test]
+    fn test_few_shot_messages() {
+        let lines: Vec<String> = few_shot()
+            .messages("Works fine")
+            .iter()
+            .map(Message::to_string)
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "system: Classify the sentiment.",
+                "user: I love it",
+                "assistant: positive",
+                "user: It broke",
+                "assistant: negative",
+                "user: Works fine",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_few_shot_render() {
+        assert_eq!(
+            few_shot().render("Works fine"),
+            "Classify the sentiment.\n\n\
+             Input: I love it\nOutput: positive\n\n\
+             Input: It broke\nOutput: negative\n\n\
+             Input: Works fine\nOutput:"
+        );
+
+        let custom = few_shot()
+            .with_labels("Review", "Sentiment")
+            .with_delimiter("\n---\n")
+            .render("Works fine");
+        assert_eq!(
+            custom,
+            "Classify the sentiment.\n---\n\
+             Review: I love it\nSentiment: positive\n---\n\
+             Review: It broke\nSentiment: negative\n---\n\
+             Review: Works fine\nSentiment:"
+        );
+    }
 }
@@ -0,0 +1,892 @@
+//! Request and response types for the chat completions API and their
+//! conversions to and from the core types.
+
+use crate::core::language_model::{
+    AudioOutput, LanguageModelOptions, LanguageModelResponse, LanguageModelResponseContentType,
+    LanguageModelStreamChunk, LanguageModelStreamChunkType, Usage, Warning,
+};
+use crate::core::messages::{AssistantMessage, ContentPart, Message};
+use crate::providers::chat_completions::ChatCompletionsApi;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A chat completions request.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChatCompletionsRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<Value>,
+    /// The kinds of output to generate, `["text", "audio"]` for audio.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modalities: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioOutput>,
+    pub stream: bool,
+}
+
+/// A single chat message.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: ChatContent,
+}
+
+impl ChatMessage {
+    pub(crate) fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: ChatContent::Text(content.into()),
+        }
+    }
+}
+
+/// The content of a chat message, plain text unless it has audio.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
+}
+
+/// A part of the content of a chat message.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatContentPart {
+    Text { text: String },
+    InputAudio { input_audio: ChatInputAudio },
+}
+
+/// Base64 encoded audio sent to the model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChatInputAudio {
+    pub data: String,
+    /// The audio format, e.g. `wav` or `mp3`.
+    pub format: String,
+}
+
+/// A chat completions response, or a single chunk of a streamed response.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatCompletionsResponse {
+    #[serde(default)]
+    pub choices: Vec<ChatChoice>,
+    /// Source urls the answer is grounded on, sent by providers that search
+    /// the web.
+    #[serde(default)]
+    pub citations: Vec<String>,
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatChoice {
+    /// The index of the completion the choice belongs to.
+    #[serde(default)]
+    pub index: usize,
+    /// The full message, set on non-streaming responses.
+    pub message: Option<ChatResponseMessage>,
+    /// The incremental message, set on streamed chunks.
+    pub delta: Option<ChatDelta>,
+    pub finish_reason: Option<String>,
+}
+
+/// The message of a non-streaming response.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatResponseMessage {
+    pub content: Option<String>,
+    /// The generated audio, set when audio output is requested.
+    pub audio: Option<ChatAudio>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatDelta {
+    pub content: Option<String>,
+    /// A chunk of the generated audio and its transcript.
+    pub audio: Option<ChatAudio>,
+}
+
+/// Generated audio, or a chunk of it on streamed responses.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatAudio {
+    /// Base64 encoded audio data.
+    pub data: Option<String>,
+    pub transcript: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatUsage {
+    pub prompt_tokens: Option<usize>,
+    pub completion_tokens: Option<usize>,
+    pub total_tokens: Option<usize>,
+    pub reasoning_tokens: Option<usize>,
+}
+
+impl From<ChatUsage> for Usage {
+    fn from(value: ChatUsage) -> Self {
+        Self {
+            input_tokens: value.prompt_tokens,
+            output_tokens: value.completion_tokens,
+            total_tokens: value.total_tokens,
+            reasoning_tokens: value.reasoning_tokens,
+            cached_tokens: None,
+        }
+    }
+}
+
+/// Returns the options the api `A` does not support.
+pub(crate) fn warnings<A: ChatCompletionsApi>(options: &LanguageModelOptions) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    if options.tools.is_some() {
+        warnings.push(Warning::new(
+            "tools",
+            format!(
+                "{} does not support tool calling, tools will be ignored",
+                A::NAME
+            ),
+        ));
+    }
+    if !A::AUDIO && (options.has_audio_input() || options.audio_output.is_some()) {
+        warnings.push(Warning::new(
+            "audio",
+            format!(
+                "{} does not support audio, audio parts and output will be ignored",
+                A::NAME
+            ),
+        ));
+    }
+    for (option, set) in [
+        ("top_k", !A::TOP_K && options.top_k.is_some()),
+        ("seed", !A::STOP_AND_SEED && options.seed.is_some()),
+        (
+            "stop_sequences",
+            !A::STOP_AND_SEED && options.stop_sequences.is_some(),
+        ),
+        ("schema", !A::JSON_SCHEMA && options.schema.is_some()),
+        (
+            "json_mode",
+            !A::JSON_SCHEMA && options.json_mode == Some(true),
+        ),
+        (
+            "n",
+            !A::MULTIPLE_COMPLETIONS && options.n.is_some_and(|n| n > 1),
+        ),
+        ("logprobs", options.logprobs == Some(true)),
+        ("top_logprobs", options.top_logprobs.is_some()),
+        ("reasoning_effort", options.reasoning_effort.is_some()),
+        ("user", options.user.is_some()),
+        ("store", options.store.is_some()),
+        (
+            "previous_response_id",
+            options.previous_response_id.is_some(),
+        ),
+    ] {
+        if set {
+            warnings.push(Warning::unsupported(option, A::NAME));
+        }
+    }
+
+    warnings
+}
+
+/// Converts the options into a request to the api `A`, leaving out the
+/// options it does not support.
+pub(crate) fn request<A: ChatCompletionsApi>(
+    options: LanguageModelOptions,
+) -> ChatCompletionsRequest {
+    let mut messages = Vec::new();
+
+    // the system prompt is resolved into `options.system` and the message
+    // list, its copy in the list is skipped to avoid sending it twice.
+    let system = options.system.filter(|s| !s.is_empty());
+    if let Some(system) = &system {
+        messages.push(ChatMessage::new("system", system.as_str()));
+    }
+
+    for tagged in options.messages {
+        match tagged.message {
+            Message::User(u) if A::AUDIO && !u.parts.is_empty() => {
+                let mut parts = Vec::new();
+                if !u.content.is_empty() {
+                    parts.push(ChatContentPart::Text { text: u.content });
+                }
+                parts.extend(u.parts.into_iter().map(|part| match part {
+                    ContentPart::Audio { media_type, data } => ChatContentPart::InputAudio {
+                        input_audio: ChatInputAudio {
+                            format: audio_format(&media_type).to_string(),
+                            data,
+                        },
+                    },
+                }));
+                messages.push(ChatMessage {
+                    role: "user".to_string(),
+                    content: ChatContent::Parts(parts),
+                });
+            }
+            Message::User(u) => messages.push(ChatMessage::new("user", u.content)),
+            Message::Assistant(a) => match a.content {
+                LanguageModelResponseContentType::Text(text) => {
+                    messages.push(ChatMessage::new("assistant", text))
+                }
+                // generated audio is sent back as its transcript.
+                LanguageModelResponseContentType::Audio {
+                    transcript: Some(transcript),
+                    ..
+                } => messages.push(ChatMessage::new("assistant", transcript)),
+                _ => {}
+            },
+            Message::System(s) if system.as_ref() == Some(&s.content) => {}
+            Message::System(s) => messages.push(ChatMessage::new("system", s.content)),
+            Message::Developer(d) => messages.push(ChatMessage::new("system", d)),
+            Message::Tool(_) => {}
+        }
+    }
+    if A::ALTERNATING_ROLES {
+        messages = merge_same_role(messages);
+    }
+
+    // there is no generic json mode, any object is accepted instead
+    let response_format = A::JSON_SCHEMA
+        .then(|| {
+            options.schema.map(serde_json::Value::from).or_else(|| {
+                (options.json_mode == Some(true)).then(|| serde_json::json!({ "type": "object" }))
+            })
+        })
+        .flatten()
+        .map(|schema| {
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "schema": schema },
+            })
+        });
+
+    let audio = options.audio_output.filter(|_| A::AUDIO);
+
+    ChatCompletionsRequest {
+        messages,
+        max_tokens: options.max_output_tokens,
+        temperature: options.temperature.map(|t| t as f32 / 100.0),
+        top_p: options.top_p.map(|t| t as f32 / 100.0),
+        top_k: options.top_k.filter(|_| A::TOP_K),
+        stop: options.stop_sequences.filter(|_| A::STOP_AND_SEED),
+        seed: options.seed.filter(|_| A::STOP_AND_SEED),
+        n: options.n.filter(|_| A::MULTIPLE_COMPLETIONS),
+        presence_penalty: options.presence_penalty,
+        frequency_penalty: options.frequency_penalty,
+        response_format,
+        modalities: audio
+            .as_ref()
+            .map(|_| vec!["text".to_string(), "audio".to_string()]),
+        audio,
+        ..Default::default()
+    }
+}
+
+/// Merges consecutive text messages of the same role, joining their
+/// content. Some apis reject messages that do not alternate between user
+/// and assistant, which happens e.g. when tool turns are dropped.
+fn merge_same_role(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let mut merged: Vec<ChatMessage> = Vec::with_capacity(messages.len());
+    for message in messages {
+        if let Some(last) = merged.last_mut()
+            && last.role == message.role
+            && let (ChatContent::Text(last), ChatContent::Text(text)) =
+                (&mut last.content, &message.content)
+        {
+            last.push_str("\n\n");
+            last.push_str(text);
+            continue;
+        }
+        merged.push(message);
+    }
+    merged
+}
+
+impl ChatCompletionsResponse {
+    /// Converts the response into the core type, reading generated audio in
+    /// the format requested with `audio`.
+    pub(crate) fn into_response(self, audio: Option<&AudioOutput>) -> LanguageModelResponse {
+        let media_type = audio_media_type(audio.map_or("wav", |audio| audio.format.as_str()));
+        let contents = self
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.message)
+            .map(|message| match message.audio {
+                Some(audio) => LanguageModelResponseContentType::Audio {
+                    media_type: media_type.clone(),
+                    data: audio.data.unwrap_or_default(),
+                    transcript: audio.transcript.or(message.content),
+                },
+                None => LanguageModelResponseContentType::new(message.content.unwrap_or_default()),
+            })
+            .collect();
+
+        LanguageModelResponse {
+            contents,
+            usage: self.usage.map(|usage| usage.into()),
+            logprobs: Vec::new(),
+            citations: self.citations,
+            id: None,
+            warnings: Vec::new(),
+            incomplete: None,
+            served_by: None,
+        }
+    }
+}
+
+/// The state of a streamed response: the text of each completion, by index,
+/// and whether it finished, and the audio of the first completion.
+#[derive(Debug, Default)]
+pub(crate) struct StreamedChoices {
+    choices: Vec<(String, bool)>,
+    audio: String,
+    audio_format: Option<String>,
+}
+
+impl StreamedChoices {
+    /// Creates the state of a stream generating audio in the format
+    /// requested with `audio`, if set.
+    pub(crate) fn new(audio: Option<&AudioOutput>) -> Self {
+        Self {
+            audio_format: audio.map(|audio| audio.format.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Converts a streamed chunk into stream chunks, accumulating the text of
+/// each completion in `state` so interleaved deltas of several completions
+/// are not mixed. The transcript of generated audio streams as text. Returns
+/// the chunks and whether the stream finished, which is once every
+/// completion finished.
+pub(crate) fn stream_chunks(
+    state: &mut StreamedChoices,
+    chunk: ChatCompletionsResponse,
+) -> (Vec<LanguageModelStreamChunk>, bool) {
+    let mut chunks = Vec::new();
+    let usage: Option<Usage> = chunk.usage.map(|usage| usage.into());
+    let choices = &mut state.choices;
+
+    let was_finished = !choices.is_empty() && choices.iter().all(|(_, done)| *done);
+    for choice in chunk.choices {
+        if choices.len() <= choice.index {
+            choices.resize(choice.index + 1, Default::default());
+        }
+        let (text, done) = &mut choices[choice.index];
+        let delta = choice.delta.unwrap_or_default();
+        let audio = delta.audio.unwrap_or_default();
+
+        if choice.index == 0
+            && let Some(data) = audio.data.filter(|data| !data.is_empty())
+        {
+            state.audio.push_str(&data);
+            chunks.push(LanguageModelStreamChunk::Delta(
+                LanguageModelStreamChunkType::Audio(data),
+            ));
+        }
+
+        if let Some(delta) = delta.content.or(audio.transcript)
+            && !delta.is_empty()
+        {
+            text.push_str(&delta);
+            chunks.push(LanguageModelStreamChunk::Delta(match choice.index {
+                0 => LanguageModelStreamChunkType::Text(delta),
+                index => LanguageModelStreamChunkType::CandidateText { index, text: delta },
+            }));
+        }
+
+        if choice.finish_reason.is_some() {
+            *done = true;
+        }
+    }
+
+    // only the first completion continues the conversation
+    let finished = !choices.is_empty() && choices.iter().all(|(_, done)| *done);
+    if finished && !was_finished {
+        let text = choices[0].0.clone();
+        let content = match state.audio_format.as_deref() {
+            Some(format) if !state.audio.is_empty() => LanguageModelResponseContentType::Audio {
+                media_type: audio_media_type(format),
+                data: std::mem::take(&mut state.audio),
+                transcript: Some(text).filter(|text| !text.is_empty()),
+            },
+            _ => LanguageModelResponseContentType::new(text),
+        };
+        chunks.push(LanguageModelStreamChunk::Done(AssistantMessage {
+            content,
+            usage,
+        }));
+    }
+
+    (chunks, finished)
+}
+
+/// Returns the audio format name of a media type, e.g. `mp3` for
+/// `audio/mpeg`.
+fn audio_format(media_type: &str) -> &str {
+    match media_type {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        other => other.strip_prefix("audio/").unwrap_or(other),
+    }
+}
+
+/// Returns the media type of an audio format name, e.g. `audio/mpeg` for
+/// `mp3`.
+fn audio_media_type(format: &str) -> String {
+    match format {
+        "mp3" => "audio/mpeg".to_string(),
+        format => format!("audio/{format}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::messages::{TaggedMessage, UserMessage};
+
+    /// An api supporting only the common options.
+    #[derive(Debug, Clone)]
+    struct PlainApi;
+
+    impl ChatCompletionsApi for PlainApi {
+        const NAME: &'static str = "Plain";
+        const PROVIDER_NAME: &'static str = "plain";
+        const BASE_URL: &'static str = "http://localhost";
+        const API_KEY_ENV: &'static str = "PLAIN_API_KEY";
+        const MODEL_NAME: &'static str = "plain";
+    }
+
+    /// An api supporting every optional part.
+    #[derive(Debug, Clone)]
+    struct FullApi;
+
+    impl ChatCompletionsApi for FullApi {
+        const NAME: &'static str = "Full";
+        const PROVIDER_NAME: &'static str = "full";
+        const BASE_URL: &'static str = "http://localhost";
+        const API_KEY_ENV: &'static str = "FULL_API_KEY";
+        const MODEL_NAME: &'static str = "full";
+        const STOP_AND_SEED: bool = true;
+        const JSON_SCHEMA: bool = true;
+        const MULTIPLE_COMPLETIONS: bool = true;
+        const AUDIO: bool = true;
+        const ALTERNATING_ROLES: bool = true;
+    }
+
+    #[test]
+    fn test_options_without_system_to_request() {
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User(
+                "What is Rust?".into(),
+            ))],
+            ..Default::default()
+        };
+
+        let request = request::<PlainApi>(options);
+        assert_eq!(
+            request.messages,
+            vec![ChatMessage::new("user", "What is Rust?")]
+        );
+    }
+
+    #[test]
+    fn test_options_to_request() {
+        let options = || LanguageModelOptions {
+            system: Some("Be concise.".to_string()),
+            messages: vec![
+                TaggedMessage::initial_step_msg(Message::System("Be concise.".into())),
+                TaggedMessage::initial_step_msg(Message::User("What is Rust?".into())),
+            ],
+            max_output_tokens: Some(100),
+            temperature: Some(70),
+            top_p: Some(90),
+            top_k: Some(40),
+            stop_sequences: Some(vec!["\n\n".to_string()]),
+            seed: Some(42),
+            n: Some(3),
+            ..Default::default()
+        };
+
+        let full = request::<FullApi>(options());
+        assert_eq!(
+            full.messages,
+            vec![
+                ChatMessage::new("system", "Be concise."),
+                ChatMessage::new("user", "What is Rust?"),
+            ]
+        );
+        assert_eq!(full.max_tokens, Some(100));
+        assert_eq!(full.temperature, Some(0.7));
+        assert_eq!(full.top_p, Some(0.9));
+        assert_eq!(full.top_k, Some(40));
+        assert_eq!(full.stop, Some(vec!["\n\n".to_string()]));
+        assert_eq!(full.seed, Some(42));
+        assert_eq!(full.n, Some(3));
+        assert!(full.response_format.is_none());
+
+        // options the api does not support are left out
+        let json = serde_json::to_value(request::<PlainApi>(options())).unwrap();
+        assert!(json.get("stop").is_none());
+        assert!(json.get("seed").is_none());
+        assert!(json.get("n").is_none());
+        assert_eq!(json["stream"], false);
+    }
+
+    #[test]
+    fn test_warnings_for_unsupported_options() {
+        let options = LanguageModelOptions {
+            top_k: Some(40),
+            seed: Some(42),
+            json_mode: Some(true),
+            ..Default::default()
+        };
+        assert!(warnings::<FullApi>(&options).is_empty());
+        assert_eq!(
+            warnings::<PlainApi>(&options),
+            vec![
+                Warning::unsupported("seed", "Plain"),
+                Warning::unsupported("json_mode", "Plain"),
+            ]
+        );
+
+        let options = LanguageModelOptions {
+            n: Some(2),
+            logprobs: Some(true),
+            top_logprobs: Some(3),
+            user: Some("user_42".to_string()),
+            ..Default::default()
+        };
+        let options: Vec<String> = warnings::<PlainApi>(&options)
+            .into_iter()
+            .map(|w| w.option)
+            .collect();
+        assert_eq!(options, ["n", "logprobs", "top_logprobs", "user"]);
+    }
+
+    #[test]
+    fn test_consecutive_same_role_messages_are_merged() {
+        let options = || LanguageModelOptions {
+            system: Some("Be brief".to_string()),
+            messages: Message::conversation_builder()
+                .user("Hi")
+                .user("What is Rust?")
+                .assistant("Let me search.")
+                .assistant("A systems language.")
+                .user("Thanks")
+                .build()
+                .into_iter()
+                .map(TaggedMessage::initial_step_msg)
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            request::<FullApi>(options()).messages,
+            vec![
+                ChatMessage::new("system", "Be brief"),
+                ChatMessage::new("user", "Hi\n\nWhat is Rust?"),
+                ChatMessage::new("assistant", "Let me search.\n\nA systems language."),
+                ChatMessage::new("user", "Thanks"),
+            ]
+        );
+        assert_eq!(request::<PlainApi>(options()).messages.len(), 6);
+    }
+
+    #[test]
+    fn test_audio_request() {
+        let options = || LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User(
+                UserMessage {
+                    content: "What is in this recording?".to_string(),
+                    parts: vec![ContentPart::Audio {
+                        media_type: "audio/mpeg".to_string(),
+                        data: "SUQzBA==".to_string(),
+                    }],
+                },
+            ))],
+            audio_output: Some(AudioOutput {
+                voice: "alloy".to_string(),
+                format: "wav".to_string(),
+            }),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(request::<FullApi>(options())).unwrap();
+        assert_eq!(
+            json["messages"],
+            serde_json::json!([{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "What is in this recording?" },
+                    { "type": "input_audio", "input_audio": { "data": "SUQzBA==", "format": "mp3" } },
+                ],
+            }])
+        );
+        assert_eq!(json["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(
+            json["audio"],
+            serde_json::json!({ "voice": "alloy", "format": "wav" })
+        );
+
+        // apis without audio get the text only
+        let json = serde_json::to_value(request::<PlainApi>(options())).unwrap();
+        assert_eq!(
+            json["messages"],
+            serde_json::json!([{ "role": "user", "content": "What is in this recording?" }])
+        );
+        assert!(json.get("modalities").is_none());
+        assert!(json.get("audio").is_none());
+        assert_eq!(warnings::<PlainApi>(&options())[0].option, "audio");
+        assert!(warnings::<FullApi>(&options()).is_empty());
+    }
+
+    #[test]
+    fn test_json_mode_requests_any_object() {
+        let options = || LanguageModelOptions {
+            json_mode: Some(true),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            request::<FullApi>(options()).response_format,
+            Some(serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "schema": { "type": "object" } },
+            }))
+        );
+        assert!(request::<PlainApi>(options()).response_format.is_none());
+    }
+
+    #[test]
+    fn test_response_with_citations() {
+        let payload = serde_json::json!({
+            "id": "3c90c3cc",
+            "model": "sonar",
+            "object": "chat.completion",
+            "created": 1724369245,
+            "citations": [
+                "https://www.rust-lang.org/",
+                "https://doc.rust-lang.org/book/"
+            ],
+            "choices": [{
+                "index": 0,
+                "finish_reason": "stop",
+                "message": { "role": "assistant", "content": "Rust is a systems language." }
+            }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 6, "total_tokens": 16 }
+        });
+
+        let response: ChatCompletionsResponse = serde_json::from_value(payload).unwrap();
+        let response = response.into_response(None);
+
+        assert_eq!(
+            response.citations,
+            vec![
+                "https://www.rust-lang.org/".to_string(),
+                "https://doc.rust-lang.org/book/".to_string()
+            ]
+        );
+        assert_eq!(response.contents.len(), 1);
+        assert!(matches!(
+            &response.contents[0],
+            LanguageModelResponseContentType::Text(t) if t == "Rust is a systems language."
+        ));
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.input_tokens, Some(10));
+        assert_eq!(usage.output_tokens, Some(6));
+        assert_eq!(usage.total_tokens, Some(16));
+    }
+
+    #[test]
+    fn test_response_without_citations() {
+        let payload = serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": "hi" } }]
+        });
+        let response: ChatCompletionsResponse = serde_json::from_value(payload).unwrap();
+        let response = response.into_response(None);
+        assert!(response.citations.is_empty());
+        assert!(response.usage.is_none());
+    }
+
+    #[test]
+    fn test_response_with_audio() {
+        let payload = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "audio": {
+                        "id": "audio_1",
+                        "expires_at": 1729018505,
+                        "data": "SUQzBA==",
+                        "transcript": "Hello!"
+                    }
+                },
+                "finish_reason": "stop"
+            }]
+        });
+        let audio = AudioOutput {
+            voice: "alloy".to_string(),
+            format: "mp3".to_string(),
+        };
+
+        let response: ChatCompletionsResponse = serde_json::from_value(payload).unwrap();
+        let response = response.into_response(Some(&audio));
+
+        assert!(matches!(
+            &response.contents[..],
+            [LanguageModelResponseContentType::Audio { media_type, data, transcript }]
+                if media_type == "audio/mpeg"
+                    && data == "SUQzBA=="
+                    && transcript.as_deref() == Some("Hello!")
+        ));
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let events = [
+            serde_json::json!({ "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "Hel" } }] }),
+            serde_json::json!({ "choices": [{ "index": 0, "delta": { "content": "lo" } }] }),
+            serde_json::json!({
+                "choices": [{ "index": 0, "delta": { "content": "" }, "finish_reason": "stop" }],
+                "usage": { "prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5 }
+            }),
+        ];
+
+        let mut choices = StreamedChoices::new(None);
+        let mut chunks = Vec::new();
+        let mut finished = false;
+        for event in events {
+            let chunk: ChatCompletionsResponse = serde_json::from_value(event).unwrap();
+            let (mut converted, done) = stream_chunks(&mut choices, chunk);
+            chunks.append(&mut converted);
+            finished = done;
+        }
+
+        assert!(finished);
+        assert_eq!(chunks.len(), 3);
+        assert!(matches!(
+            &chunks[0],
+            LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Text(t)) if t == "Hel"
+        ));
+        match &chunks[2] {
+            LanguageModelStreamChunk::Done(message) => {
+                assert!(matches!(
+                    &message.content,
+                    LanguageModelResponseContentType::Text(t) if t == "Hello"
+                ));
+                assert_eq!(message.usage.as_ref().unwrap().total_tokens, Some(5));
+            }
+            other => panic!("expected done chunk, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_stream_chunks_demultiplexes_completions() {
+        let delta = |index: usize, content: &str| serde_json::json!({ "choices": [{ "index": index, "delta": { "content": content } }] });
+        let events = [
+            delta(0, "Hel"),
+            delta(1, "Wor"),
+            delta(1, "ld"),
+            delta(0, "lo"),
+            serde_json::json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }] }),
+            serde_json::json!({ "choices": [{ "index": 1, "delta": {}, "finish_reason": "stop" }] }),
+        ];
+
+        let mut choices = StreamedChoices::new(None);
+        let mut chunks = Vec::new();
+        let mut finished = Vec::new();
+        for event in events {
+            let chunk: ChatCompletionsResponse = serde_json::from_value(event).unwrap();
+            let (mut converted, done) = stream_chunks(&mut choices, chunk);
+            chunks.append(&mut converted);
+            finished.push(done);
+        }
+
+        assert_eq!(finished, vec![false, false, false, false, false, true]);
+        assert_eq!(choices.choices[0].0, "Hello");
+        assert_eq!(choices.choices[1].0, "World");
+
+        let candidate: String = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::CandidateText {
+                    index: 1,
+                    text,
+                }) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(candidate, "World");
+        assert!(matches!(
+            chunks.last(),
+            Some(LanguageModelStreamChunk::Done(AssistantMessage {
+                content: LanguageModelResponseContentType::Text(text),
+                ..
+            })) if text == "Hello"
+        ));
+    }
+
+    #[test]
+    fn test_stream_chunks_with_audio() {
+        let events = [
+            serde_json::json!({ "choices": [{ "index": 0, "delta": { "role": "assistant", "audio": { "id": "audio_1", "transcript": "Hi" } } }] }),
+            serde_json::json!({ "choices": [{ "index": 0, "delta": { "audio": { "data": "UklG" } } }] }),
+            serde_json::json!({ "choices": [{ "index": 0, "delta": { "audio": { "transcript": " there" } } }] }),
+            serde_json::json!({ "choices": [{ "index": 0, "delta": { "audio": { "data": "RiQA" } } }] }),
+            serde_json::json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }] }),
+        ];
+        let audio = AudioOutput {
+            voice: "alloy".to_string(),
+            format: "wav".to_string(),
+        };
+
+        let mut state = StreamedChoices::new(Some(&audio));
+        let mut chunks = Vec::new();
+        for event in events {
+            let chunk: ChatCompletionsResponse = serde_json::from_value(event).unwrap();
+            chunks.append(&mut stream_chunks(&mut state, chunk).0);
+        }
+
+        let audio: String = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                LanguageModelStreamChunk::Delta(LanguageModelStreamChunkType::Audio(data)) => {
+                    Some(data.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(audio, "UklGRiQA");
+        assert!(matches!(
+            chunks.last(),
+            Some(LanguageModelStreamChunk::Done(AssistantMessage {
+                content: LanguageModelResponseContentType::Audio { media_type, data, transcript },
+                ..
+            })) if media_type == "audio/wav"
+                && data == "UklGRiQA"
+                && transcript.as_deref() == Some("Hi there")
+        ));
+    }
+}
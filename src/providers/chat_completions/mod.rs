@@ -0,0 +1,222 @@
+//! This module provides the model shared by the providers serving an OpenAI
+//! compatible chat completions api, e.g. Perplexity and Hugging Face. OpenAI
+//! sends its audio requests through it as well.
+//!
+//! A provider describes its defaults and how its api differs from the common
+//! one by implementing `ChatCompletionsApi`, and is a `ChatCompletions` model
+//! of that api.
+
+pub mod conversions;
+pub mod settings;
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use async_openai::Client;
+use futures::StreamExt;
+
+use crate::core::language_model::{
+    AudioOutput, Capabilities, LanguageModelOptions, LanguageModelResponse,
+    LanguageModelStreamChunk, ProviderStream, Warning,
+};
+use crate::providers::chat_completions::conversions::{
+    ChatCompletionsResponse, StreamedChoices, stream_chunks,
+};
+use crate::providers::chat_completions::settings::{
+    ChatCompletionsSettings, ChatCompletionsSettingsBuilder,
+};
+use crate::providers::client::{
+    ProviderConfig, post_json, post_stream, prepare_request_body, request_client,
+};
+use crate::{
+    core::{language_model::LanguageModel, provider::Provider},
+    error::Result,
+};
+use async_trait::async_trait;
+
+/// Describes a provider serving the chat completions api: its defaults and
+/// the parts of the api it supports.
+pub trait ChatCompletionsApi: Debug + Clone + Send + Sync + 'static {
+    /// The name of the provider in warnings, e.g. "Perplexity".
+    const NAME: &'static str;
+
+    /// The default provider name reported by the model, e.g. "perplexity".
+    const PROVIDER_NAME: &'static str;
+
+    /// The default API base URL.
+    const BASE_URL: &'static str;
+
+    /// The environment variable the API key is read from by default.
+    const API_KEY_ENV: &'static str;
+
+    /// The default model.
+    const MODEL_NAME: &'static str;
+
+    /// Whether `top_k` is supported.
+    const TOP_K: bool = true;
+
+    /// Whether stop sequences and a seed are supported.
+    const STOP_AND_SEED: bool = false;
+
+    /// Whether structured output is requested with a json schema
+    /// `response_format`.
+    const JSON_SCHEMA: bool = false;
+
+    /// Whether multiple completions can be requested with `n`.
+    const MULTIPLE_COMPLETIONS: bool = false;
+
+    /// Whether audio input parts and audio output are supported.
+    const AUDIO: bool = false;
+
+    /// Whether messages must alternate between user and assistant, in which
+    /// case consecutive messages of the same role are merged.
+    const ALTERNATING_ROLES: bool = false;
+
+    /// Returns the features supported by the given model.
+    fn capabilities(model_name: &str) -> Capabilities {
+        let _ = model_name;
+        Capabilities {
+            streaming: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// A model served through the chat completions api `A`.
+#[derive(Debug, Clone)]
+pub struct ChatCompletions<A> {
+    client: Client<ProviderConfig>,
+    http_client: reqwest::Client,
+    settings: ChatCompletionsSettings,
+    api: PhantomData<A>,
+}
+
+impl<A: ChatCompletionsApi> ChatCompletions<A> {
+    /// Creates a new model of the api `A` with the given model name.
+    pub fn new(model_name: impl Into<String>) -> Self {
+        ChatCompletionsSettingsBuilder::default()
+            .model_name(model_name.into())
+            .build()
+            .expect("Failed to build ChatCompletionsSettings")
+    }
+
+    /// Provider setting builder.
+    pub fn builder() -> ChatCompletionsSettingsBuilder<A> {
+        ChatCompletionsSettingsBuilder::default()
+    }
+}
+
+#[async_trait]
+impl<A: ChatCompletionsApi> Provider for ChatCompletions<A> {}
+
+#[async_trait]
+impl<A: ChatCompletionsApi> LanguageModel for ChatCompletions<A> {
+    fn model_name(&self) -> String {
+        self.settings.model_name.clone()
+    }
+
+    fn provider_name(&self) -> &str {
+        &self.settings.provider_name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        A::capabilities(&self.settings.model_name)
+    }
+
+    fn settings_key(&self) -> String {
+        self.settings.base_url.clone()
+    }
+
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        let mut request = conversions::request::<A>(options);
+        request.model = self.settings.model_name.to_string();
+
+        prepare_request_body(request, self.settings.before_request.as_ref())
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        let warnings = conversions::warnings::<A>(&options);
+        warnings.iter().for_each(Warning::log);
+        let client = request_client(&self.client, &self.http_client, &options.headers)?;
+        let audio = options.audio_output.clone().filter(|_| A::AUDIO);
+        let body = self.request_body(options)?;
+
+        let mut response = generate(&client, &self.http_client, &body, audio.as_ref()).await?;
+        response.warnings = warnings;
+        Ok(response)
+    }
+
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        conversions::warnings::<A>(&options)
+            .iter()
+            .for_each(Warning::log);
+        let client = request_client(&self.client, &self.http_client, &options.headers)?;
+        let mut request = conversions::request::<A>(options);
+        request.model = self.settings.model_name.to_string();
+        request.stream = true;
+        let audio = request.audio.clone();
+
+        let body = prepare_request_body(request, self.settings.before_request.as_ref())?;
+
+        stream(&client, &self.http_client, &body, audio).await
+    }
+}
+
+/// Sends a chat completions request, reading generated audio in the format
+/// requested with `audio`.
+pub(crate) async fn generate(
+    client: &Client<ProviderConfig>,
+    http_client: &reqwest::Client,
+    body: &serde_json::Value,
+    audio: Option<&AudioOutput>,
+) -> Result<LanguageModelResponse> {
+    let response: ChatCompletionsResponse =
+        post_json(client, http_client, "/chat/completions", body).await?;
+
+    Ok(response.into_response(audio))
+}
+
+/// Sends a streaming chat completions request, reading generated audio in
+/// the format requested with `audio`.
+pub(crate) async fn stream(
+    client: &Client<ProviderConfig>,
+    http_client: &reqwest::Client,
+    body: &serde_json::Value,
+    audio: Option<AudioOutput>,
+) -> Result<ProviderStream> {
+    let chat_stream =
+        post_stream::<ChatCompletionsResponse>(client, http_client, "/chat/completions", body)
+            .await?;
+
+    struct StreamState {
+        choices: StreamedChoices,
+        completed: bool,
+    }
+
+    let stream = chat_stream.scan::<_, Result<Vec<LanguageModelStreamChunk>>, _, _>(
+        StreamState {
+            choices: StreamedChoices::new(audio.as_ref()),
+            completed: false,
+        },
+        |state, evt_res| {
+            // If already completed, don't emit anything more
+            if state.completed {
+                return futures::future::ready(None);
+            };
+
+            futures::future::ready(match evt_res {
+                Ok(chunk) => {
+                    let (chunks, finished) = stream_chunks(&mut state.choices, chunk);
+                    state.completed = finished;
+                    Some(Ok(chunks))
+                }
+                Err(e) => {
+                    state.completed = true;
+                    Some(Err(e))
+                }
+            })
+        },
+    );
+
+    Ok(Box::pin(stream))
+}
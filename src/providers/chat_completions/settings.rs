@@ -0,0 +1,120 @@
+//! Defines the settings shared by the chat completions providers.
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use async_openai::{Client, config::OpenAIConfig};
+
+use crate::{
+    core::provider::BeforeRequestHook,
+    error::Error,
+    providers::{
+        TlsSettings,
+        chat_completions::{ChatCompletions, ChatCompletionsApi},
+        client::{ProviderConfig, client_builder_methods, resolve_http_client},
+    },
+};
+
+/// Settings of a chat completions provider.
+#[derive(Clone)]
+pub struct ChatCompletionsSettings {
+    /// The API base URL.
+    pub base_url: String,
+
+    /// The API key.
+    pub api_key: String,
+
+    /// The name of the provider.
+    pub provider_name: String,
+
+    /// The name of the model to use.
+    pub model_name: String,
+
+    /// Timeout applied to every request, if set.
+    pub request_timeout: Option<Duration>,
+
+    /// TLS settings of the http client.
+    pub tls: TlsSettings,
+
+    /// Hook called with the json body of every request before it is sent.
+    pub before_request: Option<BeforeRequestHook>,
+}
+
+impl Debug for ChatCompletionsSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChatCompletionsSettings")
+            .field("base_url", &self.base_url)
+            .field("provider_name", &self.provider_name)
+            .field("model_name", &self.model_name)
+            .field("request_timeout", &self.request_timeout)
+            .field("tls", &self.tls)
+            .field("before_request", &self.before_request.is_some())
+            .finish()
+    }
+}
+
+/// Builder of a model of the chat completions api `A`, defaulting to the
+/// settings of `A`.
+pub struct ChatCompletionsSettingsBuilder<A> {
+    base_url: Option<String>,
+    api_key: Option<String>,
+    provider_name: Option<String>,
+    model_name: Option<String>,
+    http_client: Option<reqwest::Client>,
+    request_timeout: Option<Duration>,
+    tls: TlsSettings,
+    before_request: Option<BeforeRequestHook>,
+    api: PhantomData<A>,
+}
+
+impl<A: ChatCompletionsApi> ChatCompletionsSettingsBuilder<A> {
+    client_builder_methods!();
+
+    pub fn build(self) -> Result<ChatCompletions<A>, Error> {
+        let settings = ChatCompletionsSettings {
+            base_url: self.base_url.unwrap_or_default(),
+            api_key: self.api_key.unwrap_or_default(),
+            provider_name: self
+                .provider_name
+                .unwrap_or_else(|| A::PROVIDER_NAME.to_string()),
+            model_name: self.model_name.unwrap_or_else(|| A::MODEL_NAME.to_string()),
+            request_timeout: self.request_timeout,
+            tls: self.tls,
+            before_request: self.before_request,
+        };
+
+        let client = Client::with_config(ProviderConfig::new(
+            OpenAIConfig::new()
+                .with_api_base(settings.base_url.to_string())
+                .with_api_key(settings.api_key.to_string()),
+        ));
+
+        let http_client =
+            resolve_http_client(self.http_client, settings.request_timeout, &settings.tls)?;
+        let client = client.with_http_client(http_client.clone());
+
+        Ok(ChatCompletions {
+            settings,
+            client,
+            http_client,
+            api: PhantomData,
+        })
+    }
+}
+
+impl<A: ChatCompletionsApi> Default for ChatCompletionsSettingsBuilder<A> {
+    fn default() -> Self {
+        Self {
+            base_url: Some(A::BASE_URL.to_string()),
+            api_key: Some(std::env::var(A::API_KEY_ENV).unwrap_or_default()),
+            provider_name: Some(A::PROVIDER_NAME.to_string()),
+            model_name: Some(A::MODEL_NAME.to_string()),
+            http_client: None,
+            request_timeout: None,
+            tls: TlsSettings::default(),
+            before_request: None,
+            api: PhantomData,
+        }
+    }
+}
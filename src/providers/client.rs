@@ -0,0 +1,675 @@
+//! The http client, configuration and request helpers shared by the
+//! providers built on top of `async-openai`.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+
+// providers built on top of the `async-openai` client share its error type.
+impl crate::error::ProviderError for async_openai::error::OpenAIError {
+    fn is_retryable(&self) -> bool {
+        use async_openai::error::OpenAIError;
+
+        // failed responses are `ResponseError`s, left are transport errors
+        // and errors of the remaining `async-openai` requests
+        match self {
+            OpenAIError::Reqwest(e) => e.is_timeout() || e.is_connect(),
+            OpenAIError::ApiError(e) => e.r#type.as_deref() == Some("server_error"),
+            _ => false,
+        }
+    }
+}
+
+/// Converts errors of the `async-openai` client, mapping timeouts of the
+/// http client to `Error::Timeout`.
+impl From<async_openai::error::OpenAIError> for crate::error::Error {
+    fn from(error: async_openai::error::OpenAIError) -> Self {
+        match error {
+            async_openai::error::OpenAIError::Reqwest(e) if e.is_timeout() => {
+                crate::error::Error::Timeout(e.to_string())
+            }
+            error => crate::error::Error::ProviderError(std::sync::Arc::new(error)),
+        }
+    }
+}
+
+/// How often idle pooled connections are probed, so long streams and
+/// pauses between requests do not lose them.
+const TCP_KEEPALIVE: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// TLS settings of the http client built by a provider, e.g. to reach an
+/// internal gateway or a corporate proxy with a self-signed certificate.
+///
+/// Prefer trusting the certificate of the gateway with `root_certificates`.
+/// `danger_accept_invalid_certs` turns off the verification of the server's
+/// identity altogether, anyone on the network path can then read and modify
+/// requests and responses, including the api key.
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    /// Root certificates trusted in addition to the system ones.
+    pub root_certificates: Vec<reqwest::Certificate>,
+
+    /// Accepts any server certificate, including self-signed, expired and
+    /// mismatched ones. Insecure, only meant for development.
+    pub danger_accept_invalid_certs: bool,
+}
+
+impl TlsSettings {
+    fn is_default(&self) -> bool {
+        self.root_certificates.is_empty() && !self.danger_accept_invalid_certs
+    }
+}
+
+/// Resolves the http client used by `async-openai` based providers. Without
+/// a custom client, a client with TCP keep-alive, the `request_timeout`, if
+/// any, and the `tls` settings is built. The provider stores the client and
+/// shares its connection pool between all requests, including streams.
+pub(crate) fn resolve_http_client(
+    http_client: Option<reqwest::Client>,
+    request_timeout: Option<std::time::Duration>,
+    tls: &TlsSettings,
+) -> crate::error::Result<reqwest::Client> {
+    if let Some(client) = http_client {
+        if request_timeout.is_some() {
+            log::warn!(
+                "request_timeout is ignored when a custom http client is set, configure the timeout on the client instead"
+            );
+        }
+        if !tls.is_default() {
+            log::warn!(
+                "TLS settings are ignored when a custom http client is set, configure them on the client instead"
+            );
+        }
+        return Ok(client);
+    }
+
+    let mut builder = reqwest::Client::builder().tcp_keepalive(TCP_KEEPALIVE);
+    if let Some(timeout) = request_timeout {
+        builder = builder.timeout(timeout);
+    }
+    for certificate in &tls.root_certificates {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+    if tls.danger_accept_invalid_certs {
+        log::warn!("TLS certificate verification is disabled, connections are not secure");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder
+        .build()
+        .map_err(|e| crate::error::Error::Other(format!("Failed to build http client: {e}")))
+}
+
+/// The `async-openai` config used by the providers, an `OpenAIConfig` that
+/// also sends the extra headers of a request.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProviderConfig {
+    inner: async_openai::config::OpenAIConfig,
+    headers: reqwest::header::HeaderMap,
+}
+
+impl ProviderConfig {
+    pub(crate) fn new(inner: async_openai::config::OpenAIConfig) -> Self {
+        Self {
+            inner,
+            headers: Default::default(),
+        }
+    }
+
+    /// Returns a copy of the config also sending `headers`.
+    fn with_headers(&self, headers: &HashMap<String, String>) -> crate::error::Result<Self> {
+        use reqwest::header::{HeaderName, HeaderValue};
+
+        let mut config = self.clone();
+        for (name, value) in headers {
+            let invalid = |e: &dyn std::fmt::Display| {
+                crate::error::Error::InvalidInput(format!("Invalid header `{name}`: {e}"))
+            };
+            config.headers.insert(
+                HeaderName::try_from(name).map_err(|e| invalid(&e))?,
+                HeaderValue::try_from(value).map_err(|e| invalid(&e))?,
+            );
+        }
+        Ok(config)
+    }
+}
+
+impl async_openai::config::Config for ProviderConfig {
+    /// The extra headers, with the content type and the headers of the inner
+    /// config, e.g. auth, taking precedence. Without an api key no auth
+    /// header is sent, e.g. for local servers.
+    fn headers(&self) -> reqwest::header::HeaderMap {
+        use secrecy::ExposeSecret;
+
+        let no_api_key = self.inner.api_key().expose_secret().is_empty();
+        let mut headers = self.headers.clone();
+        headers.remove(reqwest::header::CONTENT_TYPE);
+        for (name, value) in self.inner.headers() {
+            match name {
+                Some(name) if no_api_key && name == reqwest::header::AUTHORIZATION => {}
+                Some(name) => {
+                    headers.insert(name, value);
+                }
+                None => {}
+            }
+        }
+        headers
+    }
+
+    fn url(&self, path: &str) -> String {
+        self.inner.url(path)
+    }
+
+    fn query(&self) -> Vec<(&str, &str)> {
+        self.inner.query()
+    }
+
+    fn api_base(&self) -> &str {
+        self.inner.api_base()
+    }
+
+    fn api_key(&self) -> &secrecy::SecretString {
+        self.inner.api_key()
+    }
+}
+
+/// Returns the client to send a request with `headers` with, `client` itself
+/// if there are none.
+pub(crate) fn request_client(
+    client: &async_openai::Client<ProviderConfig>,
+    http_client: &reqwest::Client,
+    headers: &HashMap<String, String>,
+) -> crate::error::Result<async_openai::Client<ProviderConfig>> {
+    if headers.is_empty() {
+        return Ok(client.clone());
+    }
+
+    let config = client.config().with_headers(headers)?;
+    Ok(async_openai::Client::with_config(config).with_http_client(http_client.clone()))
+}
+
+/// A failed request to an `async-openai` based provider, with the id the
+/// provider assigned to it.
+#[derive(Debug, thiserror::Error)]
+#[error("{status}: {error}")]
+pub(crate) struct ResponseError {
+    status: reqwest::StatusCode,
+    error: async_openai::error::ApiError,
+    request_id: Option<String>,
+}
+
+impl crate::error::ProviderError for ResponseError {
+    /// Rate limits, unless the quota is used up, and server errors.
+    fn is_retryable(&self) -> bool {
+        match self.status.as_u16() {
+            429 => self.error.r#type.as_deref() != Some("insufficient_quota"),
+            status => status >= 500,
+        }
+    }
+
+    fn request_id(&self) -> Option<String> {
+        self.request_id.clone()
+    }
+}
+
+/// A stream of the deserialized Server-Sent Events of a response.
+pub(crate) type EventStream<O> = Pin<Box<dyn Stream<Item = crate::error::Result<O>> + Send>>;
+
+/// Sends a request with `body` to `path`. Unlike the `async-openai` client, a
+/// failed request keeps the `x-request-id` header of the response. Rate
+/// limits and server errors are not retried here but by the retry policy of
+/// the request.
+async fn send(
+    client: &async_openai::Client<ProviderConfig>,
+    http_client: &reqwest::Client,
+    path: &str,
+    body: &serde_json::Value,
+) -> crate::error::Result<reqwest::Response> {
+    use async_openai::config::Config;
+    use async_openai::error::{ApiError, OpenAIError, WrappedError};
+
+    let config = client.config();
+    let response = http_client
+        .post(config.url(path))
+        .query(&config.query())
+        .headers(config.headers())
+        .json(body)
+        .send()
+        .await
+        .map_err(OpenAIError::Reqwest)?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let request_id = response
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_string);
+    let bytes = response.bytes().await.map_err(OpenAIError::Reqwest)?;
+
+    // server errors are not guaranteed to be json
+    let error = serde_json::from_slice::<WrappedError>(&bytes)
+        .map(|wrapped| wrapped.error)
+        .unwrap_or_else(|_| ApiError {
+            message: String::from_utf8_lossy(&bytes).into_owned(),
+            r#type: None,
+            param: None,
+            code: None,
+        });
+    Err(crate::error::Error::ProviderError(std::sync::Arc::new(
+        ResponseError {
+            status,
+            error,
+            request_id,
+        },
+    )))
+}
+
+/// Sends a non-streaming request with `body` to `path` and deserializes the
+/// response, see `send`.
+pub(crate) async fn post_json<O: serde::de::DeserializeOwned>(
+    client: &async_openai::Client<ProviderConfig>,
+    http_client: &reqwest::Client,
+    path: &str,
+    body: &serde_json::Value,
+) -> crate::error::Result<O> {
+    let response = send(client, http_client, path, body).await?;
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(async_openai::error::OpenAIError::Reqwest)?;
+
+    serde_json::from_slice(&bytes).map_err(|e| crate::error::Error::ApiError(e.to_string()))
+}
+
+/// Sends a streaming request with `body` to `path` and deserializes the data
+/// of every Server-Sent Event of the response until `[DONE]`, see `send`.
+pub(crate) async fn post_stream<O: serde::de::DeserializeOwned + Send + 'static>(
+    client: &async_openai::Client<ProviderConfig>,
+    http_client: &reqwest::Client,
+    path: &str,
+    body: &serde_json::Value,
+) -> crate::error::Result<EventStream<O>> {
+    let response = send(client, http_client, path, body).await?;
+
+    let events = futures::stream::unfold(
+        (response.bytes_stream(), String::new(), false),
+        |(mut bytes, mut buffer, mut ended)| async move {
+            loop {
+                // an event ends with a blank line, the last one possibly
+                // with the body
+                let event = match buffer.find("\n\n") {
+                    Some(end) => {
+                        let event = buffer[..end].to_string();
+                        buffer.drain(..end + 2);
+                        event
+                    }
+                    None if ended => std::mem::take(&mut buffer),
+                    None => {
+                        match bytes.next().await {
+                            Some(Ok(chunk)) => {
+                                buffer.push_str(&String::from_utf8_lossy(&chunk).replace('\r', ""))
+                            }
+                            Some(Err(e)) => {
+                                let error = async_openai::error::OpenAIError::Reqwest(e).into();
+                                return Some((Err(error), (bytes, String::new(), true)));
+                            }
+                            None => ended = true,
+                        }
+                        continue;
+                    }
+                };
+
+                let data = event
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if data == "[DONE]" || (data.is_empty() && ended && buffer.is_empty()) {
+                    return None;
+                }
+                if data.is_empty() {
+                    // comments and keep-alives
+                    continue;
+                }
+
+                let item = serde_json::from_str(&data)
+                    .map_err(|e| crate::error::Error::ApiError(e.to_string()));
+                return Some((item, (bytes, buffer, ended)));
+            }
+        },
+    );
+
+    Ok(Box::pin(events))
+}
+
+/// Serializes a request body and applies the `before_request` hook to it.
+pub(crate) fn prepare_request_body<T: serde::Serialize>(
+    request: T,
+    before_request: Option<&crate::core::provider::BeforeRequestHook>,
+) -> crate::error::Result<serde_json::Value> {
+    let mut body = serde_json::to_value(request)
+        .map_err(|e| crate::error::Error::InvalidInput(format!("Invalid request body: {e}")))?;
+    if let Some(hook) = before_request {
+        hook(&mut body);
+    }
+    Ok(body)
+}
+
+/// Implements the builder methods shared by the settings builders of the
+/// providers built on top of `async-openai`. The builder needs the
+/// `base_url`, `api_key`, `provider_name`, `model_name`, `http_client`,
+/// `request_timeout`, `tls` and `before_request` fields.
+macro_rules! client_builder_methods {
+    () => {
+        pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+            self.base_url = Some(base_url.into());
+            self
+        }
+
+        pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+            self.api_key = Some(api_key.into());
+            self
+        }
+
+        pub fn provider_name(mut self, provider_name: impl Into<String>) -> Self {
+            self.provider_name = Some(provider_name.into());
+            self
+        }
+
+        pub fn model_name(mut self, model_name: impl Into<String>) -> Self {
+            self.model_name = Some(model_name.into());
+            self
+        }
+
+        /// Sets the http client used for requests, e.g. to configure proxies,
+        /// connection pools or custom TLS.
+        pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+            self.http_client = Some(http_client);
+            self
+        }
+
+        /// Sets the timeout for each request. Ignored if a custom `http_client`
+        /// is set, configure the timeout on that client instead.
+        pub fn request_timeout(mut self, request_timeout: std::time::Duration) -> Self {
+            self.request_timeout = Some(request_timeout);
+            self
+        }
+
+        /// Trusts an additional root certificate, e.g. of a corporate proxy or an
+        /// internal gateway. Ignored if a custom `http_client` is set.
+        pub fn root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+            self.tls.root_certificates.push(certificate);
+            self
+        }
+
+        /// Disables the verification of server certificates. Insecure, see
+        /// `TlsSettings`, prefer `root_certificate`. Ignored if a custom
+        /// `http_client` is set.
+        pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+            self.tls.danger_accept_invalid_certs = accept;
+            self
+        }
+
+        /// Sets a hook called with the json body of every request just before it
+        /// is sent, e.g. to log it or add provider specific fields.
+        pub fn before_request<F>(mut self, hook: F) -> Self
+        where
+            F: Fn(&mut serde_json::Value) + Send + Sync + 'static,
+        {
+            self.before_request = Some(std::sync::Arc::new(hook));
+            self
+        }
+    };
+}
+
+pub(crate) use client_builder_methods;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    #[test]
+    fn test_resolve_http_client_builds_default_client() {
+        assert!(resolve_http_client(None, None, &TlsSettings::default()).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_http_client_with_timeout() {
+        assert!(
+            resolve_http_client(None, Some(Duration::from_secs(5)), &TlsSettings::default())
+                .is_ok()
+        );
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_resolve_http_client_prefers_custom_client() {
+        use crate::core::language_model::{LanguageModel, LanguageModelOptions};
+        use crate::core::messages::{Message, TaggedMessage};
+        use crate::providers::openai::OpenAI;
+        use crate::test_utils::{response_payload, serve_json_with_headers};
+
+        let (base_url, _, headers) = serve_json_with_headers(response_payload()).await;
+        let mut default_headers = reqwest::header::HeaderMap::new();
+        default_headers.insert("x-client", "custom".parse().unwrap());
+        let client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .build()
+            .unwrap();
+
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .http_client(client)
+            .request_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            ..Default::default()
+        };
+        openai.generate_text(options).await.unwrap();
+
+        let headers = headers.lock().unwrap();
+        assert_eq!(
+            headers[0].get("x-client").map(String::as_str),
+            Some("custom")
+        );
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_resolve_http_client_trusts_root_certificate() {
+        use crate::test_utils::{GATEWAY_CERT, serve_json_tls};
+
+        let base_url = serve_json_tls(serde_json::json!({})).await;
+        let tls = TlsSettings {
+            root_certificates: vec![
+                reqwest::Certificate::from_pem(GATEWAY_CERT.as_bytes()).unwrap(),
+            ],
+            danger_accept_invalid_certs: false,
+        };
+
+        let trusting = resolve_http_client(None, None, &tls).unwrap();
+        assert!(trusting.get(&base_url).send().await.is_ok());
+
+        let untrusting = resolve_http_client(None, None, &TlsSettings::default()).unwrap();
+        assert!(untrusting.get(&base_url).send().await.is_err());
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_resolve_http_client_accepting_invalid_certs() {
+        let base_url = crate::test_utils::serve_json_tls(serde_json::json!({})).await;
+        let tls = TlsSettings {
+            root_certificates: Vec::new(),
+            danger_accept_invalid_certs: true,
+        };
+
+        let client = resolve_http_client(None, None, &tls).unwrap();
+        assert!(client.get(&base_url).send().await.is_ok());
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_sequential_generations_reuse_connection() {
+        use crate::core::LanguageModelRequest;
+        use crate::providers::openai::OpenAI;
+        use crate::test_utils::{response_payload, serve_json_counting_connections};
+        use std::sync::atomic::Ordering;
+
+        let (base_url, requests, _, connections) =
+            serve_json_counting_connections(response_payload()).await;
+
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            LanguageModelRequest::builder()
+                .model(openai.clone())
+                .prompt("hi")
+                .build()
+                .generate_text()
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_failed_request_keeps_request_id() {
+        use crate::core::LanguageModelRequest;
+        use crate::core::retry::RetryPolicy;
+        use crate::providers::openai::OpenAI;
+        use crate::test_utils::serve_response;
+
+        let generate = |base_url: String| async move {
+            let openai = OpenAI::builder()
+                .base_url(base_url)
+                .api_key("test")
+                .build()
+                .unwrap();
+            LanguageModelRequest::builder()
+                .model(openai)
+                .prompt("hi")
+                .retry_policy(RetryPolicy::none())
+                .build()
+                .generate_text()
+                .await
+                .unwrap_err()
+        };
+
+        let rejected = generate(
+            serve_response(
+                "HTTP/1.1 400 Bad Request\r\ncontent-type: application/json\r\nx-request-id: req_123",
+                r#"{"error":{"message":"Invalid model","type":"invalid_request_error","param":null,"code":null}}"#,
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(rejected.request_id(), Some("req_123".to_string()));
+        assert!(rejected.to_string().contains("Invalid model"));
+        assert!(!rejected.is_retryable());
+
+        let unavailable = generate(
+            serve_response(
+                "HTTP/1.1 503 Service Unavailable\r\ncontent-type: text/plain\r\nx-request-id: req_456",
+                "upstream unavailable",
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(unavailable.request_id(), Some("req_456".to_string()));
+        assert!(unavailable.is_retryable());
+    }
+
+    #[cfg(all(feature = "openai", feature = "perplexity"))]
+    #[tokio::test]
+    async fn test_failed_stream_keeps_request_id() {
+        use crate::core::language_model::{LanguageModel, LanguageModelOptions};
+        use crate::core::messages::{Message, TaggedMessage};
+        use crate::providers::openai::OpenAI;
+        use crate::providers::perplexity::Perplexity;
+        use crate::test_utils::serve_response;
+
+        let rejected = || {
+            serve_response(
+                "HTTP/1.1 400 Bad Request\r\ncontent-type: application/json\r\nx-request-id: req_789",
+                r#"{"error":{"message":"Invalid model","type":"invalid_request_error","param":null,"code":null}}"#,
+            )
+        };
+        let options = || LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            ..Default::default()
+        };
+
+        // the responses api
+        let openai = OpenAI::builder()
+            .base_url(rejected().await)
+            .api_key("test")
+            .build()
+            .unwrap();
+        let error = openai.stream_text(options()).await.err().unwrap();
+        assert_eq!(error.request_id(), Some("req_789".to_string()));
+        assert!(error.to_string().contains("Invalid model"));
+
+        // the chat completions api
+        let perplexity = Perplexity::builder()
+            .base_url(rejected().await)
+            .api_key("test")
+            .build()
+            .unwrap();
+        let error = perplexity.stream_text(options()).await.err().unwrap();
+        assert_eq!(error.request_id(), Some("req_789".to_string()));
+    }
+
+    #[cfg(feature = "perplexity")]
+    #[tokio::test]
+    async fn test_provider_request_timeout_is_applied() {
+        use crate::core::language_model::{LanguageModel, LanguageModelOptions};
+        use crate::core::messages::{Message, TaggedMessage};
+        use crate::error::Error;
+        use crate::providers::perplexity::Perplexity;
+
+        // accepts connections but never responds
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let model = Perplexity::builder()
+            .base_url(format!("http://{addr}"))
+            .api_key("test")
+            .request_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            ..Default::default()
+        };
+
+        let result =
+            tokio::time::timeout(Duration::from_secs(10), model.generate_text(options)).await;
+        server.abort();
+
+        match result {
+            Ok(Err(error @ Error::Timeout(_))) => assert!(error.is_retryable()),
+            other => panic!("request should time out, got {other:?}"),
+        }
+    }
+}
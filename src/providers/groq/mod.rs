@@ -0,0 +1,267 @@
+//! This module provides the Groq provider, which implements the `LanguageModel`
+//! and `Provider` traits for interacting with the Groq API.
+//!
+//! Groq serves an OpenAI compatible api, so requests are converted and sent by
+//! the OpenAI provider. Options Groq rejects are removed with a warning before
+//! the request is sent.
+
+pub mod settings;
+
+use crate::core::language_model::{
+    Capabilities, LanguageModelOptions, LanguageModelResponse, ProviderStream, Warning,
+};
+use crate::core::messages::{ContentPart, Message};
+use crate::providers::groq::settings::{GroqProviderSettings, GroqProviderSettingsBuilder};
+use crate::providers::openai::OpenAI;
+use crate::{
+    core::{language_model::LanguageModel, provider::Provider},
+    error::Result,
+};
+use async_trait::async_trait;
+
+/// The Groq provider.
+#[derive(Debug, Clone)]
+pub struct Groq {
+    inner: OpenAI,
+    settings: GroqProviderSettings,
+}
+
+impl Groq {
+    /// Creates a new `Groq` provider with the given model name.
+    pub fn new(model_name: impl Into<String>) -> Self {
+        GroqProviderSettingsBuilder::default()
+            .model_name(model_name.into())
+            .build()
+            .expect("Failed to build GroqProviderSettings")
+    }
+
+    /// Groq provider setting builder.
+    pub fn builder() -> GroqProviderSettingsBuilder {
+        GroqProviderSettings::builder()
+    }
+}
+
+/// Removes the options Groq does not support, logging a warning for each,
+/// and returns the warnings.
+pub(crate) fn remove_unsupported_options(options: &mut LanguageModelOptions) -> Vec<Warning> {
+    let mut removed = Vec::new();
+
+    if options.logprobs.take().is_some() {
+        removed.push("logprobs");
+    }
+    if options.top_logprobs.take().is_some() {
+        removed.push("top_logprobs");
+    }
+    if options.presence_penalty.take().is_some() {
+        removed.push("presence_penalty");
+    }
+    if options.frequency_penalty.take().is_some() {
+        removed.push("frequency_penalty");
+    }
+    if options.n.take_if(|n| *n > 1).is_some() {
+        removed.push("n");
+    }
+    if options.store.take().is_some() {
+        removed.push("store");
+    }
+    if options.previous_response_id.take().is_some() {
+        removed.push("previous_response_id");
+    }
+    if options.has_audio_input() {
+        for tagged in options.messages.iter_mut() {
+            if let Message::User(u) = &mut tagged.message {
+                u.parts
+                    .retain(|part| !matches!(part, ContentPart::Audio { .. }));
+            }
+        }
+        removed.push("audio");
+    }
+    if options.audio_output.take().is_some() {
+        removed.push("audio_output");
+    }
+
+    let warnings: Vec<Warning> = removed
+        .into_iter()
+        .map(|option| Warning::unsupported(option, "Groq"))
+        .collect();
+    warnings.iter().for_each(Warning::log);
+
+    warnings
+}
+
+#[async_trait]
+impl Provider for Groq {
+    async fn list_models(&self) -> Result<Vec<String>> {
+        self.inner.list_models().await
+    }
+}
+
+#[async_trait]
+impl LanguageModel for Groq {
+    fn model_name(&self) -> String {
+        self.settings.model_name.clone()
+    }
+
+    fn provider_name(&self) -> &str {
+        &self.settings.provider_name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let model = self.settings.model_name.as_str();
+        Capabilities {
+            tools: true,
+            vision: model.contains("llama-4"),
+            reasoning: ["qwen3", "deepseek-r1", "gpt-oss"]
+                .iter()
+                .any(|name| model.contains(name)),
+            streaming: true,
+            audio: false,
+        }
+    }
+
+    fn settings_key(&self) -> String {
+        self.inner.settings_key()
+    }
+
+    fn request_body(&self, mut options: LanguageModelOptions) -> Result<serde_json::Value> {
+        remove_unsupported_options(&mut options);
+        self.inner.request_body(options)
+    }
+
+    async fn generate_text(
+        &self,
+        mut options: LanguageModelOptions,
+    ) -> Result<LanguageModelResponse> {
+        let warnings = remove_unsupported_options(&mut options);
+        let mut response = self.inner.generate_text(options).await?;
+
+        response.warnings.splice(0..0, warnings);
+        Ok(response)
+    }
+
+    async fn stream_text(&self, mut options: LanguageModelOptions) -> Result<ProviderStream> {
+        remove_unsupported_options(&mut options);
+        self.inner.stream_text(options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::language_model::{AudioOutput, LanguageModelResponseContentType};
+    use crate::core::messages::{TaggedMessage, UserMessage};
+    use crate::test_utils::{response_payload, serve_json};
+
+    #[test]
+    fn test_remove_unsupported_options() {
+        let mut options = LanguageModelOptions {
+            logprobs: Some(true),
+            top_logprobs: Some(3),
+            frequency_penalty: Some(0.5),
+            temperature: Some(70),
+            ..Default::default()
+        };
+
+        let warnings = remove_unsupported_options(&mut options);
+
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::unsupported("logprobs", "Groq"),
+                Warning::unsupported("top_logprobs", "Groq"),
+                Warning::unsupported("frequency_penalty", "Groq"),
+            ]
+        );
+        assert_eq!(options.logprobs, None);
+        assert_eq!(options.top_logprobs, None);
+        assert_eq!(options.frequency_penalty, None);
+        assert_eq!(options.temperature, Some(70));
+    }
+
+    #[test]
+    fn test_remove_audio_parts() {
+        let mut options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User(
+                UserMessage::new("hi").with_audio("audio/wav", "UklGRg=="),
+            ))],
+            audio_output: Some(AudioOutput::new("alloy", "wav")),
+            ..Default::default()
+        };
+
+        let warnings = remove_unsupported_options(&mut options);
+        let removed: Vec<&str> = warnings.iter().map(|w| w.option.as_str()).collect();
+        assert_eq!(removed, vec!["audio", "audio_output"]);
+        assert!(!options.has_audio_input());
+        assert!(options.audio_output.is_none());
+    }
+
+    #[test]
+    fn test_provider_and_model_name() {
+        let model = Groq::builder()
+            .model_name("llama-3.1-8b-instant")
+            .api_key("test")
+            .build()
+            .unwrap();
+        assert_eq!(model.provider_name(), "groq");
+        assert_eq!(model.model_name(), "llama-3.1-8b-instant");
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let scout = Groq::new("meta-llama/llama-4-scout-17b-16e-instruct").capabilities();
+        assert!(scout.tools && scout.vision && !scout.reasoning);
+
+        let gpt_oss = Groq::new("openai/gpt-oss-120b").capabilities();
+        assert!(gpt_oss.reasoning && !gpt_oss.vision);
+    }
+
+    #[test]
+    fn test_tls_settings_are_forwarded() {
+        let groq = Groq::builder()
+            .api_key("test")
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+
+        assert!(groq.settings.tls.danger_accept_invalid_certs);
+        assert!(format!("{:?}", groq.inner).contains("danger_accept_invalid_certs: true"));
+    }
+
+    #[test]
+    fn test_remove_unsupported_options_none_set() {
+        let mut options = LanguageModelOptions::default();
+        assert!(remove_unsupported_options(&mut options).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_groq_basic_completion() {
+        let base_url = serve_json(response_payload()).await;
+
+        let groq = Groq::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            logprobs: Some(true),
+            seed: Some(42),
+            ..Default::default()
+        };
+
+        let response = groq.generate_text(options).await.unwrap();
+        assert_eq!(
+            response.warnings,
+            vec![
+                Warning::unsupported("logprobs", "Groq"),
+                Warning::unsupported("seed", "Groq")
+            ]
+        );
+        assert_eq!(response.contents.len(), 1);
+        assert!(matches!(
+            &response.contents[0],
+            LanguageModelResponseContentType::Text(t) if t == "Hello!"
+        ));
+    }
+}
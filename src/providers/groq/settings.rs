@@ -0,0 +1,126 @@
+//! Defines the settings for the Groq provider.
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use crate::{
+    core::provider::BeforeRequestHook,
+    error::Error,
+    providers::{TlsSettings, client::client_builder_methods, groq::Groq, openai::OpenAI},
+};
+
+/// Settings for the Groq provider.
+#[derive(Clone)]
+pub struct GroqProviderSettings {
+    /// The API base URL for the Groq API.
+    pub base_url: String,
+
+    /// The API key for the Groq API.
+    pub api_key: String,
+
+    /// The name of the provider.
+    pub provider_name: String,
+
+    /// The name of the model to use.
+    pub model_name: String,
+
+    /// Timeout applied to every request, if set.
+    pub request_timeout: Option<Duration>,
+
+    /// TLS settings of the http client.
+    pub tls: TlsSettings,
+
+    /// Hook called with the json body of every request before it is sent.
+    pub before_request: Option<BeforeRequestHook>,
+}
+
+impl Debug for GroqProviderSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GroqProviderSettings")
+            .field("base_url", &self.base_url)
+            .field("provider_name", &self.provider_name)
+            .field("model_name", &self.model_name)
+            .field("request_timeout", &self.request_timeout)
+            .field("tls", &self.tls)
+            .field("before_request", &self.before_request.is_some())
+            .finish()
+    }
+}
+
+impl GroqProviderSettings {
+    /// Creates a new builder for `GroqProviderSettings`.
+    pub fn builder() -> GroqProviderSettingsBuilder {
+        GroqProviderSettingsBuilder::default()
+    }
+}
+
+pub struct GroqProviderSettingsBuilder {
+    base_url: Option<String>,
+    api_key: Option<String>,
+    provider_name: Option<String>,
+    model_name: Option<String>,
+    http_client: Option<reqwest::Client>,
+    request_timeout: Option<Duration>,
+    tls: TlsSettings,
+    before_request: Option<BeforeRequestHook>,
+}
+
+impl GroqProviderSettingsBuilder {
+    client_builder_methods!();
+
+    pub fn build(self) -> Result<Groq, Error> {
+        let settings = GroqProviderSettings {
+            base_url: self.base_url.unwrap_or_default(),
+            api_key: self.api_key.unwrap_or_default(),
+            provider_name: self.provider_name.unwrap_or_else(|| "groq".to_string()),
+            model_name: self
+                .model_name
+                .unwrap_or_else(|| "llama-3.3-70b-versatile".to_string()),
+            request_timeout: self.request_timeout,
+            tls: self.tls,
+            before_request: self.before_request,
+        };
+
+        // groq exposes an openai compatible api, requests are sent through
+        // the openai provider.
+        let mut builder = OpenAI::builder()
+            .base_url(settings.base_url.to_string())
+            .api_key(settings.api_key.to_string())
+            .provider_name(settings.provider_name.to_string())
+            .model_name(settings.model_name.to_string());
+
+        if let Some(http_client) = self.http_client {
+            builder = builder.http_client(http_client);
+        }
+        if let Some(request_timeout) = settings.request_timeout {
+            builder = builder.request_timeout(request_timeout);
+        }
+        for certificate in &settings.tls.root_certificates {
+            builder = builder.root_certificate(certificate.clone());
+        }
+        builder = builder.danger_accept_invalid_certs(settings.tls.danger_accept_invalid_certs);
+        if let Some(hook) = settings.before_request.clone() {
+            builder = builder.before_request(move |body| hook(body));
+        }
+
+        Ok(Groq {
+            inner: builder.build()?.named("Groq"),
+            settings,
+        })
+    }
+}
+
+impl Default for GroqProviderSettingsBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: Some("https://api.groq.com/openai/v1".to_string()),
+            api_key: Some(std::env::var("GROQ_API_KEY").unwrap_or_default()),
+            provider_name: Some("groq".to_string()),
+            model_name: Some("llama-3.3-70b-versatile".to_string()),
+            http_client: None,
+            request_timeout: None,
+            tls: TlsSettings::default(),
+            before_request: None,
+        }
+    }
+}
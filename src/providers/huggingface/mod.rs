@@ -0,0 +1,43 @@
+//! This module provides the Hugging Face provider, which implements the `LanguageModel`
+//! and `Provider` traits for interacting with the Hugging Face inference router.
+//!
+//! The router serves models hosted on the Hub through an OpenAI compatible
+//! chat completions api. Models are selected by their Hub id.
+
+pub mod settings;
+
+use crate::providers::chat_completions::{ChatCompletions, ChatCompletionsApi};
+
+/// The chat completions api of the Hugging Face inference router.
+#[derive(Debug, Clone)]
+pub struct HuggingFaceApi;
+
+impl ChatCompletionsApi for HuggingFaceApi {
+    const NAME: &'static str = "Hugging Face";
+    const PROVIDER_NAME: &'static str = "huggingface";
+    const BASE_URL: &'static str = "https://router.huggingface.co/v1";
+    const API_KEY_ENV: &'static str = "HF_TOKEN";
+    const MODEL_NAME: &'static str = "meta-llama/Llama-3.1-8B-Instruct";
+    const STOP_AND_SEED: bool = true;
+    const MULTIPLE_COMPLETIONS: bool = true;
+}
+
+/// The Hugging Face provider.
+pub type HuggingFace = ChatCompletions<HuggingFaceApi>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::language_model::LanguageModel;
+
+    #[test]
+    fn test_provider_and_model_name() {
+        let model = HuggingFace::builder()
+            .model_name("Qwen/Qwen2.5-7B-Instruct")
+            .api_key("test")
+            .build()
+            .unwrap();
+        assert_eq!(model.provider_name(), "huggingface");
+        assert_eq!(model.model_name(), "Qwen/Qwen2.5-7B-Instruct");
+    }
+}
@@ -0,0 +1,12 @@
+//! Defines the settings for the Hugging Face provider.
+
+use crate::providers::chat_completions::settings::{
+    ChatCompletionsSettings, ChatCompletionsSettingsBuilder,
+};
+use crate::providers::huggingface::HuggingFaceApi;
+
+/// Settings for the Hugging Face provider.
+pub type HuggingFaceProviderSettings = ChatCompletionsSettings;
+
+/// Builder of the Hugging Face provider.
+pub type HuggingFaceProviderSettingsBuilder = ChatCompletionsSettingsBuilder<HuggingFaceApi>;
@@ -3,3 +3,21 @@
 
 #[cfg(feature = "openai")]
 pub mod openai;
+
+#[cfg(feature = "groq")]
+pub mod groq;
+
+#[cfg(feature = "huggingface")]
+pub mod huggingface;
+
+#[cfg(feature = "perplexity")]
+pub mod perplexity;
+
+#[cfg(any(feature = "openai", feature = "perplexity", feature = "huggingface"))]
+pub mod chat_completions;
+
+#[cfg(any(feature = "openai", feature = "perplexity", feature = "huggingface"))]
+pub(crate) mod client;
+
+#[cfg(any(feature = "openai", feature = "perplexity", feature = "huggingface"))]
+pub use client::TlsSettings;
@@ -1,19 +1,48 @@
 //! Helper functions and conversions for the OpenAI provider.
 
+use crate::core::image::{GeneratedImage, ImageOptions, ImageResponse};
 use crate::core::language_model::{
-    LanguageModelOptions, LanguageModelResponseContentType, ReasoningEffort, Usage,
+    LanguageModelOptions, LanguageModelResponseContentType, ReasoningEffort, TokenLogprob, Usage,
+    Warning,
 };
 use crate::core::messages::Message;
-use crate::core::tools::Tool;
+use crate::core::tools::{BuiltinToolCallInfo, Tool, ToolResultPart};
+use crate::providers::openai::settings::BuiltinTool;
 use async_openai::types::responses::{
-    CreateResponse, Function, Input, InputContent, InputItem, InputMessage, InputMessageType,
+    CodeInterpreter, CodeInterpreterContainer, CodeInterpreterContainerKind, CreateResponse,
+    FileSearch, Function, Input, InputContent, InputItem, InputMessage, InputMessageType,
     ReasoningConfig, ReasoningSummary, Role, TextConfig, TextResponseFormat, ToolDefinition,
-    Usage as OpenAIUsage,
+    Usage as OpenAIUsage, WebSearchPreview,
+};
+use async_openai::types::{
+    Image, ImagesResponse, ListModelResponse, ReasoningEffort as OpenAIReasoningEffort,
+    ResponseFormatJsonSchema,
 };
-use async_openai::types::{ReasoningEffort as OpenAIReasoningEffort, ResponseFormatJsonSchema};
 use schemars::Schema;
+use serde::Serialize;
 use serde_json::Value;
 
+impl From<BuiltinTool> for ToolDefinition {
+    fn from(value: BuiltinTool) -> Self {
+        match value {
+            BuiltinTool::WebSearch => ToolDefinition::WebSearchPreview(WebSearchPreview::default()),
+            BuiltinTool::FileSearch { vector_store_ids } => {
+                ToolDefinition::FileSearch(FileSearch {
+                    vector_store_ids,
+                    max_num_results: None,
+                    filters: None,
+                    ranking_options: None,
+                })
+            }
+            BuiltinTool::CodeInterpreter => ToolDefinition::CodeInterpreter(CodeInterpreter {
+                container: CodeInterpreterContainer::Container(
+                    CodeInterpreterContainerKind::Auto { file_ids: None },
+                ),
+            }),
+        }
+    }
+}
+
 impl From<Tool> for ToolDefinition {
     fn from(value: Tool) -> Self {
         let mut params = value.input_schema.to_value();
@@ -46,7 +75,7 @@ impl From<LanguageModelOptions> for CreateResponse {
             .collect();
 
         // system prompt first since openai likes it at the top
-        if let Some(system) = options.system {
+        if let Some(system) = options.system.filter(|s| !s.is_empty()) {
             items.insert(
                 0,
                 InputItem::Message(InputMessage {
@@ -71,14 +100,30 @@ impl From<LanguageModelOptions> for CreateResponse {
             effort: Some(reasoning.into()),
         });
 
+        let include = options
+            .logprobs
+            .filter(|enabled| *enabled)
+            .map(|_| vec!["message.output_text.logprobs".to_string()]);
+
         CreateResponse {
             input: Input::Items(items),
+            include,
+            top_logprobs: options.top_logprobs.map(u32::from),
             text: Some(TextConfig {
                 format: options
                     .schema
-                    .map(from_schema_to_response_format)
+                    .map(|schema| {
+                        from_schema_to_response_format(
+                            schema,
+                            options.schema_strict.unwrap_or(true),
+                        )
+                    })
                     .map(TextResponseFormat::JsonSchema)
-                    .unwrap_or(TextResponseFormat::Text),
+                    .unwrap_or(if options.json_mode == Some(true) {
+                        TextResponseFormat::JsonObject
+                    } else {
+                        TextResponseFormat::Text
+                    }),
             }),
             reasoning,
             temperature: options.temperature.map(|t| t as f32 / 100.0),
@@ -86,6 +131,9 @@ impl From<LanguageModelOptions> for CreateResponse {
             stream: Some(false),
             top_p: options.top_p.map(|t| t as f32 / 100.0),
             tools,
+            user: options.user,
+            store: options.store,
+            previous_response_id: options.previous_response_id,
             ..Default::default()
         }
     }
@@ -104,14 +152,20 @@ impl From<Message> for Option<InputItem> {
                 let mut custom_msg = Value::Object(serde_json::Map::new());
                 custom_msg["type"] = Value::String("function_call_output".to_string());
                 custom_msg["call_id"] = Value::String(tool_info.tool.id.clone());
-                custom_msg["output"] = tool_info
-                    .output
-                    .clone()
-                    .unwrap_or_else(|e| Value::String(e.to_string()));
+                // the responses api has no error flag, failed results are
+                // sent as an error object instead.
+                custom_msg["output"] = match tool_info.error_message() {
+                    Some(error) => Value::String(serde_json::json!({ "error": error }).to_string()),
+                    None if !tool_info.parts.is_empty() => {
+                        Value::Array(tool_info.parts.iter().map(tool_result_part).collect())
+                    }
+                    None => tool_info.output.clone().unwrap_or_default(),
+                };
                 Some(InputItem::Custom(custom_msg))
             }
             Message::Assistant(ref assistant_msg) => match assistant_msg.content {
-                LanguageModelResponseContentType::Text(ref msg) => {
+                LanguageModelResponseContentType::Text(ref msg)
+                | LanguageModelResponseContentType::Refusal(ref msg) => {
                     text_inp.role = Role::Assistant;
                     text_inp.content = InputContent::TextInput(msg.to_owned());
                     Some(InputItem::Message(text_inp))
@@ -124,16 +178,17 @@ impl From<Message> for Option<InputItem> {
                     custom_msg["type"] = Value::String("function_call".to_string());
                     Some(InputItem::Custom(custom_msg))
                 }
-                LanguageModelResponseContentType::Reasoning(ref reason) => {
-                    let mut custom_msg = Value::Object(serde_json::Map::new());
-                    let mut summary = Value::Object(serde_json::Map::new());
-                    summary["type"] = Value::String("summary_text".to_string());
-                    summary["text"] = Value::String(reason.clone());
-
-                    custom_msg["type"] = Value::String("reasoning".to_string());
-                    custom_msg["summary"] = summary;
-
-                    Some(InputItem::Custom(custom_msg))
+                // reasoning items are only accepted back with the id the
+                // api assigned them, which the summary alone does not keep.
+                LanguageModelResponseContentType::Reasoning(_) => None,
+                // generated audio is sent back as its transcript.
+                LanguageModelResponseContentType::Audio {
+                    transcript: Some(ref transcript),
+                    ..
+                } => {
+                    text_inp.role = Role::Assistant;
+                    text_inp.content = InputContent::TextInput(transcript.to_owned());
+                    Some(InputItem::Message(text_inp))
                 }
                 _ => None,
             },
@@ -180,7 +235,111 @@ impl From<ReasoningEffort> for OpenAIReasoningEffort {
     }
 }
 
-fn from_schema_to_response_format(schema: Schema) -> ResponseFormatJsonSchema {
+/// Request body for the images generation endpoint. `async-openai`'s typed
+/// request only knows the dall-e sizes, so the body is built here instead.
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct ImageGenerationRequest {
+    pub model: String,
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+}
+
+impl ImageGenerationRequest {
+    pub fn new(model: impl Into<String>, prompt: impl Into<String>, options: ImageOptions) -> Self {
+        Self {
+            model: model.into(),
+            prompt: prompt.into(),
+            n: options.n,
+            size: options.size,
+            quality: options.quality,
+        }
+    }
+}
+
+impl From<ImagesResponse> for ImageResponse {
+    fn from(value: ImagesResponse) -> Self {
+        let images = value
+            .data
+            .iter()
+            .map(|image| match image.as_ref() {
+                Image::Url { url, .. } => GeneratedImage::Url(url.clone()),
+                Image::B64Json { b64_json, .. } => GeneratedImage::Base64(b64_json.to_string()),
+            })
+            .collect();
+        ImageResponse { images }
+    }
+}
+
+/// Extracts the token log probabilities from a raw responses api payload.
+/// `async-openai` does not model logprobs on `OutputText`, so they are read
+/// from the untyped response body instead.
+pub(crate) fn parse_logprobs(response: &Value) -> Vec<TokenLogprob> {
+    response
+        .get("output")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.get("content").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|content| content.get("logprobs").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|logprob| serde_json::from_value(logprob.clone()).ok())
+        .collect()
+}
+
+/// Converts the output item of a built-in tool, e.g. a `web_search_call`,
+/// into a `BuiltinToolCall`. Returns `None` for any other item.
+pub(crate) fn parse_builtin_tool_call(item: &Value) -> Option<LanguageModelResponseContentType> {
+    let name = item.get("type")?.as_str()?.strip_suffix("_call")?;
+    if !["web_search", "file_search", "code_interpreter"].contains(&name) {
+        return None;
+    }
+
+    let mut info = BuiltinToolCallInfo::new(name);
+    info.id(item.get("id").and_then(Value::as_str).unwrap_or_default());
+    info.output(item.clone());
+    Some(LanguageModelResponseContentType::BuiltinToolCall(info))
+}
+
+/// Converts a tool result part to a function call output content item.
+fn tool_result_part(part: &ToolResultPart) -> Value {
+    match part {
+        ToolResultPart::Text(text) => serde_json::json!({ "type": "input_text", "text": text }),
+        ToolResultPart::Image { media_type, data } => serde_json::json!({
+            "type": "input_image",
+            "image_url": format!("data:{media_type};base64,{data}"),
+        }),
+    }
+}
+
+/// Returns the options the responses api does not support.
+pub(crate) fn warnings(options: &LanguageModelOptions, provider: &str) -> Vec<Warning> {
+    let unsupported = [
+        ("seed", options.seed.is_some()),
+        ("top_k", options.top_k.is_some()),
+        ("stop_sequences", options.stop_sequences.is_some()),
+        ("presence_penalty", options.presence_penalty.is_some()),
+        ("frequency_penalty", options.frequency_penalty.is_some()),
+    ];
+
+    unsupported
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(option, _)| Warning::unsupported(option, provider))
+        .collect()
+}
+
+/// Returns the ids of the models in a `/models` response.
+pub(crate) fn model_ids(response: ListModelResponse) -> Vec<String> {
+    response.data.into_iter().map(|model| model.id).collect()
+}
+
+fn from_schema_to_response_format(schema: Schema, strict: bool) -> ResponseFormatJsonSchema {
     let json = serde_json::to_value(schema).expect("Failed to serialize schema");
     ResponseFormatJsonSchema {
         name: json
@@ -193,7 +352,7 @@ fn from_schema_to_response_format(schema: Schema) -> ResponseFormatJsonSchema {
             .and_then(|v| v.as_str())
             .map(str::to_owned),
         schema: Some(json),
-        strict: Some(false),
+        strict: Some(strict),
     }
 }
 
@@ -202,6 +361,7 @@ mod tests {
     use super::*;
     use crate::core::language_model::{LanguageModelOptions, ReasoningEffort, Usage};
     use crate::core::messages::{AssistantMessage, Message};
+    use crate::core::tools::ToolResultInfo;
 
     #[test]
     fn test_reasoning_effort_conversion_low() {
@@ -265,6 +425,125 @@ mod tests {
         assert_eq!(reasoning.summary, Some(ReasoningSummary::Auto));
     }
 
+    #[test]
+    fn test_language_model_options_to_create_response_without_system() {
+        let options = LanguageModelOptions {
+            system: Some(String::new()),
+            messages: vec![Message::User("hi".into()).into()],
+            ..Default::default()
+        };
+        let create_response: CreateResponse = options.into();
+        match create_response.input {
+            Input::Items(items) => assert_eq!(items.len(), 1),
+            other => panic!("expected input items, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_failed_tool_result_to_input_item() {
+        let mut result = ToolResultInfo::new("get_weather");
+        result.id("call_1");
+        result.error("city not found");
+
+        let item: Option<InputItem> = Message::Tool(result).into();
+        match item {
+            Some(InputItem::Custom(value)) => {
+                assert_eq!(value["type"], "function_call_output");
+                assert_eq!(value["output"], "{\"error\":\"city not found\"}");
+            }
+            other => panic!("expected custom input item, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_image_tool_result_to_input_item() {
+        let mut result = ToolResultInfo::new("screenshot");
+        result.id("call_1");
+        result.output_parts(vec![
+            ToolResultPart::Text("The page".to_string()),
+            ToolResultPart::image("image/png", "iVBORw0KGgo="),
+        ]);
+
+        let item: Option<InputItem> = Message::Tool(result).into();
+        match item {
+            Some(InputItem::Custom(value)) => assert_eq!(
+                value["output"],
+                serde_json::json!([
+                    { "type": "input_text", "text": "The page" },
+                    { "type": "input_image", "image_url": "data:image/png;base64,iVBORw0KGgo=" }
+                ])
+            ),
+            other => panic!("expected custom input item, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_schema_strict_in_create_response() {
+        let strict = |schema_strict| {
+            let options = LanguageModelOptions {
+                schema: Some(schemars::schema_for!(String)),
+                schema_strict,
+                ..Default::default()
+            };
+            let create_response: CreateResponse = options.into();
+            let body = serde_json::to_value(create_response).unwrap();
+            body["text"]["format"]["strict"].clone()
+        };
+
+        assert_eq!(strict(None), serde_json::json!(true));
+        assert_eq!(strict(Some(true)), serde_json::json!(true));
+        assert_eq!(strict(Some(false)), serde_json::json!(false));
+    }
+
+    #[test]
+    fn test_json_mode_in_create_response() {
+        let format = |json_mode, schema| {
+            let options = LanguageModelOptions {
+                json_mode,
+                schema,
+                ..Default::default()
+            };
+            let create_response: CreateResponse = options.into();
+            let body = serde_json::to_value(create_response).unwrap();
+            body["text"]["format"]["type"].clone()
+        };
+
+        assert_eq!(format(None, None), "text");
+        assert_eq!(format(Some(true), None), "json_object");
+        assert_eq!(
+            format(Some(true), Some(schemars::schema_for!(String))),
+            "json_schema"
+        );
+    }
+
+    #[test]
+    fn test_warnings_for_unsupported_options() {
+        let options = LanguageModelOptions {
+            top_k: Some(40),
+            seed: Some(42),
+            temperature: Some(70),
+            ..Default::default()
+        };
+
+        let warnings = warnings(&options, "OpenAI");
+        let options: Vec<&str> = warnings.iter().map(|w| w.option.as_str()).collect();
+        assert_eq!(options, vec!["seed", "top_k"]);
+        assert_eq!(
+            warnings[1].reason,
+            "OpenAI does not support `top_k`, it will be ignored"
+        );
+    }
+
+    #[test]
+    fn test_language_model_options_to_create_response_with_user() {
+        let options = LanguageModelOptions {
+            user: Some("user_42".to_string()),
+            ..Default::default()
+        };
+        let create_response: CreateResponse = options.into();
+        assert_eq!(create_response.user.as_deref(), Some("user_42"));
+    }
+
     #[test]
     fn test_language_model_options_to_create_response_without_reasoning_effort() {
         let options = LanguageModelOptions {
@@ -306,17 +585,137 @@ mod tests {
         };
         let message = Message::Assistant(assistant_msg);
 
+        // the summary alone is rejected as input, so it is not sent back
         let input_item: Option<InputItem> = message.into();
-        assert!(input_item.is_some());
-
-        if let Some(InputItem::Custom(custom_msg)) = input_item {
-            assert_eq!(custom_msg["type"], "reasoning");
-            assert!(custom_msg["summary"].is_object());
-            let summary = custom_msg["summary"].as_object().unwrap();
-            assert_eq!(summary["type"], "summary_text");
-            assert_eq!(summary["text"], "This is my reasoning");
-        } else {
-            panic!("Expected Custom InputItem");
-        }
+        assert!(input_item.is_none());
+    }
+
+    #[test]
+    fn test_language_model_options_to_create_response_with_logprobs() {
+        let options = LanguageModelOptions {
+            logprobs: Some(true),
+            top_logprobs: Some(2),
+            ..Default::default()
+        };
+        let create_response: CreateResponse = options.into();
+        assert_eq!(
+            create_response.include,
+            Some(vec!["message.output_text.logprobs".to_string()])
+        );
+        assert_eq!(create_response.top_logprobs, Some(2));
+    }
+
+    #[test]
+    fn test_language_model_options_to_create_response_without_logprobs() {
+        let options = LanguageModelOptions {
+            logprobs: Some(false),
+            ..Default::default()
+        };
+        let create_response: CreateResponse = options.into();
+        assert!(create_response.include.is_none());
+        assert!(create_response.top_logprobs.is_none());
+    }
+
+    #[test]
+    fn test_parse_logprobs_from_response_payload() {
+        let payload = serde_json::json!({
+            "output": [{
+                "type": "message",
+                "content": [{
+                    "type": "output_text",
+                    "text": "Hi there",
+                    "annotations": [],
+                    "logprobs": [
+                        {
+                            "token": "Hi",
+                            "logprob": -0.01,
+                            "bytes": [72, 105],
+                            "top_logprobs": [
+                                { "token": "Hi", "logprob": -0.01, "bytes": [72, 105] },
+                                { "token": "Hello", "logprob": -4.5, "bytes": [72] }
+                            ]
+                        },
+                        { "token": " there", "logprob": -0.2, "bytes": [], "top_logprobs": [] }
+                    ]
+                }]
+            }]
+        });
+
+        let logprobs = parse_logprobs(&payload);
+        assert_eq!(logprobs.len(), 2);
+        assert_eq!(logprobs[0].token, "Hi");
+        assert_eq!(logprobs[0].logprob, -0.01);
+        assert_eq!(logprobs[0].top_logprobs.len(), 2);
+        assert_eq!(logprobs[0].top_logprobs[1].token, "Hello");
+        assert_eq!(logprobs[0].top_logprobs[1].logprob, -4.5);
+        assert_eq!(logprobs[1].token, " there");
+        assert!(logprobs[1].top_logprobs.is_empty());
+    }
+
+    #[test]
+    fn test_parse_logprobs_without_logprobs() {
+        let payload = serde_json::json!({
+            "output": [{
+                "type": "message",
+                "content": [{ "type": "output_text", "text": "Hi", "annotations": [] }]
+            }]
+        });
+        assert!(parse_logprobs(&payload).is_empty());
+    }
+
+    #[test]
+    fn test_image_generation_request_serialization() {
+        let options = ImageOptions {
+            size: Some("1536x1024".to_string()),
+            n: Some(2),
+            ..Default::default()
+        };
+        let request = ImageGenerationRequest::new("gpt-image-1", "a red fox", options);
+        assert_eq!(
+            serde_json::to_value(request).unwrap(),
+            serde_json::json!({
+                "model": "gpt-image-1",
+                "prompt": "a red fox",
+                "n": 2,
+                "size": "1536x1024"
+            })
+        );
+    }
+
+    #[test]
+    fn test_images_response_to_image_response() {
+        let payload = serde_json::json!({
+            "created": 1713833628,
+            "data": [
+                { "b64_json": "aGVsbG8=" },
+                { "url": "https://example.com/fox.png", "revised_prompt": "a red fox" }
+            ]
+        });
+        let response: ImagesResponse = serde_json::from_value(payload).unwrap();
+        let response: ImageResponse = response.into();
+
+        assert_eq!(response.images.len(), 2);
+        assert_eq!(response.images[0].to_bytes().unwrap(), b"hello".to_vec());
+        assert_eq!(
+            response.images[1],
+            GeneratedImage::Url("https://example.com/fox.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_model_ids_from_list_response() {
+        let payload = serde_json::json!({
+            "object": "list",
+            "data": [
+                { "id": "gpt-4o", "object": "model", "created": 1715367049, "owned_by": "system" },
+                { "id": "gpt-4o-mini", "object": "model", "created": 1721172741, "owned_by": "system" }
+            ]
+        });
+
+        let response: ListModelResponse = serde_json::from_value(payload).unwrap();
+        assert_eq!(
+            model_ids(response),
+            vec!["gpt-4o".to_string(), "gpt-4o-mini".to_string()]
+        );
     }
 }
@@ -3,33 +3,41 @@
 
 pub mod conversions;
 pub mod settings;
-use std::sync::Arc;
 
-use async_openai::error::OpenAIError;
+use async_openai::Client;
+use async_openai::types::ImagesResponse;
 use async_openai::types::responses::{
-    Content, CreateResponse, OutputContent, OutputItem, Response, ResponseEvent, ResponseStream,
+    Content, CreateResponse, OutputContent, OutputItem, Response, ResponseEvent, ToolDefinition,
 };
-use async_openai::{Client, config::OpenAIConfig};
 use futures::{StreamExt, stream::once};
 
+use crate::core::image::{ImageModel, ImageOptions, ImageResponse};
 use crate::core::language_model::{
-    LanguageModelOptions, LanguageModelResponse, LanguageModelResponseContentType,
-    LanguageModelStreamChunk, LanguageModelStreamChunkType, ProviderStream,
+    Capabilities, LanguageModelOptions, LanguageModelResponse, LanguageModelResponseContentType,
+    LanguageModelStreamChunk, LanguageModelStreamChunkType, ProviderStream, Warning,
 };
 use crate::core::messages::AssistantMessage;
-use crate::error::ProviderError;
+use crate::providers::chat_completions::{self, ChatCompletionsApi};
+use crate::providers::client::{
+    ProviderConfig, post_json, post_stream, prepare_request_body, request_client,
+};
+use crate::providers::openai::conversions::ImageGenerationRequest;
 use crate::providers::openai::settings::{OpenAIProviderSettings, OpenAIProviderSettingsBuilder};
 use crate::{
     core::{language_model::LanguageModel, provider::Provider, tools::ToolCallInfo},
     error::{Error, Result},
 };
 use async_trait::async_trait;
+use std::collections::HashSet;
 
 /// The OpenAI provider.
 #[derive(Debug, Clone)]
 pub struct OpenAI {
-    client: Client<OpenAIConfig>,
+    client: Client<ProviderConfig>,
+    http_client: reqwest::Client,
     settings: OpenAIProviderSettings,
+    /// The name warnings refer to the provider by.
+    name: &'static str,
 }
 
 impl OpenAI {
@@ -45,41 +53,130 @@ impl OpenAI {
     pub fn builder() -> OpenAIProviderSettingsBuilder {
         OpenAIProviderSettings::builder()
     }
+
+    /// Sets the name warnings refer to the provider by, for providers
+    /// sending their requests through this one.
+    pub(crate) fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
 }
 
-impl Provider for OpenAI {}
+#[async_trait]
+impl Provider for OpenAI {
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let response = self.client.models().list().await?;
 
-impl ProviderError for OpenAIError {}
+        Ok(conversions::model_ids(response))
+    }
+}
 
-#[async_trait]
-impl LanguageModel for OpenAI {
-    fn name(&self) -> String {
-        self.settings.model_name.clone()
+/// The chat completions api of OpenAI. Requests with audio input or output
+/// are sent to it, as the responses api does not support audio.
+#[derive(Debug, Clone)]
+pub(crate) struct OpenAIChatApi;
+
+impl ChatCompletionsApi for OpenAIChatApi {
+    const NAME: &'static str = "OpenAI with audio";
+    const PROVIDER_NAME: &'static str = "openai";
+    const BASE_URL: &'static str = "https://api.openai.com/v1";
+    const API_KEY_ENV: &'static str = "OPENAI_API_KEY";
+    const MODEL_NAME: &'static str = "gpt-4o-audio-preview";
+    const TOP_K: bool = false;
+    const STOP_AND_SEED: bool = true;
+    const MULTIPLE_COMPLETIONS: bool = true;
+    const AUDIO: bool = true;
+}
+
+/// Whether the options have audio input or request audio output, which is
+/// only supported by the chat completions api.
+fn uses_audio(options: &LanguageModelOptions) -> bool {
+    options.has_audio_input() || options.audio_output.is_some()
+}
+
+impl OpenAI {
+    /// Converts the options into a request for the configured model, adding
+    /// the enabled built-in tools.
+    fn create_request(&self, options: LanguageModelOptions) -> CreateResponse {
+        let mut request: CreateResponse = options.into();
+        request.model = self.settings.model_name.to_string();
+
+        if !self.settings.builtin_tools.is_empty() {
+            request.tools.get_or_insert_with(Vec::new).extend(
+                self.settings
+                    .builtin_tools
+                    .iter()
+                    .cloned()
+                    .map(ToolDefinition::from),
+            );
+        }
+
+        request
     }
 
-    async fn generate_text(
-        &mut self,
+    /// Converts the options into a chat completions request for the
+    /// configured model.
+    fn create_chat_request(
+        &self,
         options: LanguageModelOptions,
+    ) -> chat_completions::conversions::ChatCompletionsRequest {
+        let mut request = chat_completions::conversions::request::<OpenAIChatApi>(options);
+        request.model = self.settings.model_name.to_string();
+        request
+    }
+
+    /// Returns the warnings for the options the model does not support.
+    fn warnings(&self, options: &LanguageModelOptions) -> Vec<Warning> {
+        if !uses_audio(options) {
+            return conversions::warnings(options, self.name);
+        }
+
+        let mut warnings = chat_completions::conversions::warnings::<OpenAIChatApi>(options);
+        if !self.settings.builtin_tools.is_empty() {
+            warnings.push(Warning::new(
+                "builtin_tools",
+                "Built-in tools are not supported with audio, they will be ignored",
+            ));
+        }
+        if !self.capabilities().audio {
+            warnings.push(Warning::new(
+                "audio",
+                format!(
+                    "`{}` is not known to support audio, the request may be rejected",
+                    self.settings.model_name
+                ),
+            ));
+        }
+        warnings
+    }
+
+    /// Sends a single responses api request and converts the result.
+    async fn create_response(
+        &self,
+        client: &Client<ProviderConfig>,
+        request: CreateResponse,
     ) -> Result<LanguageModelResponse> {
-        let mut request: CreateResponse = options.clone().into();
+        let body = prepare_request_body(request, self.settings.before_request.as_ref())?;
 
-        request.model = self.settings.model_name.to_string();
+        let raw: serde_json::Value =
+            post_json(client, &self.http_client, "/responses", &body).await?;
 
-        let response: Response = self
-            .client
-            .responses()
-            .create(request)
-            .await
-            .map_err(|e| Error::ProviderError(Arc::new(e)))?;
+        let logprobs = conversions::parse_logprobs(&raw);
+        let raw_output = raw["output"].as_array().cloned().unwrap_or_default();
+        let response: Response =
+            serde_json::from_value(raw).map_err(|e| Error::ApiError(e.to_string()))?;
         let mut collected: Vec<LanguageModelResponseContentType> = Vec::new();
 
-        for out in response.output {
+        for (i, out) in response.output.into_iter().enumerate() {
             match out {
                 OutputContent::Message(msg) => {
                     for c in msg.content {
-                        if let Content::OutputText(t) = c {
-                            collected.push(LanguageModelResponseContentType::new(t.text));
-                        }
+                        collected.push(match c {
+                            Content::OutputText(t) => LanguageModelResponseContentType::new(t.text),
+                            Content::Refusal(r) => {
+                                LanguageModelResponseContentType::Refusal(r.refusal)
+                            }
+                        });
                     }
                 }
                 OutputContent::FunctionCall(f) => {
@@ -88,29 +185,174 @@ impl LanguageModel for OpenAI {
                     tool_info.input(serde_json::from_str(&f.arguments).unwrap());
                     collected.push(LanguageModelResponseContentType::ToolCall(tool_info));
                 }
-                other => collected.push(LanguageModelResponseContentType::NotSupported(format!(
-                    "{other:?}"
-                ))),
+                OutputContent::Reasoning(r) if !r.summary.is_empty() => {
+                    let summary: Vec<String> = r.summary.into_iter().map(|s| s.text).collect();
+                    collected.push(LanguageModelResponseContentType::Reasoning(
+                        summary.join("\n\n"),
+                    ));
+                }
+                other => collected.push(
+                    raw_output
+                        .get(i)
+                        .and_then(conversions::parse_builtin_tool_call)
+                        .unwrap_or_else(|| {
+                            LanguageModelResponseContentType::NotSupported(format!("{other:?}"))
+                        }),
+                ),
             }
         }
 
         Ok(LanguageModelResponse {
             contents: collected,
             usage: response.usage.map(|usage| usage.into()),
+            id: Some(response.id),
+            logprobs,
+            citations: Vec::new(),
+            warnings: Vec::new(),
+            incomplete: response.incomplete_details.map(|details| details.reason),
+            served_by: None,
         })
     }
+}
+
+/// Capabilities of the OpenAI models, based on the model name.
+pub(crate) fn model_capabilities(model: &str) -> Capabilities {
+    let reasoning = ["o1", "o3", "o4", "gpt-5"]
+        .iter()
+        .any(|prefix| model.starts_with(prefix));
+    let vision = [
+        "gpt-4o",
+        "gpt-4.1",
+        "gpt-4-turbo",
+        "gpt-5",
+        "o1",
+        "o3",
+        "o4",
+    ]
+    .iter()
+    .any(|prefix| model.starts_with(prefix))
+        && !model.starts_with("o1-mini")
+        && !model.starts_with("o3-mini");
+    let audio = model.contains("audio");
+
+    Capabilities {
+        tools: true,
+        vision,
+        reasoning,
+        streaming: true,
+        audio,
+    }
+}
 
-    async fn stream_text(&mut self, options: LanguageModelOptions) -> Result<ProviderStream> {
-        let mut request: CreateResponse = options.into();
-        request.model = self.settings.model_name.to_string();
+#[async_trait]
+impl LanguageModel for OpenAI {
+    fn model_name(&self) -> String {
+        self.settings.model_name.clone()
+    }
+
+    fn provider_name(&self) -> &str {
+        &self.settings.provider_name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        model_capabilities(&self.settings.model_name)
+    }
+
+    fn settings_key(&self) -> String {
+        format!(
+            "{} {:?} {:?} {:?}",
+            self.settings.base_url,
+            self.settings.builtin_tools,
+            self.settings.organization,
+            self.settings.project
+        )
+    }
+
+    fn request_body(&self, options: LanguageModelOptions) -> Result<serde_json::Value> {
+        let before_request = self.settings.before_request.as_ref();
+        if uses_audio(&options) {
+            return prepare_request_body(self.create_chat_request(options), before_request);
+        }
+
+        prepare_request_body(self.create_request(options), before_request)
+    }
+
+    async fn generate_text(&self, options: LanguageModelOptions) -> Result<LanguageModelResponse> {
+        let n = options.n;
+        let warnings = self.warnings(&options);
+        warnings.iter().for_each(Warning::log);
+        let client = request_client(&self.client, &self.http_client, &options.headers)?;
+
+        if uses_audio(&options) {
+            let audio = options.audio_output.clone();
+            let body = self.request_body(options)?;
+            let mut response =
+                chat_completions::generate(&client, &self.http_client, &body, audio.as_ref())
+                    .await?;
+            response.warnings = warnings;
+            return Ok(response);
+        }
+
+        let request = self.create_request(options);
+        let n = n.unwrap_or(1).max(1);
+        if n == 1 {
+            let mut response = self.create_response(&client, request).await?;
+            response.warnings = warnings;
+            return Ok(response);
+        }
+
+        // the responses api returns a single candidate per request, so one
+        // request is sent for each requested completion.
+        let responses = futures::future::try_join_all(
+            (0..n).map(|_| self.create_response(&client, request.clone())),
+        )
+        .await?;
+
+        responses
+            .into_iter()
+            .reduce(|mut combined, response| {
+                // the logprobs are kept of the first candidate only
+                combined.contents.extend(response.contents);
+                combined.usage = match (combined.usage, response.usage) {
+                    (Some(a), Some(b)) => Some(&a + &b),
+                    (a, b) => a.or(b),
+                };
+                combined
+            })
+            .map(|mut combined| {
+                combined.warnings = warnings;
+                combined
+            })
+            .ok_or_else(|| Error::ApiError("No response returned".to_string()))
+    }
+
+    async fn stream_text(&self, options: LanguageModelOptions) -> Result<ProviderStream> {
+        let mut warnings = self.warnings(&options);
+        if !uses_audio(&options) && options.n.is_some_and(|n| n > 1) {
+            warnings.push(Warning::new(
+                "n",
+                "Multiple completions are not supported when streaming, only one is returned",
+            ));
+        }
+        warnings.iter().for_each(Warning::log);
+        let client = request_client(&self.client, &self.http_client, &options.headers)?;
+
+        if uses_audio(&options) {
+            let mut request = self.create_chat_request(options);
+            request.stream = true;
+            let audio = request.audio.clone();
+            let body = prepare_request_body(request, self.settings.before_request.as_ref())?;
+
+            return chat_completions::stream(&client, &self.http_client, &body, audio).await;
+        }
+
+        let mut request = self.create_request(options);
         request.stream = Some(true);
 
-        let openai_stream: ResponseStream = self
-            .client
-            .responses()
-            .create_stream(request)
-            .await
-            .map_err(|e| Error::ProviderError(Arc::new(e)))?;
+        let body = prepare_request_body(request, self.settings.before_request.as_ref())?;
+
+        let openai_stream =
+            post_stream::<ResponseEvent>(&client, &self.http_client, "/responses", &body).await?;
 
         let (first, rest) = openai_stream.into_future().await;
 
@@ -123,6 +365,8 @@ impl LanguageModel for OpenAI {
         #[derive(Default)]
         struct StreamState {
             completed: bool,
+            /// Ids of the built-in tool calls streamed as their items finished.
+            builtin_calls: HashSet<String>,
         }
 
         let stream = openai_stream.scan::<_, Result<Vec<LanguageModelStreamChunk>>, _, _>(
@@ -151,9 +395,24 @@ impl LanguageModel for OpenAI {
                                     ));
                                 }
                                 other => {
-                                    collected.push(LanguageModelResponseContentType::NotSupported(
-                                        format!("{other:?}"),
-                                    ))
+                                    let item = serde_json::to_value(&other).ok();
+                                    if item
+                                        .as_ref()
+                                        .and_then(|item| item.get("id"))
+                                        .and_then(serde_json::Value::as_str)
+                                        .is_some_and(|id| state.builtin_calls.contains(id))
+                                    {
+                                        continue;
+                                    }
+                                    collected.push(
+                                        item.as_ref()
+                                            .and_then(conversions::parse_builtin_tool_call)
+                                            .unwrap_or_else(|| {
+                                                LanguageModelResponseContentType::NotSupported(
+                                                    format!("{other:?}"),
+                                                )
+                                            }),
+                                    )
                                 }
                             }
                         }
@@ -168,6 +427,28 @@ impl LanguageModel for OpenAI {
                             })
                             .collect()))
                     }
+                    // built-in tool calls finish before the text ending the
+                    // stream, so they are not left to `ResponseCompleted`
+                    Ok(ResponseEvent::ResponseOutputItemDone(d)) => {
+                        let call = serde_json::to_value(&d.item)
+                            .ok()
+                            .as_ref()
+                            .and_then(conversions::parse_builtin_tool_call);
+                        Some(Ok(Vec::from([match call {
+                            Some(LanguageModelResponseContentType::BuiltinToolCall(info)) => {
+                                state.builtin_calls.insert(info.tool.id.clone());
+                                LanguageModelStreamChunk::Done(AssistantMessage {
+                                    content: LanguageModelResponseContentType::BuiltinToolCall(
+                                        info,
+                                    ),
+                                    usage: None,
+                                })
+                            }
+                            _ => LanguageModelStreamChunk::Delta(
+                                LanguageModelStreamChunkType::NotSupported(format!("{d:?}")),
+                            ),
+                        }])))
+                    }
                     Ok(ResponseEvent::ResponseOutputTextDelta(d)) => {
                         Some(Ok(Vec::from([LanguageModelStreamChunk::Delta(
                             LanguageModelStreamChunkType::Text(d.delta),
@@ -182,6 +463,20 @@ impl LanguageModel for OpenAI {
                             },
                         )])))
                     }
+                    Ok(ResponseEvent::ResponseRefusalDone(d)) => {
+                        state.completed = true;
+                        Some(Ok(Vec::from([LanguageModelStreamChunk::Done(
+                            AssistantMessage {
+                                content: LanguageModelResponseContentType::Refusal(d.refusal),
+                                usage: None,
+                            },
+                        )])))
+                    }
+                    Ok(ResponseEvent::ResponseReasoningSummaryTextDelta(d)) => {
+                        Some(Ok(Vec::from([LanguageModelStreamChunk::Delta(
+                            LanguageModelStreamChunkType::Reasoning(d.delta),
+                        )])))
+                    }
                     Ok(ResponseEvent::ResponseFunctionCallArgumentsDelta(d)) => {
                         Some(Ok(Vec::from([LanguageModelStreamChunk::Delta(
                             LanguageModelStreamChunkType::ToolCall(d.delta),
@@ -197,6 +492,7 @@ impl LanguageModel for OpenAI {
                         )])))
                     }
                     Ok(ResponseEvent::ResponseIncomplete(d)) => {
+                        state.completed = true;
                         Some(Ok(Vec::from([LanguageModelStreamChunk::Delta(
                             LanguageModelStreamChunkType::Incomplete({
                                 if let Some(reason) = d.response.incomplete_details {
@@ -220,7 +516,7 @@ impl LanguageModel for OpenAI {
                     )]))),
                     Err(e) => {
                         state.completed = true;
-                        Some(Err(Error::ProviderError(Arc::new(e))))
+                        Some(Err(e))
                     }
                 })
             },
@@ -229,3 +525,799 @@ impl LanguageModel for OpenAI {
         Ok(Box::pin(stream))
     }
 }
+
+#[async_trait]
+impl ImageModel for OpenAI {
+    async fn generate_image(&self, prompt: &str, options: ImageOptions) -> Result<ImageResponse> {
+        let request =
+            ImageGenerationRequest::new(self.settings.model_name.to_string(), prompt, options);
+
+        let response: ImagesResponse = self.client.images().create_byot(request).await?;
+
+        Ok(response.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::messages::{Message, TaggedMessage, UserMessage};
+    use crate::test_utils::{response_payload, serve_json, serve_json_recording};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_boxed_models_of_different_providers() {
+        use crate::core::LanguageModelRequest;
+        use crate::test_utils::MockLanguageModel;
+
+        let openai = OpenAI::builder()
+            .base_url(serve_json(response_payload()).await)
+            .api_key("test")
+            .build()
+            .unwrap();
+        let mock = MockLanguageModel::new().respond_text("Hi from mock");
+
+        let models: Vec<Box<dyn LanguageModel>> = vec![Box::new(openai), Box::new(mock)];
+
+        let mut texts = Vec::new();
+        for model in models {
+            let response = LanguageModelRequest::builder()
+                .model(model)
+                .prompt("hi")
+                .build()
+                .generate_text()
+                .await
+                .unwrap();
+            texts.push(response.text().unwrap());
+        }
+
+        assert_eq!(texts, vec!["Hello!", "Hi from mock"]);
+    }
+
+    #[tokio::test]
+    async fn test_shared_provider_across_tasks() {
+        use crate::core::LanguageModelRequest;
+
+        let (base_url, requests) = serve_json_recording(response_payload()).await;
+        let openai = Arc::new(
+            OpenAI::builder()
+                .base_url(base_url)
+                .api_key("test")
+                .build()
+                .unwrap(),
+        );
+
+        let tasks: Vec<_> = (0..4)
+            .map(|i| {
+                let openai = openai.clone();
+                tokio::spawn(async move {
+                    LanguageModelRequest::builder()
+                        .model(openai)
+                        .prompt(format!("hi {i}"))
+                        .build()
+                        .generate_text()
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap().text().unwrap(), "Hello!");
+        }
+        assert_eq!(requests.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_list_models_uses_base_url() {
+        use crate::core::provider::Provider;
+
+        let base_url = serve_json(serde_json::json!({
+            "object": "list",
+            "data": [{ "id": "gpt-4o", "object": "model", "created": 1715367049, "owned_by": "system" }]
+        }))
+        .await;
+
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        assert_eq!(openai.list_models().await.unwrap(), vec!["gpt-4o"]);
+    }
+
+    #[test]
+    fn test_provider_and_model_name() {
+        let model = OpenAI::builder()
+            .model_name("gpt-4o-mini")
+            .api_key("test")
+            .build()
+            .unwrap();
+        assert_eq!(model.provider_name(), "openai");
+        assert_eq!(model.model_name(), "gpt-4o-mini");
+    }
+
+    #[test]
+    fn test_model_capabilities() {
+        let gpt_4o = OpenAI::new("gpt-4o").capabilities();
+        assert!(gpt_4o.tools && gpt_4o.vision && gpt_4o.streaming);
+        assert!(!gpt_4o.reasoning);
+
+        let o3 = OpenAI::new("o3").capabilities();
+        assert!(o3.reasoning && o3.vision);
+
+        let o3_mini = OpenAI::new("o3-mini").capabilities();
+        assert!(o3_mini.reasoning && !o3_mini.vision);
+
+        let gpt_35 = OpenAI::new("gpt-3.5-turbo").capabilities();
+        assert!(gpt_35.tools && !gpt_35.vision && !gpt_35.reasoning);
+    }
+
+    #[tokio::test]
+    async fn test_store_and_previous_response_id_round_trip() {
+        use crate::core::LanguageModelRequest;
+
+        let (base_url, requests) = serve_json_recording(response_payload()).await;
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let response = LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("and then?")
+            .store(true)
+            .previous_response_id("resp_0")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0]["store"], serde_json::json!(true));
+        assert_eq!(requests[0]["previous_response_id"], "resp_0");
+        assert_eq!(response.response_id(), Some("resp_1"));
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_response_stops_with_reason() {
+        use crate::core::LanguageModelRequest;
+        use crate::core::language_model::StopReason;
+
+        let mut payload = response_payload();
+        payload["status"] = serde_json::json!("incomplete");
+        payload["incomplete_details"] = serde_json::json!({ "reason": "max_output_tokens" });
+        let openai = OpenAI::builder()
+            .base_url(serve_json(payload).await)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let response = LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("Write a long story")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.stop_reason(),
+            Some(StopReason::Incomplete("max_output_tokens".to_string()))
+        );
+        assert_eq!(response.text().as_deref(), Some("Hello!"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_keeps_every_content_block() {
+        let mut payload = response_payload();
+        payload["output"] = serde_json::json!([
+            {
+                "type": "reasoning",
+                "id": "rs_1",
+                "summary": [{ "type": "summary_text", "text": "The user wants the weather." }]
+            },
+            {
+                "type": "message",
+                "id": "msg_1",
+                "role": "assistant",
+                "status": "completed",
+                "content": [
+                    { "type": "output_text", "text": "Let me check.", "annotations": [] },
+                    { "type": "output_text", "text": "One moment.", "annotations": [] }
+                ]
+            },
+            {
+                "type": "function_call",
+                "id": "fc_1",
+                "call_id": "call_1",
+                "name": "get_weather",
+                "arguments": "{\"city\":\"Paris\"}",
+                "status": "completed"
+            }
+        ]);
+        let openai = OpenAI::builder()
+            .base_url(serve_json(payload).await)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            ..Default::default()
+        };
+        let response = openai.generate_text(options).await.unwrap();
+
+        let blocks: Vec<String> = response.contents.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            blocks,
+            vec![
+                "[reasoning] The user wants the weather.",
+                "Let me check.",
+                "One moment.",
+                r#"get_weather({"city":"Paris"})"#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_parses_refusal() {
+        use crate::core::LanguageModelRequest;
+
+        let mut payload = response_payload();
+        payload["output"][0]["content"] = serde_json::json!([
+            { "type": "refusal", "refusal": "I can't help with that." }
+        ]);
+        let openai = OpenAI::builder()
+            .base_url(serve_json(payload).await)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let response = LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("hi")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.refusal(),
+            Some("I can't help with that.".to_string())
+        );
+        assert_eq!(response.text(), None);
+        assert_eq!(
+            response.stop_reason(),
+            Some(crate::core::language_model::StopReason::Finish)
+        );
+    }
+
+    /// A chat completions response with generated audio.
+    fn audio_payload() -> serde_json::Value {
+        serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1741476542,
+            "model": "gpt-4o-audio-preview",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "refusal": null,
+                    "audio": {
+                        "id": "audio_1",
+                        "expires_at": 1741480142,
+                        "data": "SUQzBA==",
+                        "transcript": "Hello!"
+                    }
+                },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 9, "total_tokens": 21 }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_audio_is_sent_to_chat_completions() {
+        use crate::core::language_model::AudioOutput;
+
+        let (base_url, requests) = serve_json_recording(audio_payload()).await;
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .model_name("gpt-4o-audio-preview")
+            .build()
+            .unwrap();
+
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User(
+                UserMessage::new("What did I say?").with_audio("audio/wav", "UklGRg=="),
+            ))],
+            audio_output: Some(AudioOutput {
+                voice: "alloy".to_string(),
+                format: "mp3".to_string(),
+            }),
+            ..Default::default()
+        };
+        let response = openai.generate_text(options).await.unwrap();
+
+        let body = &requests.lock().unwrap()[0];
+        assert_eq!(body["model"], "gpt-4o-audio-preview");
+        assert_eq!(body["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(
+            body["audio"],
+            serde_json::json!({ "voice": "alloy", "format": "mp3" })
+        );
+        assert_eq!(
+            body["messages"],
+            serde_json::json!([{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "What did I say?" },
+                    { "type": "input_audio", "input_audio": { "data": "UklGRg==", "format": "wav" } }
+                ]
+            }])
+        );
+        assert!(body.get("input").is_none());
+
+        assert!(matches!(
+            response.contents.as_slice(),
+            [LanguageModelResponseContentType::Audio { media_type, data, transcript }]
+                if media_type == "audio/mpeg" && data == "SUQzBA==" && transcript.as_deref() == Some("Hello!")
+        ));
+        assert_eq!(response.usage.as_ref().unwrap().total_tokens, Some(21));
+        assert!(response.warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_audio_warns_for_models_without_audio() {
+        let openai = OpenAI::builder()
+            .base_url(serve_json(audio_payload()).await)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User(
+                UserMessage::new("hi").with_audio("audio/wav", "UklGRg=="),
+            ))],
+            top_k: Some(40),
+            ..Default::default()
+        };
+        let response = openai.generate_text(options).await.unwrap();
+
+        let options: Vec<&str> = response
+            .warnings
+            .iter()
+            .map(|w| w.option.as_str())
+            .collect();
+        assert_eq!(options, ["top_k", "audio"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_text_streams_audio() {
+        use crate::core::LanguageModelRequest;
+        use crate::test_utils::serve_sse;
+
+        let delta = |delta: serde_json::Value| {
+            serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "choices": [{ "index": 0, "delta": delta }]
+            })
+        };
+        let base_url = serve_sse(vec![
+            delta(serde_json::json!({ "role": "assistant", "audio": { "id": "audio_1", "transcript": "Hi" } })),
+            delta(serde_json::json!({ "audio": { "data": "UklG" } })),
+            delta(serde_json::json!({ "audio": { "transcript": " there" } })),
+            delta(serde_json::json!({ "audio": { "data": "Rg==" } })),
+            serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }]
+            }),
+        ])
+        .await;
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .model_name("gpt-4o-audio-preview")
+            .build()
+            .unwrap();
+
+        let mut response = LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("hi")
+            .audio_output("alloy", "wav")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+
+        let mut audio = String::new();
+        let mut text = String::new();
+        while let Some(chunk) = response.stream.next().await {
+            match chunk {
+                LanguageModelStreamChunkType::Audio(delta) => audio.push_str(&delta),
+                LanguageModelStreamChunkType::Text(delta) => text.push_str(&delta),
+                _ => {}
+            }
+        }
+        assert_eq!(audio, "UklGRg==");
+        assert_eq!(text, "Hi there");
+        assert!(matches!(
+            response.messages().last(),
+            Some(Message::Assistant(AssistantMessage {
+                content: LanguageModelResponseContentType::Audio { media_type, data, transcript },
+                ..
+            })) if media_type == "audio/wav" && data == "UklGRg==" && transcript.as_deref() == Some("Hi there")
+        ));
+        assert_eq!(
+            response.stop_reason,
+            Some(crate::core::language_model::StopReason::Finish)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_text_keeps_builtin_tool_calls() {
+        use crate::core::LanguageModelRequest;
+        use crate::test_utils::serve_sse;
+
+        let text = |kind: &str, field: &str, value: &str| {
+            serde_json::json!({
+                "type": kind,
+                "sequence_number": 2,
+                "item_id": "msg_1",
+                "output_index": 1,
+                "content_index": 0,
+                field: value,
+                "logprobs": []
+            })
+        };
+        let base_url = serve_sse(vec![
+            serde_json::json!({
+                "type": "response.output_item.done",
+                "sequence_number": 1,
+                "output_index": 0,
+                "item": { "type": "web_search_call", "id": "ws_1", "status": "completed" }
+            }),
+            text("response.output_text.delta", "delta", "Rust 1.90"),
+            text("response.output_text.done", "text", "Rust 1.90"),
+        ])
+        .await;
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let mut response = LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("Latest Rust release?")
+            .build()
+            .stream_text()
+            .await
+            .unwrap();
+        while response.stream.next().await.is_some() {}
+
+        let contents: Vec<_> = response
+            .messages()
+            .into_iter()
+            .filter_map(|m| match m {
+                Message::Assistant(a) => Some(a.content),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(
+            &contents[..],
+            [
+                LanguageModelResponseContentType::BuiltinToolCall(call),
+                LanguageModelResponseContentType::Text(text),
+            ] if call.tool.name == "web_search" && call.tool.id == "ws_1" && text == "Rust 1.90"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_requests_n_completions() {
+        let mut payload = response_payload();
+        payload["output"][0]["content"][0]["logprobs"] =
+            serde_json::json!([{ "token": "Hello!", "logprob": -0.5, "top_logprobs": [] }]);
+        let base_url = serve_json(payload).await;
+
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            n: Some(3),
+            ..Default::default()
+        };
+
+        let response = openai.generate_text(options).await.unwrap();
+        assert_eq!(response.contents.len(), 3);
+        assert_eq!(response.usage.unwrap().total_tokens, Some(21));
+        // only the logprobs of the first candidate
+        assert_eq!(response.logprobs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_before_request_hook_modifies_body() {
+        let (base_url, requests) = serve_json_recording(response_payload()).await;
+
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .before_request(|body| {
+                body["metadata"] = serde_json::json!({ "trace_id": "abc" });
+            })
+            .build()
+            .unwrap();
+
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            ..Default::default()
+        };
+        openai.generate_text(options).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["metadata"]["trace_id"], "abc");
+        assert_eq!(requests[0]["model"], "gpt-4o");
+    }
+
+    #[tokio::test]
+    async fn test_request_headers_are_sent() {
+        use crate::core::LanguageModelRequest;
+        use crate::test_utils::serve_json_with_headers;
+
+        let (base_url, _, headers) = serve_json_with_headers(response_payload()).await;
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("hi")
+            .header("X-Tenant-Id", "acme")
+            .header("OpenAI-Project", "proj_1")
+            .header("Authorization", "Bearer other")
+            .header("Content-Type", "text/plain")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let headers = headers.lock().unwrap();
+        assert_eq!(headers[0]["x-tenant-id"], "acme");
+        assert_eq!(headers[0]["openai-project"], "proj_1");
+        assert_eq!(headers[0]["authorization"], "Bearer test");
+        assert_eq!(headers[0]["content-type"], "application/json");
+    }
+
+    #[tokio::test]
+    async fn test_organization_and_project_headers() {
+        use crate::test_utils::serve_json_with_headers;
+
+        let (base_url, _, headers) = serve_json_with_headers(response_payload()).await;
+        let options = || LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            ..Default::default()
+        };
+
+        let openai = OpenAI::builder()
+            .base_url(base_url.clone())
+            .api_key("test")
+            .organization("org-acme")
+            .project("proj_1")
+            .build()
+            .unwrap();
+        openai.generate_text(options()).await.unwrap();
+
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+        openai.generate_text(options()).await.unwrap();
+
+        let headers = headers.lock().unwrap();
+        assert_eq!(headers[0]["openai-organization"], "org-acme");
+        assert_eq!(headers[0]["openai-project"], "proj_1");
+        assert!(!headers[1].contains_key("openai-organization"));
+        assert!(!headers[1].contains_key("openai-project"));
+    }
+
+    #[test]
+    fn test_invalid_organization_fails_to_build() {
+        let result = OpenAI::builder().organization("org\nacme").build();
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_provider_with_custom_root_certificate() {
+        use crate::test_utils::{GATEWAY_CERT, serve_json_tls};
+
+        let base_url = serve_json_tls(response_payload()).await;
+        let options = || LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            ..Default::default()
+        };
+
+        let certificate = reqwest::Certificate::from_pem(GATEWAY_CERT.as_bytes()).unwrap();
+        let trusting = OpenAI::builder()
+            .base_url(base_url.clone())
+            .api_key("test")
+            .root_certificate(certificate)
+            .build()
+            .unwrap();
+        let response = trusting.generate_text(options()).await.unwrap();
+        assert_eq!(response.contents[0].to_string(), "Hello!");
+
+        let untrusting = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+        assert!(untrusting.generate_text(options()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_server_without_api_key() {
+        use crate::core::LanguageModelRequest;
+        use crate::test_utils::serve_json_with_headers;
+
+        let (base_url, _, headers) = serve_json_with_headers(response_payload()).await;
+        let local = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("")
+            .model_name("llama-3.1-8b")
+            .build()
+            .unwrap();
+
+        let response = LanguageModelRequest::builder()
+            .model(local)
+            .prompt("hi")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        assert_eq!(response.text(), Some("Hello!".to_string()));
+        assert!(!headers.lock().unwrap()[0].contains_key("authorization"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_header_fails() {
+        let openai = OpenAI::builder().api_key("test").build().unwrap();
+        let options = LanguageModelOptions {
+            messages: vec![TaggedMessage::initial_step_msg(Message::User("hi".into()))],
+            headers: [("bad header".to_string(), "x".to_string())].into(),
+            ..Default::default()
+        };
+
+        let result = openai.generate_text(options).await;
+        assert!(matches!(result, Err(Error::InvalidInput(e)) if e.contains("bad header")));
+    }
+
+    #[tokio::test]
+    async fn test_web_search_builtin_tool() {
+        use crate::core::LanguageModelRequest;
+        use crate::providers::openai::settings::BuiltinTool;
+
+        let mut payload = response_payload();
+        payload["output"].as_array_mut().unwrap().insert(
+            0,
+            serde_json::json!({
+                "type": "web_search_call",
+                "id": "ws_1",
+                "status": "completed",
+                "action": { "type": "search", "query": "rust 2024 edition" }
+            }),
+        );
+        let (base_url, requests) = serve_json_recording(payload).await;
+
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .builtin_tool(BuiltinTool::WebSearch)
+            .build()
+            .unwrap();
+
+        let response = LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("What is new in rust?")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[0]["tools"],
+            serde_json::json!([{ "type": "web_search_preview" }])
+        );
+
+        let calls = response.builtin_tool_calls().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].tool.name, "web_search");
+        assert_eq!(calls[0].tool.id, "ws_1");
+        assert_eq!(calls[0].output["action"]["query"], "rust 2024 edition");
+        assert_eq!(response.text(), Some("Hello!".to_string()));
+    }
+
+    #[test]
+    fn test_preview_returns_request_body() {
+        use crate::core::LanguageModelRequest;
+        use crate::test_utils::tool;
+
+        let tool = tool("get_weather", "sunny");
+
+        let openai = OpenAI::builder()
+            .model_name("gpt-4o")
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let preview = LanguageModelRequest::builder()
+            .model(openai)
+            .system("Be brief")
+            .messages(vec![
+                Message::User("Hi".into()),
+                Message::Assistant("Hello!".to_string().into()),
+                Message::User("Weather in Paris?".into()),
+            ])
+            .temperature(50_u32)
+            .with_tool(tool)
+            .build()
+            .preview()
+            .unwrap();
+
+        assert_eq!(preview["model"], "gpt-4o");
+        assert_eq!(preview["temperature"], 0.5);
+        assert_eq!(preview["stream"], false);
+
+        let input = preview["input"].as_array().unwrap();
+        let roles: Vec<&str> = input.iter().map(|i| i["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, vec!["system", "user", "assistant", "user"]);
+        assert_eq!(input[0]["content"], "Be brief");
+        assert_eq!(input[3]["content"], "Weather in Paris?");
+
+        let tools = preview["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0]["name"], "get_weather");
+        assert_eq!(tools[0]["type"], "function");
+    }
+
+    #[tokio::test]
+    async fn test_request_without_system_prompt_has_no_system_message() {
+        use crate::core::LanguageModelRequest;
+
+        let (base_url, requests) = serve_json_recording(response_payload()).await;
+        let openai = OpenAI::builder()
+            .base_url(base_url)
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        LanguageModelRequest::builder()
+            .model(openai)
+            .prompt("hi")
+            .build()
+            .generate_text()
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let input = requests[0]["input"].as_array().unwrap();
+        assert_eq!(input.len(), 1);
+        assert_eq!(input[0]["role"], "user");
+    }
+}
@@ -1,16 +1,28 @@
 //! Defines the settings for the OpenAI provider.
 
+use std::fmt::Debug;
+use std::time::Duration;
+
 use async_openai::{Client, config::OpenAIConfig};
 
-use crate::{error::Error, providers::openai::OpenAI};
+use crate::{
+    core::provider::BeforeRequestHook,
+    error::Error,
+    providers::{
+        TlsSettings,
+        client::{ProviderConfig, client_builder_methods, resolve_http_client},
+        openai::OpenAI,
+    },
+};
 
 /// Settings for the OpenAI provider.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct OpenAIProviderSettings {
     /// The API base URL for the OpenAI API.
     pub base_url: String,
 
-    /// The API key for the OpenAI API.
+    /// The API key for the OpenAI API. Leave empty for OpenAI compatible
+    /// servers without auth, e.g. a local vLLM or LM Studio server.
     pub api_key: String,
 
     /// The name of the provider.
@@ -18,6 +30,55 @@ pub struct OpenAIProviderSettings {
 
     /// The name of the model to use.
     pub model_name: String,
+
+    /// Timeout applied to every request, if set.
+    pub request_timeout: Option<Duration>,
+
+    /// TLS settings of the http client.
+    pub tls: TlsSettings,
+
+    /// Hook called with the json body of every request before it is sent.
+    pub before_request: Option<BeforeRequestHook>,
+
+    /// Tools executed by OpenAI itself, sent along with every request.
+    pub builtin_tools: Vec<BuiltinTool>,
+
+    /// The organization sent as the `OpenAI-Organization` header, if set.
+    pub organization: Option<String>,
+
+    /// The project sent as the `OpenAI-Project` header, if set.
+    pub project: Option<String>,
+}
+
+/// A tool executed by OpenAI itself instead of by the caller.
+///
+/// Calls to built-in tools are returned as
+/// `LanguageModelResponseContentType::BuiltinToolCall` and are not executed
+/// by the generate loop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuiltinTool {
+    /// Searches the web, `web_search_preview`.
+    WebSearch,
+    /// Searches the given vector stores.
+    FileSearch { vector_store_ids: Vec<String> },
+    /// Runs code in an automatically created container.
+    CodeInterpreter,
+}
+
+impl Debug for OpenAIProviderSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAIProviderSettings")
+            .field("base_url", &self.base_url)
+            .field("provider_name", &self.provider_name)
+            .field("model_name", &self.model_name)
+            .field("request_timeout", &self.request_timeout)
+            .field("tls", &self.tls)
+            .field("before_request", &self.before_request.is_some())
+            .field("builtin_tools", &self.builtin_tools)
+            .field("organization", &self.organization)
+            .field("project", &self.project)
+            .finish()
+    }
 }
 
 impl OpenAIProviderSettings {
@@ -32,21 +93,35 @@ pub struct OpenAIProviderSettingsBuilder {
     api_key: Option<String>,
     provider_name: Option<String>,
     model_name: Option<String>,
+    http_client: Option<reqwest::Client>,
+    request_timeout: Option<Duration>,
+    tls: TlsSettings,
+    before_request: Option<BeforeRequestHook>,
+    builtin_tools: Vec<BuiltinTool>,
+    organization: Option<String>,
+    project: Option<String>,
 }
 
 impl OpenAIProviderSettingsBuilder {
-    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
-        self.api_key = Some(api_key.into());
+    client_builder_methods!();
+
+    /// Enables a tool executed by OpenAI, e.g. `BuiltinTool::WebSearch`.
+    pub fn builtin_tool(mut self, tool: BuiltinTool) -> Self {
+        self.builtin_tools.push(tool);
         self
     }
 
-    pub fn provider_name(mut self, provider_name: impl Into<String>) -> Self {
-        self.provider_name = Some(provider_name.into());
+    /// Sets the organization requests are made for, e.g. `org-...`. Takes
+    /// precedence over an `OpenAI-Organization` request header.
+    pub fn organization(mut self, organization: impl Into<String>) -> Self {
+        self.organization = Some(organization.into());
         self
     }
 
-    pub fn model_name(mut self, model_name: impl Into<String>) -> Self {
-        self.model_name = Some(model_name.into());
+    /// Sets the project requests are made for, e.g. `proj_...`. Takes
+    /// precedence over an `OpenAI-Project` request header.
+    pub fn project(mut self, project: impl Into<String>) -> Self {
+        self.project = Some(project.into());
         self
     }
 
@@ -56,15 +131,47 @@ impl OpenAIProviderSettingsBuilder {
             api_key: self.api_key.unwrap_or_default(),
             provider_name: self.provider_name.unwrap_or_else(|| "openai".to_string()),
             model_name: self.model_name.unwrap_or_else(|| "gpt-4o".to_string()),
+            request_timeout: self.request_timeout,
+            tls: self.tls,
+            before_request: self.before_request,
+            builtin_tools: self.builtin_tools,
+            organization: self.organization,
+            project: self.project,
         };
 
-        let client = Client::with_config(
-            OpenAIConfig::new()
-                .with_api_base(settings.base_url.to_string())
-                .with_api_key(settings.api_key.to_string()),
-        );
+        let mut config = OpenAIConfig::new()
+            .with_api_base(settings.base_url.to_string())
+            .with_api_key(settings.api_key.to_string());
+        // async-openai panics on invalid header values, so they are checked here
+        for (name, value) in [
+            ("organization", &settings.organization),
+            ("project", &settings.project),
+        ] {
+            if let Some(value) = value
+                && reqwest::header::HeaderValue::try_from(value.as_str()).is_err()
+            {
+                return Err(Error::InvalidInput(format!("Invalid {name} `{value}`")));
+            }
+        }
+        if let Some(organization) = &settings.organization {
+            config = config.with_org_id(organization);
+        }
+        if let Some(project) = &settings.project {
+            config = config.with_project_id(project);
+        }
+
+        let client = Client::with_config(ProviderConfig::new(config));
+
+        let http_client =
+            resolve_http_client(self.http_client, settings.request_timeout, &settings.tls)?;
+        let client = client.with_http_client(http_client.clone());
 
-        Ok(OpenAI { settings, client })
+        Ok(OpenAI {
+            settings,
+            client,
+            http_client,
+            name: "OpenAI",
+        })
     }
 }
 
@@ -75,6 +182,13 @@ impl Default for OpenAIProviderSettingsBuilder {
             api_key: Some(std::env::var("OPENAI_API_KEY").unwrap_or_default()),
             provider_name: Some("openai".to_string()),
             model_name: Some("gpt-4o".to_string()),
+            http_client: None,
+            request_timeout: None,
+            tls: TlsSettings::default(),
+            before_request: None,
+            builtin_tools: Vec::new(),
+            organization: None,
+            project: None,
         }
     }
 }
@@ -0,0 +1,123 @@
+//! This module provides the Perplexity provider, which implements the `LanguageModel`
+//! and `Provider` traits for interacting with the Perplexity API.
+//!
+//! Perplexity's online models ground their answers on web searches and return
+//! the source urls alongside the text. These are exposed as citations on the
+//! response.
+
+pub mod settings;
+
+use crate::core::language_model::Capabilities;
+use crate::providers::chat_completions::{ChatCompletions, ChatCompletionsApi};
+
+/// The Perplexity chat completions api.
+#[derive(Debug, Clone)]
+pub struct PerplexityApi;
+
+impl ChatCompletionsApi for PerplexityApi {
+    const NAME: &'static str = "Perplexity";
+    const PROVIDER_NAME: &'static str = "perplexity";
+    const BASE_URL: &'static str = "https://api.perplexity.ai";
+    const API_KEY_ENV: &'static str = "PERPLEXITY_API_KEY";
+    const MODEL_NAME: &'static str = "sonar";
+    const JSON_SCHEMA: bool = true;
+    const ALTERNATING_ROLES: bool = true;
+
+    fn capabilities(model_name: &str) -> Capabilities {
+        Capabilities {
+            reasoning: model_name.starts_with("sonar-reasoning")
+                || model_name == "sonar-deep-research",
+            streaming: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// The Perplexity provider.
+pub type Perplexity = ChatCompletions<PerplexityApi>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::LanguageModelRequest;
+    use crate::core::language_model::LanguageModel;
+
+    #[test]
+    fn test_provider_and_model_name() {
+        let model = Perplexity::builder()
+            .model_name("sonar-pro")
+            .api_key("test")
+            .build()
+            .unwrap();
+        assert_eq!(model.provider_name(), "perplexity");
+        assert_eq!(model.model_name(), "sonar-pro");
+    }
+
+    #[test]
+    fn test_perplexity_preview_applies_before_request_hook() {
+        let perplexity = Perplexity::builder()
+            .model_name("sonar")
+            .api_key("test")
+            .before_request(|body| body["search_recency_filter"] = "week".into())
+            .build()
+            .unwrap();
+
+        let preview = LanguageModelRequest::builder()
+            .model(perplexity)
+            .system("Cite sources")
+            .prompt("Latest Rust release?")
+            .top_k(5_u32)
+            .build()
+            .preview()
+            .unwrap();
+
+        assert_eq!(
+            preview,
+            serde_json::json!({
+                "model": "sonar",
+                "messages": [
+                    { "role": "system", "content": "Cite sources" },
+                    { "role": "user", "content": "Latest Rust release?" }
+                ],
+                "top_k": 5,
+                "stream": false,
+                "search_recency_filter": "week"
+            })
+        );
+    }
+
+    #[test]
+    fn test_summarized_history_reaches_request_body() {
+        use crate::core::{HistoryPolicy, Message};
+
+        let perplexity = Perplexity::builder()
+            .model_name("sonar")
+            .api_key("test")
+            .build()
+            .unwrap();
+
+        let preview = LanguageModelRequest::builder()
+            .model(perplexity)
+            .system("Cite sources")
+            .messages(vec![
+                Message::User("a".repeat(400).into()),
+                Message::Assistant("b".repeat(400).into()),
+                Message::User("Latest Rust release?".into()),
+            ])
+            .context_limit(100_usize)
+            .history_policy(HistoryPolicy::summarize_oldest(|dropped| {
+                format!("Summary of {} messages", dropped.len())
+            }))
+            .build()
+            .preview()
+            .unwrap();
+
+        assert_eq!(
+            preview["messages"],
+            serde_json::json!([
+                { "role": "system", "content": "Cite sources\n\nSummary of 2 messages" },
+                { "role": "user", "content": "Latest Rust release?" }
+            ])
+        );
+    }
+}
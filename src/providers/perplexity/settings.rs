@@ -0,0 +1,12 @@
+//! Defines the settings for the Perplexity provider.
+
+use crate::providers::chat_completions::settings::{
+    ChatCompletionsSettings, ChatCompletionsSettingsBuilder,
+};
+use crate::providers::perplexity::PerplexityApi;
+
+/// Settings for the Perplexity provider.
+pub type PerplexityProviderSettings = ChatCompletionsSettings;
+
+/// Builder of the Perplexity provider.
+pub type PerplexityProviderSettingsBuilder = ChatCompletionsSettingsBuilder<PerplexityApi>;
@@ -86,7 +86,7 @@ async fn test_stop_reason_hook_stop() {
 
     assert!(result.is_ok());
     let response = result.unwrap();
-    assert!(matches!(response.stop_reason(), Some(StopReason::Hook)));
+    assert!(matches!(response.stop_reason(), Some(StopReason::Hook(_))));
 }
 
 #[tokio::test]
@@ -126,7 +126,7 @@ async fn test_stop_reason_stream_finish() {
         return;
     }
 
-    let response = LanguageModelRequest::builder()
+    let mut response = LanguageModelRequest::builder()
         .model(OpenAI::new("gpt-4o"))
         .prompt("Respond with 'world'")
         .build()
@@ -134,7 +134,8 @@ async fn test_stop_reason_stream_finish() {
         .await
         .unwrap();
 
-    // The stream is already consumed internally, stop_reason is set
+    // stop_reason is set once the stream is consumed
+    while response.stream.next().await.is_some() {}
     assert!(matches!(response.stop_reason(), Some(StopReason::Finish)));
 }
 
@@ -501,7 +502,7 @@ async fn test_step_id_streaming() {
         return;
     }
 
-    let response = LanguageModelRequest::builder()
+    let mut response = LanguageModelRequest::builder()
         .model(OpenAI::new("gpt-4o"))
         .prompt("Respond with 'stream test'")
         .build()
@@ -509,6 +510,7 @@ async fn test_step_id_streaming() {
         .await
         .unwrap();
 
+    while response.stream.next().await.is_some() {}
     let step_ids = response.step_ids();
     // system (0), user (0), assistant (1)
     assert_eq!(step_ids.len(), 3);
@@ -653,7 +655,7 @@ async fn test_stop_when_halts_during_tool_call() {
     let step_ids = result.step_ids();
     // Should stop after tool call, no final text step
     assert!(step_ids.len() < 5); // Less than full flow
-    assert!(matches!(result.stop_reason().unwrap(), StopReason::Hook));
+    assert!(matches!(result.stop_reason().unwrap(), StopReason::Hook(_)));
 }
 
 #[tokio::test]